@@ -0,0 +1,330 @@
+//! `assert`: CI-style checks built on the daemon's get/is actions.
+//!
+//! The CLI sends the underlying action (`gettext`, `url`, `title`, `isvisible`,
+//! `isenabled`, `ischecked` or `count`) and compares the result itself, polling
+//! until the check passes or the assertion's `--timeout` runs out.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+use serde_json::{json, Value};
+
+use crate::color;
+use crate::commands::gen_id;
+use crate::connection::send_command;
+use crate::error::{error_envelope, fail, print_error, CliError, ErrorKind};
+
+/// How often a check is repeated while waiting for it to pass
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The daemon action that observes a check, and the response field holding the value
+fn query_for(check: &str) -> (&'static str, &'static str) {
+    match check {
+        "text" => ("gettext", "text"),
+        "url" => ("url", "url"),
+        "title" => ("title", "title"),
+        "visible" | "hidden" => ("isvisible", "visible"),
+        "enabled" => ("isenabled", "enabled"),
+        "checked" => ("ischecked", "checked"),
+        _ => ("count", "count"),
+    }
+}
+
+/// Glob as used by `wait --url`: `**` matches anything (`**/` zero or more
+/// directories), `*` anything but `/`
+pub fn glob_to_regex(pattern: &str) -> String {
+    let mut re = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '*' {
+            if chars.peek() == Some(&'*') {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    re.push_str("(?:.*/)?");
+                } else {
+                    re.push_str(".*");
+                }
+            } else {
+                re.push_str("[^/]*");
+            }
+        } else {
+            re.push_str(&regex::escape(&c.to_string()));
+        }
+    }
+    re.push('$');
+    re
+}
+
+fn compare_text(op: &str, actual: &str, expected: &str) -> bool {
+    match op {
+        "contains" => actual.contains(expected),
+        "equals" => actual == expected,
+        "matches" => Regex::new(expected).map(|re| re.is_match(actual)).unwrap_or(false),
+        "glob" => Regex::new(&glob_to_regex(expected)).map(|re| re.is_match(actual)).unwrap_or(false),
+        _ => false,
+    }
+}
+
+fn compare_count(op: &str, actual: u64, expected: u64) -> bool {
+    match op {
+        "==" => actual == expected,
+        "!=" => actual != expected,
+        "<" => actual < expected,
+        "<=" => actual <= expected,
+        ">" => actual > expected,
+        ">=" => actual >= expected,
+        _ => false,
+    }
+}
+
+fn passes(cmd: &Value, actual: &Value) -> bool {
+    let check = cmd["check"].as_str().unwrap_or_default();
+    let op = cmd["op"].as_str().unwrap_or_default();
+    match check {
+        "text" | "url" | "title" => compare_text(
+            op,
+            actual.as_str().unwrap_or_default(),
+            cmd["expected"].as_str().unwrap_or_default(),
+        ),
+        "hidden" => actual.as_bool() == Some(false),
+        "count" => match (actual.as_u64(), cmd["expected"].as_u64()) {
+            (Some(a), Some(e)) => compare_count(op, a, e),
+            _ => false,
+        },
+        _ => actual.as_bool() == Some(true),
+    }
+}
+
+/// What the assertion expects, e.g. `contains "Done"` or `>= 3`
+fn describe_expected(cmd: &Value) -> String {
+    let check = cmd["check"].as_str().unwrap_or_default();
+    let op = cmd["op"].as_str().unwrap_or_default();
+    match check {
+        "text" | "url" | "title" => {
+            let op = if op == "glob" { "matches glob" } else { op };
+            format!("{} {}", op, cmd["expected"])
+        }
+        "count" => format!("{} {}", op, cmd["expected"]),
+        _ => check.to_string(),
+    }
+}
+
+/// The assertion as a sentence, e.g. `text of "#status" contains "Done"`
+fn describe(cmd: &Value) -> String {
+    let check = cmd["check"].as_str().unwrap_or_default();
+    let selector = &cmd["selector"];
+    match check {
+        "text" => format!("text of {} {}", selector, describe_expected(cmd)),
+        "url" | "title" => format!("{} {}", check, describe_expected(cmd)),
+        "count" => format!("count of {} {}", selector, describe_expected(cmd)),
+        _ => format!("{} is {}", selector, check),
+    }
+}
+
+/// An observed value in the same terms as `describe_expected`
+fn describe_actual(check: &str, actual: &Value) -> String {
+    let state = |yes: &str, no: &str| match actual.as_bool() {
+        Some(true) => yes.to_string(),
+        Some(false) => no.to_string(),
+        None => actual.to_string(),
+    };
+    match check {
+        "visible" | "hidden" => state("visible", "hidden"),
+        "enabled" => state("enabled", "disabled"),
+        "checked" => state("checked", "unchecked"),
+        _ => actual.to_string(),
+    }
+}
+
+/// Line-by-line diff of an expected and actual multi-line string, colored
+/// unless it ends up in a --json envelope
+fn diff_lines(expected: &str, actual: &str, colored: bool) -> String {
+    let red = |s: &str| if colored { color::red(s) } else { s.to_string() };
+    let green = |s: &str| if colored { color::green(s) } else { s.to_string() };
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let mut out = vec![red("  - expected"), green("  + actual")];
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) if e == a => out.push(format!("    {}", e)),
+            (e, a) => {
+                if let Some(e) = e {
+                    out.push(red(&format!("  - {}", e)));
+                }
+                if let Some(a) = a {
+                    out.push(green(&format!("  + {}", a)));
+                }
+            }
+        }
+    }
+    out.join("\n")
+}
+
+fn failure_message(
+    cmd: &Value,
+    observed: &Result<Value, String>,
+    attempts: u32,
+    waited: Duration,
+    colored: bool,
+) -> String {
+    let check = cmd["check"].as_str().unwrap_or_default();
+    let mut lines = vec![format!("Assertion failed: {}", describe(cmd))];
+    match observed {
+        Ok(Value::String(actual))
+            if cmd["op"] == "equals" && (actual.contains('\n') || cmd["expected"].as_str().unwrap_or_default().contains('\n')) =>
+        {
+            lines.push(diff_lines(cmd["expected"].as_str().unwrap_or_default(), actual, colored));
+        }
+        Ok(actual) => {
+            lines.push(format!("  expected: {}", describe_expected(cmd)));
+            lines.push(format!("  actual:   {}", describe_actual(check, actual)));
+        }
+        Err(e) => {
+            lines.push(format!("  expected: {}", describe_expected(cmd)));
+            lines.push(format!("  error:    {}", e));
+        }
+    }
+    if attempts > 1 {
+        lines.push(format!("  waited:   {}ms ({} checks)", waited.as_millis(), attempts));
+    }
+    lines.join("\n")
+}
+
+/// How an assertion ended after polling
+pub struct Outcome {
+    pub passed: bool,
+    /// The last observed value, or the daemon's error for the underlying action
+    pub observed: Result<Value, String>,
+    pub attempts: u32,
+    pub elapsed: Duration,
+}
+
+impl Outcome {
+    /// Plain-text explanation of a failed assertion
+    pub fn failure_message(&self, cmd: &Value) -> String {
+        failure_message(cmd, &self.observed, self.attempts, self.elapsed, false)
+    }
+}
+
+/// Poll the action behind an `assert` command until the check passes or its
+/// timeout runs out. Only failing to reach the daemon is an error.
+pub fn check_assertion(cmd: &Value, session: &str) -> Result<Outcome, CliError> {
+    let check = cmd["check"].as_str().unwrap_or_default();
+    let (action, field) = query_for(check);
+    let mut query = json!({ "action": action });
+    if let Some(selector) = cmd.get("selector") {
+        query["selector"] = selector.clone();
+    }
+
+    let start = Instant::now();
+    let timeout = Duration::from_millis(cmd["timeout"].as_u64().unwrap_or(0));
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        query["id"] = json!(gen_id());
+        // A daemon-side failure (e.g. element not found) counts as not passing yet
+        let observed = match send_command(query.clone(), session)? {
+            resp if resp.success => {
+                Ok(resp.data.as_ref().and_then(|d| d.get(field)).cloned().unwrap_or(Value::Null))
+            }
+            resp => Err(resp.error.unwrap_or_else(|| "Unknown error".to_string())),
+        };
+
+        let passed = observed.as_ref().is_ok_and(|actual| passes(cmd, actual));
+        let elapsed = start.elapsed();
+        if passed || elapsed >= timeout {
+            return Ok(Outcome { passed, observed, attempts, elapsed });
+        }
+        thread::sleep(POLL_INTERVAL.min(timeout - elapsed));
+    }
+}
+
+/// Run an `assert` command and print the result, returning the failure message
+/// if it did not pass
+pub fn run_assert(cmd: &Value, session: &str, json_mode: bool) -> Result<(), String> {
+    let outcome = match check_assertion(cmd, session) {
+        Ok(outcome) => outcome,
+        Err(e) => fail(e.kind, &e.message, Some("assert"), json_mode),
+    };
+
+    if outcome.passed {
+        if json_mode {
+            let data = json!({
+                "assertion": describe(cmd),
+                "actual": outcome.observed.unwrap_or(Value::Null),
+                "attempts": outcome.attempts
+            });
+            println!("{}", json!({ "success": true, "data": data }));
+        } else {
+            println!("{} {}", color::success_indicator(), describe(cmd));
+        }
+        return Ok(());
+    }
+
+    let message = failure_message(cmd, &outcome.observed, outcome.attempts, outcome.elapsed, !json_mode);
+    if json_mode {
+        let mut envelope = error_envelope(ErrorKind::AssertionFailed, &message, Some("assert"));
+        envelope["expected"] = cmd["expected"].clone();
+        envelope["actual"] = outcome.observed.clone().unwrap_or(Value::Null);
+        envelope["attempts"] = json!(outcome.attempts);
+        println!("{}", envelope);
+    } else {
+        print_error(ErrorKind::AssertionFailed, &message, Some("assert"), false);
+    }
+    Err(outcome.failure_message(cmd))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_to_regex() {
+        let re = Regex::new(&glob_to_regex("**/dashboard")).unwrap();
+        assert!(re.is_match("https://example.com/app/dashboard"));
+        assert!(!re.is_match("https://example.com/dashboard/settings"));
+        let re = Regex::new(&glob_to_regex("https://example.com/*/edit?tab=1")).unwrap();
+        assert!(re.is_match("https://example.com/42/edit?tab=1"));
+        assert!(!re.is_match("https://example.com/a/b/edit?tab=1"));
+    }
+
+    #[test]
+    fn test_glob_double_star_matches_zero_dirs() {
+        let re = Regex::new(&glob_to_regex("tests/**/*.ab")).unwrap();
+        assert!(re.is_match("tests/login.ab"));
+        assert!(re.is_match("tests/auth/login.ab"));
+        assert!(re.is_match("tests/a/b/login.ab"));
+        assert!(!re.is_match("other/login.ab"));
+    }
+
+    #[test]
+    fn test_passes() {
+        let text = json!({ "check": "text", "op": "matches", "expected": "^Order #\\d+" });
+        assert!(passes(&text, &json!("Order #1234 placed")));
+        assert!(!passes(&text, &json!("Cart")));
+        let count = json!({ "check": "count", "op": "<", "expected": 3 });
+        assert!(passes(&count, &json!(2)));
+        assert!(!passes(&count, &json!(3)));
+        let hidden = json!({ "check": "hidden", "selector": "#modal" });
+        assert!(passes(&hidden, &json!(false)));
+        assert!(!passes(&hidden, &json!(true)));
+    }
+
+    #[test]
+    fn test_failure_message() {
+        let cmd = json!({ "check": "text", "selector": "#status", "op": "contains", "expected": "Done" });
+        let message = failure_message(&cmd, &Ok(json!("Loading")), 1, Duration::ZERO, false);
+        assert_eq!(
+            message,
+            "Assertion failed: text of \"#status\" contains \"Done\"\n  expected: contains \"Done\"\n  actual:   \"Loading\""
+        );
+        let cmd = json!({ "check": "visible", "selector": "#btn" });
+        let message = failure_message(&cmd, &Ok(json!(false)), 12, Duration::from_millis(3000), false);
+        assert!(message.ends_with("  actual:   hidden\n  waited:   3000ms (12 checks)"));
+        let cmd = json!({ "check": "text", "selector": "#s", "op": "equals", "expected": "a\nb\nc" });
+        let message = failure_message(&cmd, &Ok(json!("a\nB\nc")), 1, Duration::ZERO, false);
+        assert!(message.ends_with("  - expected\n  + actual\n    a\n  - b\n  + B\n    c"));
+    }
+}
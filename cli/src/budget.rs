@@ -0,0 +1,363 @@
+//! Fitting a snapshot into `--max-chars` / `--max-tokens`.
+//!
+//! The text tree is reduced one step at a time until it fits:
+//!
+//! 1. long runs of similar non-interactive siblings (table rows, list items)
+//!    keep their first few items and end with `- ... 48 more similar rows`
+//! 2. subtrees without interactive elements are collapsed, deepest first
+//! 3. long runs of interactive siblings (links, buttons, options) are shortened
+//!    the same way
+//! 4. any remaining subtrees are collapsed, deepest first
+//! 5. as a last resort the output is cut off
+//!
+//! Shown lines are never rewritten, so every ref that is left stays valid.
+
+use crate::snapshot::parse_line;
+
+/// Rough number of characters per token, for `--max-tokens`
+pub const CHARS_PER_TOKEN: usize = 4;
+
+/// Roles the daemon gives refs because they can be acted on (INTERACTIVE_ROLES in snapshot.ts)
+const INTERACTIVE_ROLES: &[&str] = &[
+    "button",
+    "link",
+    "textbox",
+    "checkbox",
+    "radio",
+    "combobox",
+    "listbox",
+    "menuitem",
+    "menuitemcheckbox",
+    "menuitemradio",
+    "option",
+    "searchbox",
+    "slider",
+    "spinbutton",
+    "switch",
+    "tab",
+    "treeitem",
+];
+
+/// Runs of more similar siblings than this are shortened
+const MAX_RUN: usize = 5;
+
+#[derive(Clone)]
+enum Child {
+    Node(usize),
+    /// A `- ... N more ...` line standing in for `hidden` lines
+    Marker { line: String, hidden: usize },
+}
+
+#[derive(Clone)]
+struct Node<'a> {
+    line: &'a str,
+    indent: usize,
+    /// 0 for the (virtual) root, 1 for top-level lines
+    depth: usize,
+    children: Vec<Child>,
+}
+
+/// A snapshot as a tree of its lines; node 0 is a virtual root
+#[derive(Clone)]
+struct Tree<'a> {
+    nodes: Vec<Node<'a>>,
+}
+
+fn line_chars(line: &str) -> usize {
+    line.chars().count() + 1
+}
+
+fn plural(n: usize, word: &str) -> String {
+    if n == 1 {
+        word.to_string()
+    } else {
+        format!("{}s", word)
+    }
+}
+
+impl<'a> Tree<'a> {
+    fn parse(text: &'a str) -> Self {
+        let mut nodes = vec![Node { line: "", indent: 0, depth: 0, children: Vec::new() }];
+        let mut open = vec![0];
+        for line in text.lines() {
+            let indent = line.len() - line.trim_start().len();
+            while open.len() > 1 && nodes[open[open.len() - 1]].indent >= indent {
+                open.pop();
+            }
+            let parent = open[open.len() - 1];
+            let idx = nodes.len();
+            nodes.push(Node { line, indent, depth: open.len(), children: Vec::new() });
+            nodes[parent].children.push(Child::Node(idx));
+            open.push(idx);
+        }
+        Tree { nodes }
+    }
+
+    fn role(&self, idx: usize) -> &'a str {
+        parse_line(self.nodes[idx].line).1
+    }
+
+    /// Lines below a node, including those already hidden behind markers
+    fn hidden_below(&self, idx: usize) -> usize {
+        self.nodes[idx]
+            .children
+            .iter()
+            .map(|c| match c {
+                Child::Node(i) => 1 + self.hidden_below(*i),
+                Child::Marker { hidden, .. } => *hidden,
+            })
+            .sum()
+    }
+
+    /// Characters the children of a node take up when rendered
+    fn chars_below(&self, idx: usize) -> usize {
+        self.nodes[idx]
+            .children
+            .iter()
+            .map(|c| match c {
+                Child::Node(i) => line_chars(self.nodes[*i].line) + self.chars_below(*i),
+                Child::Marker { line, .. } => line_chars(line),
+            })
+            .sum()
+    }
+
+    /// Whether a node is, or contains, an interactive element
+    fn is_interactive(&self, idx: usize) -> bool {
+        INTERACTIVE_ROLES.contains(&self.role(idx)) || self.has_interactive(idx)
+    }
+
+    fn has_interactive(&self, idx: usize) -> bool {
+        self.nodes[idx].children.iter().any(|c| match c {
+            Child::Node(i) => self.is_interactive(*i),
+            Child::Marker { .. } => false,
+        })
+    }
+
+    /// Indentation for a marker among a node's children
+    fn child_indent(&self, idx: usize) -> String {
+        let indent = self.nodes[idx].children.iter().find_map(|c| match c {
+            Child::Node(i) => Some(self.nodes[*i].indent),
+            Child::Marker { .. } => None,
+        });
+        " ".repeat(indent.unwrap_or(self.nodes[idx].indent + 2))
+    }
+
+    /// Keep the first `keep` items of every run of more than MAX_RUN siblings with
+    /// the same role. Runs of interactive items are only shortened with `interactive`.
+    fn elide_runs(&mut self, keep: usize, interactive: bool) {
+        for idx in 0..self.nodes.len() {
+            let children = std::mem::take(&mut self.nodes[idx].children);
+            let mut kept = Vec::new();
+            let mut run: Vec<usize> = Vec::new();
+            for child in children.into_iter().chain([Child::Marker { line: String::new(), hidden: 0 }]) {
+                let same = match (&child, run.first()) {
+                    (Child::Node(i), Some(first)) => self.role(*i) == self.role(*first),
+                    _ => false,
+                };
+                if same {
+                    if let Child::Node(i) = child {
+                        run.push(i);
+                    }
+                    continue;
+                }
+                self.flush_run(&mut kept, std::mem::take(&mut run), keep, interactive);
+                match child {
+                    Child::Node(i) => run.push(i),
+                    Child::Marker { line, .. } if line.is_empty() => {}
+                    marker => kept.push(marker),
+                }
+            }
+            self.nodes[idx].children = kept;
+        }
+    }
+
+    fn flush_run(&self, kept: &mut Vec<Child>, run: Vec<usize>, keep: usize, interactive: bool) {
+        if run.len() <= MAX_RUN || (!interactive && run.iter().any(|i| self.is_interactive(*i))) {
+            kept.extend(run.into_iter().map(Child::Node));
+            return;
+        }
+        let role = self.role(run[0]);
+        let indent = " ".repeat(self.nodes[run[0]].indent);
+        let rest = run.len() - keep;
+        let hidden = run[keep..].iter().map(|i| 1 + self.hidden_below(*i)).sum();
+        kept.extend(run[..keep].iter().map(|i| Child::Node(*i)));
+        let line = format!("{}- ... {} more similar {}", indent, rest, plural(rest, role));
+        kept.push(Child::Marker { line, hidden });
+    }
+
+    /// A copy with long runs shortened to three items, or to one if that does not fit
+    fn with_runs_elided(&self, interactive: bool, max_chars: usize) -> Self {
+        let mut tree = self.clone();
+        tree.elide_runs(3, interactive);
+        if tree.render().chars().count() > max_chars {
+            tree = self.clone();
+            tree.elide_runs(1, interactive);
+        }
+        tree
+    }
+
+    /// Replace the children of nodes at `depth` with a marker, where that saves space
+    fn collapse(&mut self, depth: usize, keep_interactive: bool) {
+        for idx in 1..self.nodes.len() {
+            if self.nodes[idx].depth != depth || self.nodes[idx].children.is_empty() {
+                continue;
+            }
+            if keep_interactive && self.has_interactive(idx) {
+                continue;
+            }
+            let hidden = self.hidden_below(idx);
+            let line = format!("{}- ... {} more {}", self.child_indent(idx), hidden, plural(hidden, "line"));
+            if line_chars(&line) < self.chars_below(idx) {
+                self.nodes[idx].children = vec![Child::Marker { line, hidden }];
+            }
+        }
+    }
+
+    fn max_depth(&self) -> usize {
+        self.nodes.iter().map(|n| n.depth).max().unwrap_or(0)
+    }
+
+    fn render_into(&self, idx: usize, out: &mut Vec<String>) {
+        for child in &self.nodes[idx].children {
+            match child {
+                Child::Node(i) => {
+                    out.push(self.nodes[*i].line.to_string());
+                    self.render_into(*i, out);
+                }
+                Child::Marker { line, .. } => out.push(line.clone()),
+            }
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut out = Vec::new();
+        self.render_into(0, &mut out);
+        out.join("\n")
+    }
+}
+
+/// Keep whole lines of `text` up to `max_chars`, ending with a note of how many were cut
+pub fn truncate_lines(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let lines: Vec<&str> = text.lines().collect();
+    let note = format!("... {} more lines (truncated)", lines.len());
+    if note.chars().count() >= max_chars {
+        // Not even the note fits a budget this small
+        return note.chars().take(max_chars).collect();
+    }
+    let mut used = line_chars(&note);
+    let mut kept = 0;
+    for line in &lines {
+        if used + line_chars(line) > max_chars {
+            break;
+        }
+        used += line_chars(line);
+        kept += 1;
+    }
+    let cut = lines.len() - kept;
+    let mut out: Vec<String> = lines[..kept].iter().map(|l| l.to_string()).collect();
+    out.push(format!("... {} more {} (truncated)", cut, plural(cut, "line")));
+    out.join("\n")
+}
+
+/// Reduce a snapshot tree to at most `max_chars` characters (see the module docs)
+pub fn fit_snapshot(text: &str, max_chars: usize) -> String {
+    let fits = |s: &str| s.chars().count() <= max_chars;
+    if fits(text) {
+        return text.to_string();
+    }
+
+    let mut tree = Tree::parse(text).with_runs_elided(false, max_chars);
+    if fits(&tree.render()) {
+        return tree.render();
+    }
+    for depth in (1..tree.max_depth()).rev() {
+        tree.collapse(depth, true);
+        let out = tree.render();
+        if fits(&out) {
+            return out;
+        }
+    }
+    tree = tree.with_runs_elided(true, max_chars);
+    if fits(&tree.render()) {
+        return tree.render();
+    }
+    for depth in (1..tree.max_depth()).rev() {
+        tree.collapse(depth, false);
+        let out = tree.render();
+        if fits(&out) {
+            return out;
+        }
+    }
+    truncate_lines(&tree.render(), max_chars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(rows: usize) -> String {
+        let mut lines = vec!["- heading \"Orders\" [ref=e1] [level=1]".to_string(), "- table:".to_string()];
+        for i in 0..rows {
+            lines.push("  - row:".to_string());
+            lines.push(format!("    - cell \"Order {}\" [ref=e{}]", i, i + 2));
+        }
+        lines.push(format!("- button \"Next page\" [ref=e{}]", rows + 2));
+        lines.join("\n")
+    }
+
+    #[test]
+    fn test_fits_unchanged() {
+        let text = table(3);
+        assert_eq!(fit_snapshot(&text, 10_000), text);
+    }
+
+    #[test]
+    fn test_elides_similar_rows() {
+        let out = fit_snapshot(&table(50), 500);
+        assert_eq!(
+            out,
+            "- heading \"Orders\" [ref=e1] [level=1]\n- table:\n  - row:\n    - cell \"Order 0\" [ref=e2]\n  - row:\n    - cell \"Order 1\" [ref=e3]\n  - row:\n    - cell \"Order 2\" [ref=e4]\n  - ... 47 more similar rows\n- button \"Next page\" [ref=e52]"
+        );
+    }
+
+    #[test]
+    fn test_collapses_non_interactive_first() {
+        let text = "- navigation:\n  - link \"Home\" [ref=e1]\n  - link \"Docs\" [ref=e2]\n- main:\n  - article:\n    - paragraph: A long paragraph of text that nobody will click on\n    - paragraph: Another long paragraph of text that is not interactive";
+        let out = fit_snapshot(text, 120);
+        assert_eq!(
+            out,
+            "- navigation:\n  - link \"Home\" [ref=e1]\n  - link \"Docs\" [ref=e2]\n- main:\n  - article:\n    - ... 2 more lines"
+        );
+    }
+
+    #[test]
+    fn test_keeps_link_runs_over_text() {
+        let links: Vec<String> = (1..=12).map(|i| format!("  - link \"Section {}\" [ref=e{}]", i, i)).collect();
+        let text = format!(
+            "- navigation:\n{}\n- main:\n  - article:\n    - section:\n      - paragraph: {}\n      - paragraph: {}",
+            links.join("\n"),
+            "A long paragraph of text that nobody will click on",
+            "Another long paragraph of text that is not interactive"
+        );
+        let out = fit_snapshot(&text, 440);
+        assert_eq!(
+            out,
+            format!("- navigation:\n{}\n- main:\n  - article:\n    - section:\n      - ... 2 more lines", links.join("\n"))
+        );
+        // Only a tighter budget shortens the links themselves
+        let out = fit_snapshot(&text, 250);
+        assert!(out.contains("  - link \"Section 1\" [ref=e1]\n"));
+        assert!(out.contains("more similar links"));
+    }
+
+    #[test]
+    fn test_truncate_lines() {
+        let out = truncate_lines("- first line\n- second line\n- third line\n- fourth line", 45);
+        assert_eq!(out, "- first line\n... 3 more lines (truncated)");
+        let out = truncate_lines("- first line\n- second line", 10);
+        assert_eq!(out.chars().count(), 10);
+    }
+}
@@ -1,6 +1,10 @@
+use std::path::Path;
+
 use serde_json::{json, Value};
 
+use crate::budget::CHARS_PER_TOKEN;
 use crate::flags::Flags;
+use crate::history::get_history_path;
 
 /// Error type for command parsing with contextual information
 #[derive(Debug)]
@@ -338,7 +342,56 @@ pub fn parse_command(args: &[String], flags: &Flags) -> Result<Value, ParseError
             // screenshot [selector] [path]
             // selector: @ref or CSS selector
             // path: file path (contains / or . or ends with known extension)
-            let (selector, path) = match (rest.get(0), rest.get(1)) {
+            const USAGE: &str =
+                "screenshot [selector] [path] [--annotate] [--compare <baseline.png> [--threshold <0-1>] [--diff-out <file>] [--update]]";
+            let mut options = serde_json::Map::new();
+            let mut positional = Vec::new();
+            let mut i = 0;
+            while i < rest.len() {
+                match rest[i] {
+                    "--annotate" => {
+                        options.insert("annotate".to_string(), json!(true));
+                    }
+                    // --compare and its options are handled by the CLI (see visual.rs)
+                    "--update" => {
+                        options.insert("update".to_string(), json!(true));
+                    }
+                    "--compare" | "--threshold" | "--diff-out" => {
+                        let value = rest.get(i + 1).ok_or_else(|| ParseError::MissingArguments {
+                            context: format!("screenshot {}", rest[i]),
+                            usage: USAGE,
+                        })?;
+                        let (key, value) = match rest[i] {
+                            "--compare" => ("compare", json!(value)),
+                            "--diff-out" => ("diffOut", json!(value)),
+                            _ => match value.parse::<f64>() {
+                                Ok(t) if (0.0..=1.0).contains(&t) => ("threshold", json!(t)),
+                                _ => {
+                                    return Err(ParseError::InvalidValue {
+                                        message: format!("Invalid threshold: {} (expected 0 to 1)", value),
+                                        usage: USAGE,
+                                    })
+                                }
+                            },
+                        };
+                        options.insert(key.to_string(), value);
+                        i += 1;
+                    }
+                    arg => positional.push(arg),
+                }
+                i += 1;
+            }
+            if !options.contains_key("compare") {
+                for (key, flag) in [("update", "--update"), ("threshold", "--threshold"), ("diffOut", "--diff-out")] {
+                    if options.contains_key(key) {
+                        return Err(ParseError::InvalidValue {
+                            message: format!("{} only applies with --compare <baseline.png>", flag),
+                            usage: USAGE,
+                        });
+                    }
+                }
+            }
+            let (selector, path) = match (positional.first(), positional.get(1)) {
                 (Some(first), Some(second)) => {
                     // Two args: first is selector, second is path
                     (Some(*first), Some(*second))
@@ -361,7 +414,9 @@ pub fn parse_command(args: &[String], flags: &Flags) -> Result<Value, ParseError
                 }
                 _ => (None, None),
             };
-            Ok(json!({ "id": id, "action": "screenshot", "path": path, "selector": selector, "fullPage": flags.full }))
+            let mut cmd = json!({ "id": id, "action": "screenshot", "path": path, "selector": selector, "fullPage": flags.full });
+            cmd.as_object_mut().unwrap().extend(options);
+            Ok(cmd)
         }
         "pdf" => {
             let path = rest.get(0).ok_or_else(|| ParseError::MissingArguments {
@@ -398,10 +453,95 @@ pub fn parse_command(args: &[String], flags: &Flags) -> Result<Value, ParseError
                             i += 1;
                         }
                     }
+                    "--visible" => {
+                        obj.insert("visible".to_string(), json!(true));
+                    }
+                    "--page" => {
+                        let usage = "snapshot --page <n>";
+                        match rest.get(i + 1).map(|v| v.parse::<u64>()) {
+                            Some(Ok(n)) if n > 0 => {
+                                obj.insert("page".to_string(), json!(n));
+                            }
+                            Some(_) => {
+                                return Err(ParseError::InvalidValue {
+                                    message: format!("Invalid page: {} (pages start at 1)", rest[i + 1]),
+                                    usage,
+                                })
+                            }
+                            None => {
+                                return Err(ParseError::MissingArguments {
+                                    context: "snapshot --page".to_string(),
+                                    usage,
+                                })
+                            }
+                        }
+                        i += 1;
+                    }
+                    // --diff and --save are handled by the CLI (see snapshot.rs)
+                    "--diff" => {
+                        obj.insert("diff".to_string(), json!(true));
+                    }
+                    "--save" => {
+                        let path = rest.get(i + 1).ok_or_else(|| ParseError::MissingArguments {
+                            context: "snapshot --save".to_string(),
+                            usage: "snapshot --save <file>",
+                        })?;
+                        obj.insert("save".to_string(), json!(path));
+                        i += 1;
+                    }
+                    "--max-chars" | "--max-tokens" => {
+                        let usage = "snapshot --max-chars <n> | --max-tokens <n>";
+                        let n = match rest.get(i + 1).map(|v| v.parse::<u64>()) {
+                            Some(Ok(n)) if n > 0 => n,
+                            Some(_) => {
+                                return Err(ParseError::InvalidValue {
+                                    message: format!("Invalid value for {}: expected a positive number", rest[i]),
+                                    usage,
+                                })
+                            }
+                            None => {
+                                return Err(ParseError::MissingArguments {
+                                    context: format!("snapshot {}", rest[i]),
+                                    usage,
+                                })
+                            }
+                        };
+                        // Both are enforced by the CLI as a character budget (see budget.rs)
+                        let max_chars = if rest[i] == "--max-tokens" { n * CHARS_PER_TOKEN as u64 } else { n };
+                        let max_chars = obj.get("maxChars").and_then(|v| v.as_u64()).map_or(max_chars, |m| m.min(max_chars));
+                        obj.insert("maxChars".to_string(), json!(max_chars));
+                        i += 1;
+                    }
+                    "--format" => {
+                        const USAGE: &str = "snapshot --format <text|json|yaml>";
+                        let format = match rest.get(i + 1).copied() {
+                            Some(f @ ("text" | "json" | "yaml")) => f,
+                            Some(f) => {
+                                return Err(ParseError::InvalidValue {
+                                    message: format!("Unknown snapshot format: {}", f),
+                                    usage: USAGE,
+                                })
+                            }
+                            None => {
+                                return Err(ParseError::MissingArguments {
+                                    context: "snapshot --format".to_string(),
+                                    usage: USAGE,
+                                })
+                            }
+                        };
+                        obj.insert("format".to_string(), json!(format));
+                        i += 1;
+                    }
                     _ => {}
                 }
                 i += 1;
             }
+            if cmd["diff"] == true && cmd.get("format").is_some_and(|f| f != "text") {
+                return Err(ParseError::InvalidValue {
+                    message: "--diff only works with the text format".to_string(),
+                    usage: "snapshot --diff [-i] [-c] [-d <n>] [-s <sel>]",
+                });
+            }
             Ok(cmd)
         }
 
@@ -463,6 +603,36 @@ pub fn parse_command(args: &[String], flags: &Flags) -> Result<Value, ParseError
         // === Is (state checks) ===
         "is" => parse_is(&rest, &id),
 
+        // === Assert (CI checks, evaluated by the CLI on top of get/is) ===
+        "assert" => parse_assert(&rest, &id),
+
+        // === Replay (re-executes a history journal, run by the CLI) ===
+        "replay" => {
+            const USAGE: &str = "replay <journal|session> [--from <n>]";
+            let journal = rest.first().filter(|s| !s.starts_with("--")).ok_or_else(|| {
+                ParseError::MissingArguments { context: "replay".to_string(), usage: USAGE }
+            })?;
+            // A session name stands for that session's journal
+            let path = if Path::new(journal).exists() {
+                journal.to_string()
+            } else {
+                get_history_path(journal).to_string_lossy().to_string()
+            };
+            let from = match rest.iter().position(|&s| s == "--from") {
+                Some(idx) => match rest.get(idx + 1).and_then(|s| s.parse::<u64>().ok()) {
+                    Some(n) if n > 0 => n,
+                    _ => {
+                        return Err(ParseError::InvalidValue {
+                            message: "Invalid value for --from: expected an entry number (from 1)".to_string(),
+                            usage: USAGE,
+                        })
+                    }
+                },
+                None => 1,
+            };
+            Ok(json!({ "id": id, "action": "replay", "journal": path, "from": from }))
+        }
+
         // === Find (locators) ===
         "find" => parse_find(&rest, &id),
 
@@ -704,7 +874,7 @@ pub fn parse_command(args: &[String], flags: &Flags) -> Result<Value, ParseError
 }
 
 fn parse_get(rest: &[&str], id: &str) -> Result<Value, ParseError> {
-    const VALID: &[&str] = &["text", "html", "value", "attr", "url", "title", "count", "box", "styles"];
+    const VALID: &[&str] = &["text", "html", "value", "attr", "url", "title", "count", "box", "styles", "locator"];
     
     match rest.get(0).map(|s| *s) {
         Some("text") => {
@@ -762,6 +932,13 @@ fn parse_get(rest: &[&str], id: &str) -> Result<Value, ParseError> {
             })?;
             Ok(json!({ "id": id, "action": "styles", "selector": sel }))
         }
+        Some("locator") => {
+            let sel = rest.get(1).ok_or_else(|| ParseError::MissingArguments {
+                context: "get locator".to_string(),
+                usage: "get locator <ref|selector>",
+            })?;
+            Ok(json!({ "id": id, "action": "locator", "selector": sel }))
+        }
         Some(sub) => Err(ParseError::UnknownSubcommand {
             subcommand: sub.to_string(),
             valid_options: VALID,
@@ -809,6 +986,139 @@ fn parse_is(rest: &[&str], id: &str) -> Result<Value, ParseError> {
     }
 }
 
+const ASSERT_USAGE: &str = "assert <text|url|title|visible|hidden|enabled|checked|count> ... [--timeout <ms>]";
+
+fn parse_assert(rest: &[&str], id: &str) -> Result<Value, ParseError> {
+    const VALID: &[&str] = &["text", "url", "title", "visible", "hidden", "enabled", "checked", "count"];
+    const TEXT_OPS: &[&str] = &["contains", "equals", "matches"];
+
+    // Pull out --timeout <ms> so positional arguments can be read by index
+    let mut args: Vec<&str> = Vec::new();
+    let mut timeout: u64 = 0;
+    let mut i = 0;
+    while i < rest.len() {
+        if rest[i] == "--timeout" {
+            let value = rest.get(i + 1).ok_or_else(|| ParseError::MissingArguments {
+                context: "assert --timeout".to_string(),
+                usage: ASSERT_USAGE,
+            })?;
+            timeout = value.parse::<u64>().map_err(|_| ParseError::InvalidValue {
+                message: format!("Invalid timeout: '{}' (expected milliseconds)", value),
+                usage: ASSERT_USAGE,
+            })?;
+            i += 2;
+            continue;
+        }
+        args.push(rest[i]);
+        i += 1;
+    }
+
+    let check = match args.first() {
+        Some(c) => *c,
+        None => {
+            return Err(ParseError::MissingArguments {
+                context: "assert".to_string(),
+                usage: ASSERT_USAGE,
+            })
+        }
+    };
+    let mut cmd = json!({ "id": id, "action": "assert", "check": check, "timeout": timeout });
+
+    let validate_regex = |pattern: &str, usage: &'static str| {
+        regex::Regex::new(pattern).map(|_| ()).map_err(|e| ParseError::InvalidValue {
+            message: format!("Invalid regular expression '{}': {}", pattern, e),
+            usage,
+        })
+    };
+
+    match check {
+        "text" => {
+            const USAGE: &str = "assert text <selector> <contains|equals|matches> <value>";
+            let (Some(sel), Some(op), Some(expected)) = (args.get(1), args.get(2), args.get(3)) else {
+                return Err(ParseError::MissingArguments { context: "assert text".to_string(), usage: USAGE });
+            };
+            if !TEXT_OPS.contains(op) {
+                return Err(ParseError::InvalidValue {
+                    message: format!("Unknown comparison: {} (expected contains, equals, or matches)", op),
+                    usage: USAGE,
+                });
+            }
+            if *op == "matches" {
+                validate_regex(expected, USAGE)?;
+            }
+            cmd["selector"] = json!(sel);
+            cmd["op"] = json!(op);
+            cmd["expected"] = json!(expected);
+        }
+        "url" | "title" => {
+            let usage = if check == "url" {
+                "assert url [contains|equals|matches] <pattern>"
+            } else {
+                "assert title [contains|equals|matches] <value>"
+            };
+            // The comparison is optional: a URL defaults to a glob pattern
+            // (like `wait --url`), a title to an exact match
+            let (op, expected) = match (args.get(1), args.get(2)) {
+                (Some(op), Some(expected)) if TEXT_OPS.contains(op) => (*op, *expected),
+                (Some(expected), None) => (if check == "url" { "glob" } else { "equals" }, *expected),
+                (Some(op), Some(_)) => {
+                    return Err(ParseError::InvalidValue {
+                        message: format!("Unknown comparison: {} (expected contains, equals, or matches)", op),
+                        usage,
+                    })
+                }
+                _ => return Err(ParseError::MissingArguments { context: format!("assert {}", check), usage }),
+            };
+            if op == "matches" {
+                validate_regex(expected, usage)?;
+            }
+            cmd["op"] = json!(op);
+            cmd["expected"] = json!(expected);
+        }
+        "visible" | "hidden" | "enabled" | "checked" => {
+            let sel = args.get(1).ok_or_else(|| ParseError::MissingArguments {
+                context: format!("assert {}", check),
+                usage: "assert <visible|hidden|enabled|checked> <selector>",
+            })?;
+            cmd["selector"] = json!(sel);
+        }
+        "count" => {
+            const USAGE: &str = "assert count <selector> <op> <n>  (op: = != < <= > >=, or eq ne lt le gt ge)";
+            let (Some(sel), Some(op), Some(n)) = (args.get(1), args.get(2), args.get(3)) else {
+                return Err(ParseError::MissingArguments { context: "assert count".to_string(), usage: USAGE });
+            };
+            let op = match *op {
+                "=" | "==" | "eq" => "==",
+                "!=" | "ne" => "!=",
+                "<" | "lt" => "<",
+                "<=" | "le" => "<=",
+                ">" | "gt" => ">",
+                ">=" | "ge" => ">=",
+                other => {
+                    return Err(ParseError::InvalidValue {
+                        message: format!("Unknown comparison: {}", other),
+                        usage: USAGE,
+                    })
+                }
+            };
+            let n = n.parse::<u64>().map_err(|_| ParseError::InvalidValue {
+                message: format!("Invalid count: '{}' (expected a whole number)", n),
+                usage: USAGE,
+            })?;
+            cmd["selector"] = json!(sel);
+            cmd["op"] = json!(op);
+            cmd["expected"] = json!(n);
+        }
+        other => {
+            return Err(ParseError::UnknownSubcommand {
+                subcommand: other.to_string(),
+                valid_options: VALID,
+            })
+        }
+    }
+    Ok(cmd)
+}
+
 fn parse_find(rest: &[&str], id: &str) -> Result<Value, ParseError> {
     const VALID: &[&str] = &[
         "role",
@@ -1212,6 +1522,16 @@ mod tests {
             args: None,
             user_agent: None,
             provider: None,
+            no_auto_restart: false,
+            idle_timeout: None,
+            relaunch: false,
+            no_lock: false,
+            recover: false,
+            lock_timeout: None,
+            retry: None,
+            retry_delay: None,
+            retry_unsafe: false,
+            allow_stale_refs: false,
         }
     }
 
@@ -1586,6 +1906,35 @@ mod tests {
         assert_eq!(cmd["path"], "./button.png");
     }
 
+    #[test]
+    fn test_screenshot_annotate() {
+        let cmd = parse_command(&args("screenshot --annotate page.png"), &default_flags()).unwrap();
+        assert_eq!(cmd["annotate"], true);
+        assert_eq!(cmd["path"], "page.png");
+        assert!(cmd["selector"].is_null());
+        let cmd = parse_command(&args("screenshot page.png"), &default_flags()).unwrap();
+        assert!(cmd.get("annotate").is_none());
+    }
+
+    #[test]
+    fn test_screenshot_compare() {
+        let cmd = parse_command(
+            &args("screenshot --compare base.png --threshold 0.2 --diff-out out/diff.png"),
+            &default_flags(),
+        )
+        .unwrap();
+        assert_eq!(cmd["compare"], "base.png");
+        assert_eq!(cmd["threshold"], 0.2);
+        assert_eq!(cmd["diffOut"], "out/diff.png");
+        assert!(cmd["path"].is_null());
+        let cmd = parse_command(&args("screenshot #hero --compare hero.png --update"), &default_flags()).unwrap();
+        assert_eq!(cmd["selector"], "#hero");
+        assert_eq!(cmd["update"], true);
+        assert!(parse_command(&args("screenshot --compare"), &default_flags()).is_err());
+        assert!(parse_command(&args("screenshot --compare a.png --threshold 2"), &default_flags()).is_err());
+        assert!(parse_command(&args("screenshot --update"), &default_flags()).is_err());
+    }
+
     // === Snapshot ===
 
     #[test]
@@ -1615,6 +1964,48 @@ mod tests {
         assert_eq!(cmd["maxDepth"], 3);
     }
 
+    #[test]
+    fn test_snapshot_diff_save() {
+        let cmd = parse_command(&args("snapshot -i --diff --save before.txt"), &default_flags()).unwrap();
+        assert_eq!(cmd["interactive"], true);
+        assert_eq!(cmd["diff"], true);
+        assert_eq!(cmd["save"], "before.txt");
+        assert!(parse_command(&args("snapshot --save"), &default_flags()).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_visible_page() {
+        let cmd = parse_command(&args("snapshot -i --visible"), &default_flags()).unwrap();
+        assert_eq!(cmd["visible"], true);
+        assert_eq!(cmd["interactive"], true);
+        let cmd = parse_command(&args("snapshot --page 3 -c"), &default_flags()).unwrap();
+        assert_eq!(cmd["page"], 3);
+        assert_eq!(cmd["compact"], true);
+        assert!(parse_command(&args("snapshot --page 0"), &default_flags()).is_err());
+        assert!(parse_command(&args("snapshot --page"), &default_flags()).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_budget() {
+        let cmd = parse_command(&args("snapshot -i -d 4 --max-chars 8000"), &default_flags()).unwrap();
+        assert_eq!(cmd["maxChars"], 8000);
+        assert_eq!(cmd["maxDepth"], 4);
+        let cmd = parse_command(&args("snapshot --max-tokens 1000"), &default_flags()).unwrap();
+        assert_eq!(cmd["maxChars"], 4000);
+        let cmd = parse_command(&args("snapshot --max-tokens 1000 --max-chars 3000"), &default_flags()).unwrap();
+        assert_eq!(cmd["maxChars"], 3000);
+        assert!(parse_command(&args("snapshot --max-chars 0"), &default_flags()).is_err());
+        assert!(parse_command(&args("snapshot --max-tokens"), &default_flags()).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_format() {
+        let cmd = parse_command(&args("snapshot -i --format yaml"), &default_flags()).unwrap();
+        assert_eq!(cmd["format"], "yaml");
+        assert!(parse_command(&args("snapshot --format xml"), &default_flags()).is_err());
+        assert!(parse_command(&args("snapshot --diff --format json"), &default_flags()).is_err());
+    }
+
     // === Wait ===
 
     #[test]
@@ -1669,6 +2060,62 @@ mod tests {
         assert_eq!(cmd["selector"], "text=Welcome");
     }
 
+    // === Assert ===
+
+    #[test]
+    fn test_assert_text() {
+        let cmd = parse_command(&args("assert text #status contains Done --timeout 3000"), &default_flags()).unwrap();
+        assert_eq!(cmd["action"], "assert");
+        assert_eq!(cmd["check"], "text");
+        assert_eq!(cmd["selector"], "#status");
+        assert_eq!(cmd["op"], "contains");
+        assert_eq!(cmd["expected"], "Done");
+        assert_eq!(cmd["timeout"], 3000);
+    }
+
+    #[test]
+    fn test_assert_url_and_title_defaults() {
+        let cmd = parse_command(&args("assert url **/dashboard"), &default_flags()).unwrap();
+        assert_eq!(cmd["op"], "glob");
+        assert_eq!(cmd["expected"], "**/dashboard");
+        assert_eq!(cmd["timeout"], 0);
+        let cmd = parse_command(&args("assert title matches ^Home"), &default_flags()).unwrap();
+        assert_eq!(cmd["op"], "matches");
+    }
+
+    #[test]
+    fn test_assert_count_ops() {
+        let cmd = parse_command(&args("assert count li ge 3"), &default_flags()).unwrap();
+        assert_eq!(cmd["op"], ">=");
+        assert_eq!(cmd["expected"], 3);
+        let result = parse_command(&args("assert count li about 3"), &default_flags());
+        assert!(matches!(result, Err(ParseError::InvalidValue { .. })));
+    }
+
+    #[test]
+    fn test_assert_invalid() {
+        let result = parse_command(&args("assert text #a matches ("), &default_flags());
+        assert!(matches!(result, Err(ParseError::InvalidValue { .. })));
+        let result = parse_command(&args("assert visible"), &default_flags());
+        assert!(matches!(result, Err(ParseError::MissingArguments { .. })));
+        let result = parse_command(&args("assert focused #a"), &default_flags());
+        assert!(matches!(result, Err(ParseError::UnknownSubcommand { .. })));
+    }
+
+    // === Replay ===
+
+    #[test]
+    fn test_replay() {
+        let cmd = parse_command(&args("replay agent1 --from 5"), &default_flags()).unwrap();
+        assert_eq!(cmd["action"], "replay");
+        assert!(cmd["journal"].as_str().unwrap().ends_with("agent1.history.jsonl"));
+        assert_eq!(cmd["from"], 5);
+        let result = parse_command(&args("replay agent1 --from 0"), &default_flags());
+        assert!(matches!(result, Err(ParseError::InvalidValue { .. })));
+        let result = parse_command(&args("replay"), &default_flags());
+        assert!(matches!(result, Err(ParseError::MissingArguments { .. })));
+    }
+
     // === Unknown command ===
 
     // === Record Tests ===
@@ -1797,6 +2244,14 @@ mod tests {
         assert!(err.format().contains("get text"));
     }
 
+    #[test]
+    fn test_get_locator() {
+        let cmd = parse_command(&args("get locator @e12"), &default_flags()).unwrap();
+        assert_eq!(cmd["action"], "locator");
+        assert_eq!(cmd["selector"], "@e12");
+        assert!(parse_command(&args("get locator"), &default_flags()).is_err());
+    }
+
     // === Protocol alignment tests ===
 
     #[test]
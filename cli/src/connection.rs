@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Value};
 use std::env;
 use std::fs;
 use std::io::{BufRead, BufReader, Read, Write};
@@ -7,11 +7,21 @@ use std::net::TcpStream;
 use std::path::PathBuf;
 use std::process::{Command, Stdio};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 #[cfg(unix)]
 use std::os::unix::net::UnixStream;
 
+#[cfg(windows)]
+use windows_sys::Win32::Foundation::CloseHandle;
+#[cfg(windows)]
+use windows_sys::Win32::System::Threading::{OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION};
+
+use crate::debug;
+use crate::error::{CliError, ErrorKind};
+use crate::interrupt;
+use crate::logs;
+
 #[derive(Serialize)]
 #[allow(dead_code)]
 pub struct Request {
@@ -26,6 +36,9 @@ pub struct Response {
     pub success: bool,
     pub data: Option<Value>,
     pub error: Option<String>,
+    /// Set by the daemon when sending the same command again may succeed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub retryable: bool,
 }
 
 #[allow(dead_code)]
@@ -116,6 +129,21 @@ fn get_pid_path(session: &str) -> PathBuf {
     get_socket_dir().join(format!("{}.pid", session))
 }
 
+fn get_idle_path(session: &str) -> PathBuf {
+    get_socket_dir().join(format!("{}.idle", session))
+}
+
+/// Time left before the session's daemon shuts down for inactivity.
+/// The daemon writes its timeout (ms) to `<session>.idle` and rewrites it after every
+/// command, so the file's mtime marks the last activity. None if no timeout is set.
+pub fn idle_remaining(session: &str) -> Option<Duration> {
+    let path = get_idle_path(session);
+    let timeout_ms: u64 = fs::read_to_string(&path).ok()?.trim().parse().ok()?;
+    let last_activity = fs::metadata(&path).ok()?.modified().ok()?;
+    let elapsed = last_activity.elapsed().unwrap_or_default();
+    Some(Duration::from_millis(timeout_ms).saturating_sub(elapsed))
+}
+
 #[cfg(windows)]
 fn get_port_path(session: &str) -> PathBuf {
     get_socket_dir().join(format!("{}.port", session))
@@ -132,6 +160,86 @@ fn get_port_for_session(session: &str) -> u16 {
     49152 + ((hash.unsigned_abs() as u32 % 16383) as u16)
 }
 
+/// Returns true if a process with this pid exists
+pub fn is_process_alive(pid: u32) -> bool {
+    #[cfg(unix)]
+    unsafe {
+        libc::kill(pid as i32, 0) == 0
+    }
+    #[cfg(windows)]
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle != 0 {
+            CloseHandle(handle);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Whether `pid` is still an agent-browser daemon, rather than an unrelated
+/// process that was given the same pid after the daemon exited. If the process
+/// list cannot be read, a live process is assumed to be the daemon.
+pub fn is_daemon_process(pid: u32) -> bool {
+    if !is_process_alive(pid) {
+        return false;
+    }
+    #[cfg(unix)]
+    let (output, marker) = (
+        Command::new("ps").args(["-o", "command=", "-p", &pid.to_string()]).stderr(Stdio::null()).output(),
+        "daemon.js",
+    );
+    #[cfg(windows)]
+    let (output, marker) = (
+        Command::new("tasklist")
+            .args(["/FI", &format!("PID eq {}", pid), "/FO", "CSV", "/NH"])
+            .stderr(Stdio::null())
+            .output(),
+        "node",
+    );
+    match output {
+        Ok(out) if out.status.success() => String::from_utf8_lossy(&out.stdout).contains(marker),
+        _ => true,
+    }
+}
+
+/// Where the session's daemon listens: the Unix socket path, or `127.0.0.1:<port>` on Windows
+pub fn daemon_endpoint(session: &str) -> String {
+    #[cfg(unix)]
+    {
+        get_socket_path(session).display().to_string()
+    }
+    #[cfg(windows)]
+    {
+        format!("127.0.0.1:{}", get_port_for_session(session))
+    }
+}
+
+/// Pid recorded in the session's pid file, if any
+pub fn read_daemon_pid(session: &str) -> Option<u32> {
+    fs::read_to_string(get_pid_path(session)).ok()?.trim().parse().ok()
+}
+
+/// Ask a daemon to exit (SIGTERM), or kill it outright when `force` is set.
+/// The daemon is started as a session leader, so its whole process group
+/// (including the browser) is signalled.
+pub fn terminate_process(pid: u32, force: bool) {
+    #[cfg(unix)]
+    unsafe {
+        libc::kill(-(pid as i32), if force { libc::SIGKILL } else { libc::SIGTERM });
+    }
+    #[cfg(windows)]
+    {
+        let mut cmd = Command::new("taskkill");
+        cmd.args(["/PID", &pid.to_string(), "/T"]);
+        if force {
+            cmd.arg("/F");
+        }
+        let _ = cmd.stdout(Stdio::null()).stderr(Stdio::null()).status();
+    }
+}
+
 #[cfg(unix)]
 fn is_daemon_running(session: &str) -> bool {
     let pid_path = get_pid_path(session);
@@ -179,10 +287,100 @@ fn daemon_ready(session: &str) -> bool {
     }
 }
 
+/// Returns true if the session has a live daemon accepting connections
+pub fn is_daemon_alive(session: &str) -> bool {
+    is_daemon_running(session) && daemon_ready(session)
+}
+
+/// Locations searched for `daemon.js`, in priority order.
+/// AGENT_BROWSER_HOME comes first, then paths relative to the CLI binary, then the working directory.
+pub fn daemon_path_candidates() -> Result<Vec<PathBuf>, String> {
+    let exe_path = env::current_exe().map_err(|e| e.to_string())?;
+    let exe_dir = exe_path.parent().unwrap();
+
+    let mut daemon_paths = vec![
+        exe_dir.join("daemon.js"),
+        exe_dir.join("../dist/daemon.js"),
+        PathBuf::from("dist/daemon.js"),
+    ];
+
+    // Check AGENT_BROWSER_HOME environment variable
+    if let Ok(home) = env::var("AGENT_BROWSER_HOME") {
+        let home_path = PathBuf::from(&home);
+        daemon_paths.insert(0, home_path.join("dist/daemon.js"));
+        daemon_paths.insert(1, home_path.join("daemon.js"));
+    }
+
+    Ok(daemon_paths)
+}
+
 /// Result of ensure_daemon indicating whether a new daemon was started
 pub struct DaemonResult {
     /// True if we connected to an existing daemon, false if we started a new one
     pub already_running: bool,
+    /// Version of a running daemon that does not match the CLI. The caller
+    /// restarts it (carrying over tabs and cookies) unless --no-auto-restart is set.
+    pub version_mismatch: Option<String>,
+}
+
+/// Version information reported by a running daemon
+pub struct VersionInfo {
+    /// Daemon package version, None for daemons that predate the version handshake
+    pub version: Option<String>,
+    /// Browser version, None if the browser has not been launched yet
+    pub browser_version: Option<String>,
+}
+
+/// Ask the session's daemon for its version without starting a daemon or a browser.
+/// Returns Ok(None) if no daemon is running for the session.
+pub fn query_daemon_version(session: &str) -> Result<Option<VersionInfo>, String> {
+    if !(is_daemon_running(session) && daemon_ready(session)) {
+        return Ok(None);
+    }
+    let resp = send_command_with_timeout(
+        json!({ "id": crate::commands::gen_id(), "action": "version" }),
+        session,
+        Duration::from_secs(5),
+    )?;
+    let data = resp.data.unwrap_or_default();
+    Ok(Some(VersionInfo {
+        // Older daemons reject the unknown "version" action with a validation error
+        version: data.get("version").and_then(|v| v.as_str()).map(String::from),
+        browser_version: data.get("browserVersion").and_then(|v| v.as_str()).map(String::from),
+    }))
+}
+
+/// Ask the session's daemon for its pid, uptime, launch options and current page
+/// without starting a browser. Returns Ok(None) if no daemon is running for the session.
+pub fn query_daemon_status(session: &str) -> Result<Option<Value>, String> {
+    if !(is_daemon_running(session) && daemon_ready(session)) {
+        return Ok(None);
+    }
+    let resp = send_command_with_timeout(
+        json!({ "id": crate::commands::gen_id(), "action": "status" }),
+        session,
+        Duration::from_secs(5),
+    )?;
+    if !resp.success {
+        return Err(resp.error.unwrap_or_else(|| "Status query failed".to_string()));
+    }
+    Ok(resp.data)
+}
+
+/// Ask a daemon to close and wait for it to exit
+pub fn stop_daemon(session: &str) -> Result<(), String> {
+    let _ = send_command_with_timeout(
+        json!({ "id": crate::commands::gen_id(), "action": "close" }),
+        session,
+        Duration::from_secs(10),
+    );
+    for _ in 0..50 {
+        if !is_daemon_running(session) && !daemon_ready(session) {
+            return Ok(());
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    Err(format!("Daemon for session '{}' did not shut down", session))
 }
 
 pub fn ensure_daemon(
@@ -194,12 +392,34 @@ pub fn ensure_daemon(
     user_agent: Option<&str>,
     proxy: Option<&str>,
     proxy_bypass: Option<&str>,
+    idle_timeout_ms: Option<u64>,
 ) -> Result<DaemonResult, String> {
     if is_daemon_running(session) && daemon_ready(session) {
-        return Ok(DaemonResult {
-            already_running: true,
-        });
+        logs::rotate_log(session);
+        let cli_version = env!("CARGO_PKG_VERSION");
+        let daemon_version = match query_daemon_version(session) {
+            Ok(Some(info)) => Some(info.version.unwrap_or_else(|| "unknown".to_string())),
+            // Could not complete the handshake (e.g. daemon busy); keep using it
+            Ok(None) | Err(_) => None,
+        };
+        match daemon_version {
+            Some(v) if v != cli_version => {
+                debug::log(&format!("running daemon version {} does not match CLI {}", v, cli_version));
+                return Ok(DaemonResult {
+                    already_running: true,
+                    version_mismatch: Some(v),
+                });
+            }
+            _ => {
+                debug::log(&format!("reusing running daemon for session '{}'", session));
+                return Ok(DaemonResult {
+                    already_running: true,
+                    version_mismatch: None,
+                });
+            }
+        }
     }
+    debug::log(&format!("no running daemon for session '{}', starting one", session));
 
     // Ensure socket directory exists
     let socket_dir = get_socket_dir();
@@ -207,30 +427,70 @@ pub fn ensure_daemon(
         fs::create_dir_all(&socket_dir).map_err(|e| format!("Failed to create socket directory: {}", e))?;
     }
 
-    let exe_path = env::current_exe().map_err(|e| e.to_string())?;
-    let exe_dir = exe_path.parent().unwrap();
-
-    let mut daemon_paths = vec![
-        exe_dir.join("daemon.js"),
-        exe_dir.join("../dist/daemon.js"),
-        PathBuf::from("dist/daemon.js"),
-    ];
-
-    // Check AGENT_BROWSER_HOME environment variable
-    if let Ok(home) = env::var("AGENT_BROWSER_HOME") {
-        let home_path = PathBuf::from(&home);
-        daemon_paths.insert(0, home_path.join("dist/daemon.js"));
-        daemon_paths.insert(1, home_path.join("daemon.js"));
-    }
+    let daemon_paths = daemon_path_candidates()?;
 
     let daemon_path = daemon_paths
         .iter()
-        .find(|p| p.exists())
+        .find(|p| {
+            let found = p.exists();
+            debug::log(&format!(
+                "daemon candidate {} ({})",
+                p.display(),
+                if found { "found" } else { "missing" }
+            ));
+            found
+        })
         .ok_or("Daemon not found. Set AGENT_BROWSER_HOME environment variable or run from project directory.")?;
 
+    if debug::is_enabled() {
+        let mut opts = Vec::new();
+        if headed {
+            opts.push("headed".to_string());
+        }
+        if let Some(path) = executable_path {
+            opts.push(format!("executable={}", path));
+        }
+        if !extensions.is_empty() {
+            opts.push(format!("extensions={}", extensions.join(",")));
+        }
+        if let Some(a) = args {
+            opts.push(format!("args={}", a));
+        }
+        if let Some(ua) = user_agent {
+            opts.push(format!("user-agent={}", ua));
+        }
+        if let Some(p) = proxy {
+            opts.push(format!("proxy={}", debug::redact_url(p)));
+        }
+        if let Some(pb) = proxy_bypass {
+            opts.push(format!("proxy-bypass={}", pb));
+        }
+        if let Some(ms) = idle_timeout_ms {
+            opts.push(format!("idle-timeout={}ms", ms));
+        }
+        debug::log(&format!(
+            "spawning: node {}{}",
+            daemon_path.display(),
+            if opts.is_empty() { String::new() } else { format!(" [{}]", opts.join(" ")) }
+        ));
+    }
+
+    // Daemon stdout/stderr go to <socket_dir>/<session>.log so startup failures can be diagnosed
+    let log_path = logs::get_log_path(session);
+    let (log_out, log_err) = match logs::open_log_for_daemon(session)
+        .and_then(|f| f.try_clone().map(|f2| (f, f2)))
+    {
+        Ok((out, err)) => (Stdio::from(out), Stdio::from(err)),
+        Err(e) => {
+            debug::log(&format!("could not open {}: {}", log_path.display(), e));
+            (Stdio::null(), Stdio::null())
+        }
+    };
+    debug::log(&format!("daemon log: {}", log_path.display()));
+
     // Spawn daemon as a fully detached background process
     #[cfg(unix)]
-    {
+    let mut child = {
         use std::os::unix::process::CommandExt;
 
         let mut cmd = Command::new("node");
@@ -266,26 +526,31 @@ pub fn ensure_daemon(
             cmd.env("AGENT_BROWSER_PROXY_BYPASS", pb);
         }
 
+        // Always set explicitly so a raw duration string inherited from our env is not misread
+        cmd.env("AGENT_BROWSER_IDLE_TIMEOUT", idle_timeout_ms.unwrap_or(0).to_string());
+
         // Create new process group and session to fully detach
         unsafe {
             cmd.pre_exec(|| {
                 // Create new session (detach from terminal)
                 libc::setsid();
+                // SIGINT is blocked in the CLI so Ctrl-C can be forwarded; don't pass that on
+                interrupt::unblock_in_child();
                 Ok(())
             });
         }
 
         cmd.stdin(Stdio::null())
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
+            .stdout(log_out)
+            .stderr(log_err)
             .spawn()
-            .map_err(|e| format!("Failed to start daemon: {}", e))?;
-    }
+            .map_err(|e| format!("Failed to start daemon: {}", e))?
+    };
 
     #[cfg(windows)]
-    {
+    let mut child = {
         use std::os::windows::process::CommandExt;
-        
+
         // On Windows, call node directly. Command::new handles PATH resolution (node.exe or node.cmd)
         // and automatically quotes arguments containing spaces.
         let mut cmd = Command::new("node");
@@ -321,67 +586,147 @@ pub fn ensure_daemon(
             cmd.env("AGENT_BROWSER_PROXY_BYPASS", pb);
         }
 
+        // Always set explicitly so a raw duration string inherited from our env is not misread
+        cmd.env("AGENT_BROWSER_IDLE_TIMEOUT", idle_timeout_ms.unwrap_or(0).to_string());
+
         // CREATE_NEW_PROCESS_GROUP | DETACHED_PROCESS
         const CREATE_NEW_PROCESS_GROUP: u32 = 0x00000200;
         const DETACHED_PROCESS: u32 = 0x00000008;
 
         cmd.creation_flags(CREATE_NEW_PROCESS_GROUP | DETACHED_PROCESS)
             .stdin(Stdio::null())
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
+            .stdout(log_out)
+            .stderr(log_err)
             .spawn()
-            .map_err(|e| format!("Failed to start daemon: {}", e))?;
-    }
+            .map_err(|e| format!("Failed to start daemon: {}", e))?
+    };
 
+    let started = Instant::now();
+    let mut exit_status = None;
     for _ in 0..50 {
         if daemon_ready(session) {
+            debug::log(&format!("daemon ready after {}ms", started.elapsed().as_millis()));
             return Ok(DaemonResult {
                 already_running: false,
+                version_mismatch: None,
             });
         }
+        // Stop waiting early if the daemon process already exited
+        if let Ok(Some(status)) = child.try_wait() {
+            exit_status = Some(status);
+            break;
+        }
         thread::sleep(Duration::from_millis(100));
     }
 
-    Err("Daemon failed to start".to_string())
+    debug::log(&format!("daemon not ready after {}ms", started.elapsed().as_millis()));
+    let mut msg = match exit_status {
+        Some(status) => format!("Daemon failed to start (exited with {})", status),
+        None => "Daemon failed to start".to_string(),
+    };
+    let tail = logs::tail_lines(&log_path, logs::DEFAULT_TAIL_LINES);
+    if !tail.is_empty() {
+        msg.push_str(&format!("\nLast lines of {}:\n{}", log_path.display(), tail.join("\n")));
+    }
+    Err(msg)
 }
 
-fn connect(session: &str) -> Result<Connection, String> {
+fn connect(session: &str) -> Result<Connection, CliError> {
     #[cfg(unix)]
     {
         let socket_path = get_socket_path(session);
+        debug::log(&format!("connecting to {}", socket_path.display()));
         UnixStream::connect(&socket_path)
             .map(Connection::Unix)
-            .map_err(|e| format!("Failed to connect: {}", e))
+            .map_err(|e| CliError::new(ErrorKind::ConnectionFailed, format!("Failed to connect: {}", e)))
     }
     #[cfg(windows)]
     {
         let port = get_port_for_session(session);
+        debug::log(&format!("connecting to 127.0.0.1:{}", port));
         TcpStream::connect(format!("127.0.0.1:{}", port))
             .map(Connection::Tcp)
-            .map_err(|e| format!("Failed to connect: {}", e))
+            .map_err(|e| CliError::new(ErrorKind::ConnectionFailed, format!("Failed to connect: {}", e)))
+    }
+}
+
+pub fn send_command(cmd: Value, session: &str) -> Result<Response, CliError> {
+    // Let Ctrl-C cancel this request in the daemon
+    if let Some(id) = cmd.get("id").and_then(|v| v.as_str()) {
+        interrupt::set_in_flight(session, id);
+    }
+    let result = send_command_with_timeout(cmd, session, Duration::from_secs(30));
+    interrupt::park_if_interrupted();
+    interrupt::clear_in_flight();
+    result
+}
+
+/// Ask the daemon to abandon a request that is still running, on a separate connection
+pub fn cancel_request(session: &str, id: &str) -> Result<(), String> {
+    let resp = send_command_with_timeout(
+        json!({ "id": crate::commands::gen_id(), "action": "cancel", "targetId": id }),
+        session,
+        Duration::from_secs(2),
+    )?;
+    if resp.success {
+        Ok(())
+    } else {
+        Err(resp.error.unwrap_or_else(|| "Cancel failed".to_string()))
     }
 }
 
-pub fn send_command(cmd: Value, session: &str) -> Result<Response, String> {
+fn send_command_with_timeout(cmd: Value, session: &str, timeout: Duration) -> Result<Response, CliError> {
     let mut stream = connect(session)?;
 
-    stream.set_read_timeout(Some(Duration::from_secs(30))).ok();
+    stream.set_read_timeout(Some(timeout)).ok();
     stream.set_write_timeout(Some(Duration::from_secs(5))).ok();
 
-    let mut json_str = serde_json::to_string(&cmd).map_err(|e| e.to_string())?;
+    let mut json_str = serde_json::to_string(&cmd)
+        .map_err(|e| CliError::new(ErrorKind::ParseError, e.to_string()))?;
     json_str.push('\n');
 
+    if debug::is_enabled() {
+        debug::log(&format!("-> {}", debug::redact(&cmd)));
+    }
+    let started = Instant::now();
+
     stream
         .write_all(json_str.as_bytes())
-        .map_err(|e| format!("Failed to send: {}", e))?;
+        .map_err(|e| CliError::new(ErrorKind::ConnectionFailed, format!("Failed to send: {}", e)))?;
 
     let mut reader = BufReader::new(stream);
     let mut response_line = String::new();
-    reader
-        .read_line(&mut response_line)
-        .map_err(|e| format!("Failed to read: {}", e))?;
+    match reader.read_line(&mut response_line) {
+        Ok(0) => {
+            return Err(CliError::new(
+                ErrorKind::ConnectionFailed,
+                "Daemon closed the connection without responding",
+            )
+            .after_delivery())
+        }
+        Ok(_) => {}
+        Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {
+            return Err(CliError::new(
+                ErrorKind::Timeout,
+                format!("Timed out after {}s waiting for the daemon to respond", timeout.as_secs()),
+            )
+            .after_delivery())
+        }
+        Err(e) => {
+            return Err(CliError::new(ErrorKind::ConnectionFailed, format!("Failed to read: {}", e)).after_delivery())
+        }
+    }
+
+    if debug::is_enabled() {
+        let shown = match serde_json::from_str::<Value>(&response_line) {
+            Ok(v) => debug::redact(&v).to_string(),
+            Err(_) => response_line.trim_end().to_string(),
+        };
+        debug::log(&format!("<- {} ({}ms)", shown, started.elapsed().as_millis()));
+    }
 
-    serde_json::from_str(&response_line).map_err(|e| format!("Invalid response: {}", e))
+    serde_json::from_str(&response_line)
+        .map_err(|e| CliError::new(ErrorKind::ConnectionFailed, format!("Invalid response: {}", e)).after_delivery())
 }
 
 #[cfg(test)]
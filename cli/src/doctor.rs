@@ -0,0 +1,302 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{exit, Command, Stdio};
+
+use serde::Serialize;
+use serde_json::json;
+
+use crate::color;
+use crate::connection::{daemon_path_candidates, get_socket_dir};
+use crate::error::{error_envelope, ErrorKind};
+use crate::install::linux_dependencies;
+use crate::session::stale_sessions;
+
+/// Oldest Node.js major version the daemon supports
+const MIN_NODE_MAJOR: u32 = 18;
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fix: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &'static str, message: impl Into<String>) -> Self {
+        Check { name, status: Status::Pass, message: message.into(), fix: None }
+    }
+
+    fn warn(name: &'static str, message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Check { name, status: Status::Warn, message: message.into(), fix: Some(fix.into()) }
+    }
+
+    fn fail(name: &'static str, message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Check { name, status: Status::Fail, message: message.into(), fix: Some(fix.into()) }
+    }
+}
+
+pub fn run_doctor(json_mode: bool) {
+    let mut checks = vec![check_node(), check_daemon_path(), check_browser()];
+    if cfg!(target_os = "linux") {
+        checks.push(check_linux_deps());
+    }
+    checks.push(check_socket_dir());
+    checks.push(check_stale_sessions());
+
+    let ok = checks.iter().all(|c| c.status != Status::Fail);
+
+    if json_mode && ok {
+        println!("{}", json!({ "success": true, "data": { "checks": checks } }));
+    } else if json_mode {
+        let mut envelope = error_envelope(ErrorKind::ActionFailed, "Some checks failed", Some("doctor"));
+        envelope["checks"] = json!(checks);
+        println!("{}", envelope);
+    } else {
+        for check in &checks {
+            let indicator = match check.status {
+                Status::Pass => color::success_indicator(),
+                Status::Warn => color::warning_indicator(),
+                Status::Fail => color::error_indicator(),
+            };
+            println!("{} {}: {}", indicator, color::bold(check.name), check.message);
+            if let Some(ref fix) = check.fix {
+                for line in fix.lines() {
+                    println!("    {}", color::dim(line));
+                }
+            }
+        }
+        println!();
+        if ok {
+            println!("{} No blocking problems found", color::success_indicator());
+        } else {
+            println!("{} Some checks failed", color::error_indicator());
+        }
+    }
+
+    if !ok {
+        exit(ErrorKind::ActionFailed.exit_code());
+    }
+}
+
+/// Parse the major version from `node --version` output (e.g. "v20.10.0")
+fn parse_node_major(version: &str) -> Option<u32> {
+    version.trim().trim_start_matches('v').split('.').next()?.parse().ok()
+}
+
+fn check_node() -> Check {
+    let output = Command::new("node").arg("--version").stderr(Stdio::null()).output();
+    match output {
+        Ok(out) if out.status.success() => {
+            let version = String::from_utf8_lossy(&out.stdout).trim().to_string();
+            match parse_node_major(&version) {
+                Some(major) if major >= MIN_NODE_MAJOR => Check::pass("node", version),
+                Some(_) => Check::fail(
+                    "node",
+                    format!("{} is too old (need v{} or newer)", version, MIN_NODE_MAJOR),
+                    "Upgrade Node.js: https://nodejs.org",
+                ),
+                None => Check::warn(
+                    "node",
+                    format!("could not parse version '{}'", version),
+                    "Check that 'node' on PATH is Node.js",
+                ),
+            }
+        }
+        _ => Check::fail(
+            "node",
+            "node not found on PATH",
+            "Install Node.js (https://nodejs.org) and make sure 'node' is on PATH",
+        ),
+    }
+}
+
+fn check_daemon_path() -> Check {
+    let candidates = match daemon_path_candidates() {
+        Ok(c) => c,
+        Err(e) => return Check::fail("daemon", e, "Reinstall agent-browser"),
+    };
+    match candidates.iter().find(|p| p.exists()) {
+        Some(path) => Check::pass("daemon", path.display().to_string()),
+        None => {
+            let tried: Vec<String> = candidates.iter().map(|p| format!("  {}", p.display())).collect();
+            let home_hint = if env::var("AGENT_BROWSER_HOME").is_ok() {
+                "AGENT_BROWSER_HOME is set but contains no daemon.js (run 'npm run build' there)"
+            } else {
+                "Set AGENT_BROWSER_HOME to the agent-browser package directory"
+            };
+            Check::fail(
+                "daemon",
+                "daemon.js not found",
+                format!("{}\nTried:\n{}", home_hint, tried.join("\n")),
+            )
+        }
+    }
+}
+
+/// Directory where Playwright stores downloaded browsers
+fn playwright_cache_dir() -> Option<PathBuf> {
+    if let Ok(path) = env::var("PLAYWRIGHT_BROWSERS_PATH") {
+        if !path.is_empty() && path != "0" {
+            return Some(PathBuf::from(path));
+        }
+    }
+    dirs::cache_dir().map(|d| d.join("ms-playwright"))
+}
+
+fn check_browser() -> Check {
+    if let Ok(path) = env::var("AGENT_BROWSER_EXECUTABLE_PATH") {
+        return if PathBuf::from(&path).exists() {
+            Check::pass("browser", format!("using AGENT_BROWSER_EXECUTABLE_PATH {}", path))
+        } else {
+            Check::fail(
+                "browser",
+                format!("AGENT_BROWSER_EXECUTABLE_PATH {} does not exist", path),
+                "Point AGENT_BROWSER_EXECUTABLE_PATH at a Chromium/Chrome binary or unset it",
+            )
+        };
+    }
+
+    let Some(cache) = playwright_cache_dir() else {
+        return Check::warn(
+            "browser",
+            "could not determine Playwright browser cache directory",
+            "Run: agent-browser install",
+        );
+    };
+    let chromium: Vec<String> = fs::read_dir(&cache)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .filter(|name| name.starts_with("chromium"))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    if chromium.is_empty() {
+        Check::fail(
+            "browser",
+            format!("no Chromium found in {}", cache.display()),
+            "Run: agent-browser install",
+        )
+    } else {
+        Check::pass("browser", format!("{} ({})", cache.display(), chromium.join(", ")))
+    }
+}
+
+fn package_installed(pkg_mgr: &str, pkg: &str) -> bool {
+    let (cmd, args): (&str, &[&str]) = match pkg_mgr {
+        "apt-get" => ("dpkg", &["-s"]),
+        _ => ("rpm", &["-q"]),
+    };
+    Command::new(cmd)
+        .args(args)
+        .arg(pkg)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+fn check_linux_deps() -> Check {
+    let Some((pkg_mgr, deps)) = linux_dependencies() else {
+        return Check::warn(
+            "system deps",
+            "no supported package manager found (apt-get, dnf, or yum); cannot verify",
+            "See: npx playwright install-deps chromium",
+        );
+    };
+    let missing: Vec<&str> = deps.into_iter().filter(|pkg| !package_installed(pkg_mgr, pkg)).collect();
+    if missing.is_empty() {
+        Check::pass("system deps", format!("all Chromium libraries installed ({})", pkg_mgr))
+    } else {
+        Check::fail(
+            "system deps",
+            format!("{} missing: {}", missing.len(), missing.join(" ")),
+            "Run: agent-browser install --with-deps",
+        )
+    }
+}
+
+fn check_socket_dir() -> Check {
+    let dir = get_socket_dir();
+    if !dir.exists() {
+        return Check::pass(
+            "socket dir",
+            format!("{} (will be created on first use)", dir.display()),
+        );
+    }
+    let probe = dir.join(format!(".doctor-{}", std::process::id()));
+    if let Err(e) = fs::write(&probe, b"") {
+        return Check::fail(
+            "socket dir",
+            format!("{} is not writable: {}", dir.display(), e),
+            "Fix permissions or set AGENT_BROWSER_SOCKET_DIR to a writable directory",
+        );
+    }
+    let _ = fs::remove_file(&probe);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Ok(meta) = fs::metadata(&dir) {
+            if meta.permissions().mode() & 0o002 != 0 {
+                return Check::warn(
+                    "socket dir",
+                    format!("{} is world-writable", dir.display()),
+                    format!("Run: chmod 700 {}", dir.display()),
+                );
+            }
+        }
+    }
+
+    Check::pass("socket dir", dir.display().to_string())
+}
+
+fn check_stale_sessions() -> Check {
+    let stale = stale_sessions();
+    if stale.is_empty() {
+        Check::pass("sessions", "no stale session files")
+    } else {
+        Check::warn(
+            "sessions",
+            format!("stale files for dead session(s): {}", stale.join(", ")),
+            "Run: agent-browser session prune",
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_node_major() {
+        assert_eq!(parse_node_major("v20.10.0\n"), Some(20));
+        assert_eq!(parse_node_major("v18.0.0"), Some(18));
+        assert_eq!(parse_node_major("garbage"), None);
+    }
+
+    #[test]
+    fn test_check_serializes_status_lowercase() {
+        let check = Check::warn("node", "old", "upgrade");
+        let v = serde_json::to_value(&check).unwrap();
+        assert_eq!(v["status"], "warn");
+        assert_eq!(v["fix"], "upgrade");
+
+        let v = serde_json::to_value(Check::pass("node", "v20")).unwrap();
+        assert!(v.get("fix").is_none());
+    }
+}
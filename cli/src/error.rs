@@ -0,0 +1,152 @@
+//! Error reporting shared by every failure path.
+//!
+//! With `--json`, failures are printed as a serde-serialized envelope
+//! `{"success":false,"error":...,"type":...,"command":...}` so messages that
+//! contain quotes, backslashes or newlines still produce valid JSON.
+
+use std::fmt;
+use std::process::exit;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::color;
+
+/// Machine-readable failure class, serialized as the envelope's `type`
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// Invalid command line: unknown command, missing arguments, bad flag value
+    ParseError,
+    /// The daemon could not be started (or restarted)
+    DaemonStartFailed,
+    /// The daemon could not be reached or the connection broke
+    ConnectionFailed,
+    /// The daemon, or another command holding the session lock, did not answer in time
+    Timeout,
+    /// The daemon (or a local command such as `session kill`) reported a failure
+    ActionFailed,
+    /// The session's daemon or browser died and was not recovered
+    SessionCrashed,
+    /// An assertion command's condition (or a `screenshot --compare`) did not hold
+    AssertionFailed,
+    /// A ref was used after the page navigated away from the snapshot it came from
+    StaleRef,
+}
+
+impl ErrorKind {
+    /// Process exit code for this failure class. These are part of the CLI's
+    /// interface (scripts retry on 4 and 5), so never renumber them.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::ActionFailed => 1,
+            ErrorKind::ParseError => 2,
+            ErrorKind::DaemonStartFailed => 3,
+            ErrorKind::ConnectionFailed => 4,
+            ErrorKind::Timeout => 5,
+            ErrorKind::SessionCrashed => 6,
+            ErrorKind::AssertionFailed => 7,
+            ErrorKind::StaleRef => 8,
+        }
+    }
+}
+
+/// An error with its failure class, e.g. from talking to the daemon
+#[derive(Debug)]
+pub struct CliError {
+    pub kind: ErrorKind,
+    pub message: String,
+    /// The request reached the daemon before this happened, so it may have run
+    pub delivered: bool,
+}
+
+impl CliError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        CliError { kind, message: message.into(), delivered: false }
+    }
+
+    /// Mark an error that happened after the request was written to the daemon
+    pub fn after_delivery(mut self) -> Self {
+        self.delivered = true;
+        self
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<CliError> for String {
+    fn from(e: CliError) -> String {
+        e.message
+    }
+}
+
+/// The `--json` failure envelope
+pub fn error_envelope(kind: ErrorKind, message: &str, command: Option<&str>) -> Value {
+    let mut envelope = json!({ "success": false, "error": message, "type": kind });
+    if let Some(cmd) = command {
+        envelope["command"] = json!(cmd);
+    }
+    envelope
+}
+
+/// Print an error envelope (with --json) or a red message on stderr
+pub fn print_error(kind: ErrorKind, message: &str, command: Option<&str>, json_mode: bool) {
+    if json_mode {
+        println!("{}", error_envelope(kind, message, command));
+    } else if kind == ErrorKind::ParseError {
+        eprintln!("{}", color::red(message));
+    } else {
+        eprintln!("{} {}", color::error_indicator(), message);
+    }
+}
+
+/// Report an error and exit with the code for its class
+pub fn fail(kind: ErrorKind, message: &str, command: Option<&str>, json_mode: bool) -> ! {
+    print_error(kind, message, command, json_mode);
+    exit(kind.exit_code());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envelope_escapes_message() {
+        let msg = "Element \"#a\\b\" not found\nline 2";
+        let envelope = error_envelope(ErrorKind::ActionFailed, msg, Some("click"));
+        let parsed: Value = serde_json::from_str(&envelope.to_string()).unwrap();
+        assert_eq!(parsed["error"], msg);
+        assert_eq!(parsed["type"], "action_failed");
+        assert_eq!(parsed["command"], "click");
+        assert_eq!(parsed["success"], false);
+    }
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let kinds = [
+            ErrorKind::ParseError,
+            ErrorKind::DaemonStartFailed,
+            ErrorKind::ConnectionFailed,
+            ErrorKind::Timeout,
+            ErrorKind::ActionFailed,
+            ErrorKind::SessionCrashed,
+            ErrorKind::AssertionFailed,
+            ErrorKind::StaleRef,
+        ];
+        let codes: std::collections::HashSet<i32> = kinds.iter().map(|k| k.exit_code()).collect();
+        assert_eq!(codes.len(), kinds.len());
+        assert!(!codes.contains(&0));
+        assert!(!codes.contains(&crate::interrupt::EXIT_INTERRUPTED));
+    }
+
+    #[test]
+    fn test_envelope_without_command() {
+        let envelope = error_envelope(ErrorKind::DaemonStartFailed, "no daemon", None);
+        assert_eq!(envelope["type"], "daemon_start_failed");
+        assert!(envelope.get("command").is_none());
+    }
+}
@@ -0,0 +1,790 @@
+//! `export playwright`: turn a script (see script.rs) into a Playwright test.
+//!
+//! Each line is parsed like the CLI would parse it and the resulting command is
+//! translated to the equivalent Playwright call. `@eN` refs only mean something
+//! to the session that took the snapshot, so they are resolved against the live
+//! session when exporting and written as `getByRole` locators. Only the refs
+//! used on the page of the script's last snapshot can be resolved that way;
+//! refs from before a later snapshot or navigation are left as TODOs.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::assert::glob_to_regex;
+use crate::color;
+use crate::commands::{gen_id, parse_command};
+use crate::connection::{is_daemon_alive, send_command};
+use crate::error::{fail, ErrorKind};
+use crate::flags::{clean_args, parse_flags};
+use crate::refs::parse_ref;
+use crate::script::{load_script, Node, Step};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Lang {
+    Ts,
+    Py,
+}
+
+/// What a ref resolved to in the session's last snapshot
+#[derive(Deserialize)]
+struct RefInfo {
+    role: String,
+    name: Option<String>,
+    nth: Option<u64>,
+}
+
+type Refs = HashMap<String, RefInfo>;
+
+/// A piece of a script word: literal text or a `${name}` reference
+enum Segment {
+    Text(String),
+    Var(String),
+}
+
+/// Split a word on `${name}` the way `script::interpolate` reads it
+fn segments(word: &str) -> Result<Vec<Segment>, String> {
+    let mut out = Vec::new();
+    let mut text = String::new();
+    let mut rest = word;
+    while let Some(pos) = rest.find("${") {
+        if rest[..pos].ends_with('$') {
+            text.push_str(&rest[..pos - 1]);
+            text.push_str("${");
+            rest = &rest[pos + 2..];
+            continue;
+        }
+        text.push_str(&rest[..pos]);
+        let after = &rest[pos + 2..];
+        let close = after.find('}').ok_or_else(|| format!("Unterminated ${{ in '{}'", word))?;
+        if !text.is_empty() {
+            out.push(Segment::Text(std::mem::take(&mut text)));
+        }
+        out.push(Segment::Var(after[..close].to_string()));
+        rest = &after[close + 1..];
+    }
+    text.push_str(rest);
+    if !text.is_empty() || out.is_empty() {
+        out.push(Segment::Text(text));
+    }
+    Ok(out)
+}
+
+/// A string literal: single-quoted for TypeScript, double-quoted for Python
+fn quote(s: &str, lang: Lang) -> String {
+    match lang {
+        Lang::Py => serde_json::to_string(s).unwrap_or_default(),
+        Lang::Ts => {
+            let escaped = s
+                .replace('\\', "\\\\")
+                .replace('\'', "\\'")
+                .replace('\n', "\\n")
+                .replace('\r', "\\r")
+                .replace('\t', "\\t");
+            format!("'{}'", escaped)
+        }
+    }
+}
+
+/// Script commands after which earlier refs no longer apply: a snapshot
+/// renumbers them, and navigating replaces the page they point into
+const PAGE_STEPS: &[&str] = &["snapshot", "open", "goto", "navigate", "back", "forward", "reload"];
+
+fn starts_page(step: &Step) -> bool {
+    step.args.first().is_some_and(|a| PAGE_STEPS.contains(&a.as_str()))
+}
+
+/// Call `visit` with each step in script order and the page it runs on, where
+/// each PAGE_STEPS command starts a new page. Must walk like `Exporter::nodes`.
+fn walk_pages<'n>(nodes: &'n [Node], page: &mut usize, visit: &mut dyn FnMut(&'n Step, usize)) {
+    for node in nodes {
+        match node {
+            Node::Command(step) | Node::SetVar { step, .. } => {
+                if starts_page(step) {
+                    *page += 1;
+                }
+                visit(step, *page);
+            }
+            Node::If { step, then, otherwise, .. } => {
+                visit(step, *page);
+                walk_pages(then, page, visit);
+                walk_pages(otherwise, page, visit);
+            }
+            Node::Repeat { body, .. } => walk_pages(body, page, visit),
+        }
+    }
+}
+
+/// The page whose refs the session's last snapshot holds: the one the script's
+/// last snapshot was taken on, or, if the script takes none, the last page that uses refs
+fn ref_page(nodes: &[Node]) -> Option<usize> {
+    let (mut last_snapshot, mut last_ref_use) = (None, None);
+    walk_pages(nodes, &mut 0, &mut |step, page| {
+        if step.args.first().map(String::as_str) == Some("snapshot") {
+            last_snapshot = Some(page);
+        } else if step.args.iter().any(|a| parse_ref(a).is_some()) {
+            last_ref_use = Some(page);
+        }
+    });
+    last_snapshot.or(last_ref_use)
+}
+
+/// Generates the body of one test, keeping track of what it needs
+struct Exporter<'a> {
+    lang: Lang,
+    refs: &'a Refs,
+    /// Page the current step runs on (see `walk_pages`) and the one `refs` belong to
+    page: usize,
+    ref_page: Option<usize>,
+    /// Script variables declared so far; other `${name}`s read the environment
+    vars: HashSet<String>,
+    lines: Vec<String>,
+    depth: usize,
+    /// Lines that could not be exported, as `line N: text (reason)`
+    skipped: Vec<String>,
+    uses_env: bool,
+    uses_re: bool,
+}
+
+impl<'a> Exporter<'a> {
+    fn new(lang: Lang, refs: &'a Refs) -> Self {
+        Exporter {
+            lang,
+            refs,
+            page: 0,
+            ref_page: None,
+            vars: HashSet::new(),
+            lines: Vec::new(),
+            depth: 1,
+            skipped: Vec::new(),
+            uses_env: false,
+            uses_re: false,
+        }
+    }
+
+    fn ts(&self) -> bool {
+        self.lang == Lang::Ts
+    }
+
+    fn emit(&mut self, line: String) {
+        let indent = if self.ts() { "  " } else { "    " };
+        self.lines.push(format!("{}{}", indent.repeat(self.depth), line));
+    }
+
+    /// An awaited statement (`await x;` in TypeScript, `x` in Python)
+    fn emit_call(&mut self, call: String) {
+        let line = if self.ts() { format!("await {};", call) } else { call };
+        self.emit(line);
+    }
+
+    /// A string expression for a script word: a literal, or a template literal /
+    /// f-string if it interpolates variables
+    fn string(&mut self, word: &str) -> Result<String, String> {
+        let segs = segments(word)?;
+        if let [Segment::Text(text)] = segs.as_slice() {
+            return Ok(quote(text, self.lang));
+        }
+        let mut out = String::new();
+        for seg in segs {
+            match (seg, self.lang) {
+                (Segment::Text(t), Lang::Ts) => {
+                    out.push_str(&t.replace('\\', "\\\\").replace('`', "\\`").replace("${", "\\${"))
+                }
+                (Segment::Text(t), Lang::Py) => {
+                    let quoted = serde_json::to_string(&t).unwrap_or_default();
+                    out.push_str(&quoted[1..quoted.len() - 1].replace('{', "{{").replace('}', "}}"))
+                }
+                (Segment::Var(name), lang) => {
+                    let expr = if self.vars.contains(&name) {
+                        name
+                    } else {
+                        self.uses_env = true;
+                        match lang {
+                            Lang::Ts => format!("process.env.{}", name),
+                            Lang::Py => format!("os.environ['{}']", name),
+                        }
+                    };
+                    match lang {
+                        Lang::Ts => out.push_str(&format!("${{{}}}", expr)),
+                        Lang::Py => out.push_str(&format!("{{{}}}", expr)),
+                    }
+                }
+            }
+        }
+        Ok(match self.lang {
+            Lang::Ts => format!("`{}`", out),
+            Lang::Py => format!("f\"{}\"", out),
+        })
+    }
+
+    /// A regex literal for a pattern that is known at export time
+    fn regex(&mut self, pattern: &str) -> String {
+        match self.lang {
+            Lang::Ts => format!("new RegExp({})", quote(pattern, self.lang)),
+            Lang::Py => {
+                self.uses_re = true;
+                format!("re.compile({})", quote(pattern, self.lang))
+            }
+        }
+    }
+
+    fn locator(&mut self, selector: &Value) -> Result<String, String> {
+        let selector = selector.as_str().ok_or("missing selector")?;
+        let Some(id) = parse_ref(selector) else {
+            let sel = self.string(selector)?;
+            return Ok(format!("page.locator({})", sel));
+        };
+        if self.ref_page != Some(self.page) {
+            return Err(format!(
+                "ref {} is from an earlier snapshot or page than the session's last snapshot",
+                selector
+            ));
+        }
+        let info = self.refs.get(id).ok_or_else(|| {
+            format!("ref {} is not in the session's last snapshot; take a snapshot and export again", selector)
+        })?;
+        let role = quote(&info.role, self.lang);
+        let mut loc = match (&info.name, self.lang) {
+            (Some(name), Lang::Ts) => {
+                format!("page.getByRole({}, {{ name: {}, exact: true }})", role, quote(name, self.lang))
+            }
+            (Some(name), Lang::Py) => {
+                format!("page.get_by_role({}, name={}, exact=True)", role, quote(name, self.lang))
+            }
+            (None, Lang::Ts) => format!("page.getByRole({})", role),
+            (None, Lang::Py) => format!("page.get_by_role({})", role),
+        };
+        if let Some(nth) = info.nth {
+            loc.push_str(&format!(".nth({})", nth));
+        }
+        Ok(loc)
+    }
+
+    /// `ts` or `py` method name for a call that differs only in case
+    fn method(&self, ts: &str, py: &str) -> String {
+        if self.ts() { ts } else { py }.to_string()
+    }
+
+    fn arg(&mut self, cmd: &Value, field: &str) -> Result<String, String> {
+        match &cmd[field] {
+            Value::String(s) => self.string(s),
+            Value::Null => Err(format!("missing {}", field)),
+            v => Ok(v.to_string()),
+        }
+    }
+
+    /// The statement for a parsed command
+    fn command(&mut self, cmd: &Value) -> Result<(), String> {
+        let action = cmd["action"].as_str().unwrap_or_default();
+        let call = match action {
+            "navigate" => format!("page.goto({})", self.arg(cmd, "url")?),
+            "back" => format!("page.{}()", self.method("goBack", "go_back")),
+            "forward" => format!("page.{}()", self.method("goForward", "go_forward")),
+            "reload" => "page.reload()".to_string(),
+            "click" | "dblclick" | "hover" | "focus" | "check" | "uncheck" => {
+                format!("{}.{}()", self.locator(&cmd["selector"])?, action)
+            }
+            "scrollintoview" => format!(
+                "{}.{}()",
+                self.locator(&cmd["selector"])?,
+                self.method("scrollIntoViewIfNeeded", "scroll_into_view_if_needed")
+            ),
+            "fill" => format!("{}.fill({})", self.locator(&cmd["selector"])?, self.arg(cmd, "value")?),
+            "type" => format!(
+                "{}.{}({})",
+                self.locator(&cmd["selector"])?,
+                self.method("pressSequentially", "press_sequentially"),
+                self.arg(cmd, "text")?
+            ),
+            "press" => format!("page.keyboard.press({})", self.arg(cmd, "key")?),
+            "select" => {
+                let values = match &cmd["values"] {
+                    Value::Array(values) => {
+                        let values: Vec<String> = values
+                            .iter()
+                            .map(|v| self.string(v.as_str().unwrap_or_default()))
+                            .collect::<Result<_, _>>()?;
+                        format!("[{}]", values.join(", "))
+                    }
+                    _ => self.arg(cmd, "values")?,
+                };
+                let method = self.method("selectOption", "select_option");
+                format!("{}.{}({})", self.locator(&cmd["selector"])?, method, values)
+            }
+            "wait" if cmd.get("selector").is_some() => {
+                format!("{}.{}()", self.locator(&cmd["selector"])?, self.method("waitFor", "wait_for"))
+            }
+            "wait" => format!("page.{}({})", self.method("waitForTimeout", "wait_for_timeout"), cmd["timeout"]),
+            "waitforurl" => format!("page.{}({})", self.method("waitForURL", "wait_for_url"), self.arg(cmd, "url")?),
+            "waitforloadstate" => format!(
+                "page.{}({})",
+                self.method("waitForLoadState", "wait_for_load_state"),
+                self.arg(cmd, "state")?
+            ),
+            "waitforfunction" => format!(
+                "page.{}({})",
+                self.method("waitForFunction", "wait_for_function"),
+                self.arg(cmd, "expression")?
+            ),
+            "evaluate" => format!("page.evaluate({})", self.arg(cmd, "script")?),
+            "assert" => self.assertion(cmd)?,
+            _ => return Err(format!("'{}' has no Playwright equivalent in export", action)),
+        };
+        self.emit_call(call);
+        Ok(())
+    }
+
+    /// An `expect(...)` call for an `assert` command
+    fn assertion(&mut self, cmd: &Value) -> Result<String, String> {
+        let check = cmd["check"].as_str().unwrap_or_default();
+        let op = cmd["op"].as_str().unwrap_or_default();
+        let ts = self.ts();
+        let subject = match check {
+            "url" | "title" => "page".to_string(),
+            _ => self.locator(&cmd["selector"])?,
+        };
+        let matcher = |ts_name: &str, py_name: &str| if ts { ts_name } else { py_name }.to_string();
+        let (name, mut args) = match (check, op) {
+            ("visible", _) => (matcher("toBeVisible", "to_be_visible"), vec![]),
+            ("hidden", _) => (matcher("toBeHidden", "to_be_hidden"), vec![]),
+            ("enabled", _) => (matcher("toBeEnabled", "to_be_enabled"), vec![]),
+            ("checked", _) => (matcher("toBeChecked", "to_be_checked"), vec![]),
+            ("count", "==") => (matcher("toHaveCount", "to_have_count"), vec![cmd["expected"].to_string()]),
+            ("count", _) => return self.count_assertion(&subject, op, &cmd["expected"]),
+            ("text", "contains") => (matcher("toContainText", "to_contain_text"), vec![self.arg(cmd, "expected")?]),
+            ("text", "equals") => (matcher("toHaveText", "to_have_text"), vec![self.arg(cmd, "expected")?]),
+            ("text", _) => (matcher("toHaveText", "to_have_text"), vec![self.pattern(cmd, op)?]),
+            (_, "equals") => {
+                let name = if check == "url" { matcher("toHaveURL", "to_have_url") } else { matcher("toHaveTitle", "to_have_title") };
+                (name, vec![self.arg(cmd, "expected")?])
+            }
+            _ => {
+                let name = if check == "url" { matcher("toHaveURL", "to_have_url") } else { matcher("toHaveTitle", "to_have_title") };
+                (name, vec![self.pattern(cmd, op)?])
+            }
+        };
+        if let Some(timeout) = cmd["timeout"].as_u64().filter(|t| *t > 0) {
+            args.push(if ts { format!("{{ timeout: {} }}", timeout) } else { format!("timeout={}", timeout) });
+        }
+        Ok(format!("expect({}).{}({})", subject, name, args.join(", ")))
+    }
+
+    /// The regex for a `matches`, `contains` or `glob` comparison
+    fn pattern(&mut self, cmd: &Value, op: &str) -> Result<String, String> {
+        let expected = cmd["expected"].as_str().unwrap_or_default();
+        if expected.contains("${") {
+            return Err(format!("'{}' with ${{...}} can't be turned into a regex at export time", op));
+        }
+        let pattern = match op {
+            "contains" => regex::escape(expected),
+            "glob" => glob_to_regex(expected),
+            _ => expected.to_string(),
+        };
+        Ok(self.regex(&pattern))
+    }
+
+    /// Count comparisons other than `==`: polled in TypeScript, checked once in Python
+    fn count_assertion(&mut self, subject: &str, op: &str, expected: &Value) -> Result<String, String> {
+        let (ts_matcher, py_op) = match op {
+            "!=" => ("not.toBe", "!="),
+            "<" => ("toBeLessThan", "<"),
+            "<=" => ("toBeLessThanOrEqual", "<="),
+            ">" => ("toBeGreaterThan", ">"),
+            _ => ("toBeGreaterThanOrEqual", ">="),
+        };
+        Ok(match self.lang {
+            Lang::Ts => format!("expect.poll(() => {}.count()).{}({})", subject, ts_matcher, expected),
+            // Emitted through emit_call, which adds nothing for Python
+            Lang::Py => format!("assert {}.count() {} {}", subject, py_op, expected),
+        })
+    }
+
+    /// A boolean expression for an `if` check
+    fn condition(&mut self, cmd: &Value) -> Result<String, String> {
+        let check = cmd["check"].as_str().unwrap_or_default();
+        let op = cmd["op"].as_str().unwrap_or_default();
+        let ts = self.ts();
+        let aw = if ts { "await " } else { "" };
+        let state = |s: &mut Self, ts_name: &str, py_name: &str| -> Result<String, String> {
+            let loc = s.locator(&cmd["selector"])?;
+            Ok(format!("{}{}.{}()", aw, loc, if ts { ts_name } else { py_name }))
+        };
+        match check {
+            "visible" => state(self, "isVisible", "is_visible"),
+            "hidden" => state(self, "isHidden", "is_hidden"),
+            "enabled" => state(self, "isEnabled", "is_enabled"),
+            "checked" => state(self, "isChecked", "is_checked"),
+            "count" => {
+                let op = if ts && op == "==" { "===" } else if ts && op == "!=" { "!==" } else { op };
+                Ok(format!("({}{}.count()) {} {}", aw, self.locator(&cmd["selector"])?, op, cmd["expected"]))
+            }
+            _ => {
+                let value = match (check, self.lang) {
+                    ("url", Lang::Ts) => "page.url()".to_string(),
+                    ("url", Lang::Py) => "page.url".to_string(),
+                    ("title", _) => format!("({}page.title())", aw),
+                    (_, Lang::Ts) => format!("((await {}.textContent()) ?? '')", self.locator(&cmd["selector"])?),
+                    (_, Lang::Py) => format!("({}.text_content() or \"\")", self.locator(&cmd["selector"])?),
+                };
+                match (op, self.lang) {
+                    ("equals", Lang::Ts) => Ok(format!("{} === {}", value, self.arg(cmd, "expected")?)),
+                    ("equals", Lang::Py) => Ok(format!("{} == {}", value, self.arg(cmd, "expected")?)),
+                    ("contains", Lang::Ts) => Ok(format!("{}.includes({})", value, self.arg(cmd, "expected")?)),
+                    ("contains", Lang::Py) => Ok(format!("{} in {}", self.arg(cmd, "expected")?, value)),
+                    (_, Lang::Ts) => Ok(format!("{}.test({})", self.pattern(cmd, op)?, value)),
+                    (_, Lang::Py) => Ok(format!("{}.search({}) is not None", self.pattern(cmd, op)?, value)),
+                }
+            }
+        }
+    }
+
+    /// The expression for a `set-var name = <command>` capture
+    fn capture(&mut self, cmd: &Value) -> Result<String, String> {
+        let action = cmd["action"].as_str().unwrap_or_default();
+        let aw = if self.ts() { "await " } else { "" };
+        let getter = |s: &mut Self, ts: &str, py: &str| -> Result<String, String> {
+            let loc = s.locator(&cmd["selector"])?;
+            Ok(format!("{}{}.{}", aw, loc, s.method(ts, py)))
+        };
+        Ok(match action {
+            "gettext" => getter(self, "textContent()", "text_content()")?,
+            "inputvalue" => getter(self, "inputValue()", "input_value()")?,
+            "innerhtml" => getter(self, "innerHTML()", "inner_html()")?,
+            "count" => getter(self, "count()", "count()")?,
+            "getattribute" => {
+                let attr = self.arg(cmd, "attribute")?;
+                format!("{}({})", getter(self, "getAttribute", "get_attribute")?, attr)
+            }
+            "url" => self.method("page.url()", "page.url"),
+            "title" => format!("{}page.title()", aw),
+            "evaluate" => format!("{}page.evaluate({})", aw, self.arg(cmd, "script")?),
+            _ => return Err(format!("set-var can't capture '{}' in export", action)),
+        })
+    }
+
+    fn skip(&mut self, step: &Step, reason: &str) {
+        let comment = if self.ts() { "//" } else { "#" };
+        self.emit(format!("{} TODO: {} ({})", comment, step.text, reason));
+        self.skipped.push(format!("line {}: {} ({})", step.line, step.text, reason));
+    }
+
+    fn parse(words: &[String]) -> Result<Value, String> {
+        parse_command(&clean_args(words), &parse_flags(words)).map_err(|e| e.format())
+    }
+
+    fn nodes(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            if let Node::Command(step) | Node::SetVar { step, .. } = node {
+                if starts_page(step) {
+                    self.page += 1;
+                }
+            }
+            let result = match node {
+                Node::Command(step) => Self::parse(&step.args).and_then(|cmd| self.command(&cmd)),
+                Node::SetVar { step, name, value, capture } => self.set_var(step, name, value, *capture),
+                Node::If { step, negate, then, otherwise } => self.if_block(step, *negate, then, otherwise),
+                Node::Repeat { count, body, .. } => self.repeat(count, body),
+            };
+            if let Err(reason) = result {
+                let step = match node {
+                    Node::Command(step)
+                    | Node::SetVar { step, .. }
+                    | Node::If { step, .. }
+                    | Node::Repeat { step, .. } => step,
+                };
+                self.skip(step, &reason);
+            }
+        }
+    }
+
+    fn set_var(&mut self, step: &Step, name: &str, value: &str, capture: bool) -> Result<(), String> {
+        let expr = if capture {
+            let cmd = Self::parse(&step.args[3..])?;
+            self.capture(&cmd)?
+        } else {
+            self.string(value)?
+        };
+        let line = match self.lang {
+            Lang::Ts if self.vars.contains(name) => format!("{} = {};", name, expr),
+            Lang::Ts => format!("let {} = {};", name, expr),
+            Lang::Py => format!("{} = {}", name, expr),
+        };
+        self.vars.insert(name.to_string());
+        self.emit(line);
+        Ok(())
+    }
+
+    fn if_block(&mut self, step: &Step, negate: bool, then: &[Node], otherwise: &[Node]) -> Result<(), String> {
+        let check_at = if negate { 2 } else { 1 };
+        let mut words = vec!["assert".to_string()];
+        words.extend_from_slice(&step.args[check_at..]);
+        let cond = self.condition(&Self::parse(&words)?)?;
+        let cond = match (negate, self.lang) {
+            (false, _) => cond,
+            (true, Lang::Ts) => format!("!({})", cond),
+            (true, Lang::Py) => format!("not ({})", cond),
+        };
+        self.block(if self.ts() { format!("if ({}) {{", cond) } else { format!("if {}:", cond) }, then);
+        if !otherwise.is_empty() {
+            let (close, open) = if self.ts() { ("} else {", true) } else { ("else:", false) };
+            if open {
+                // `} else {` replaces the closing brace block() just emitted
+                self.lines.pop();
+            }
+            self.block(close.to_string(), otherwise);
+        }
+        Ok(())
+    }
+
+    fn repeat(&mut self, count: &str, body: &[Node]) -> Result<(), String> {
+        let count = match count.parse::<u32>() {
+            Ok(n) => n.to_string(),
+            Err(_) => {
+                let expr = self.string(count).map_err(|_| format!("invalid repeat count '{}'", count))?;
+                if self.ts() { format!("Number({})", expr) } else { format!("int({})", expr) }
+            }
+        };
+        // `${i}` is the loop variable inside the body, whatever it was outside
+        let added_i = self.vars.insert("i".to_string());
+        let header = match self.lang {
+            Lang::Ts => format!("for (let i = 1; i <= {}; i++) {{", count),
+            Lang::Py => format!("for i in range(1, {} + 1):", count),
+        };
+        self.block(header, body);
+        if added_i {
+            self.vars.remove("i");
+        }
+        Ok(())
+    }
+
+    /// A header line, its body one level deeper, and (in TypeScript) a closing brace
+    fn block(&mut self, header: String, body: &[Node]) {
+        self.emit(header);
+        self.depth += 1;
+        let before = self.lines.len();
+        self.nodes(body);
+        if self.lang == Lang::Py && self.lines.len() == before {
+            self.emit("pass".to_string());
+        }
+        self.depth -= 1;
+        if self.ts() {
+            self.emit("}".to_string());
+        }
+    }
+}
+
+/// Whether any selector in the script is a ref, so the session has to be asked
+fn uses_refs(nodes: &[Node]) -> bool {
+    nodes.iter().any(|node| match node {
+        Node::Command(step) | Node::SetVar { step, .. } => step.args.iter().any(|a| parse_ref(a).is_some()),
+        Node::If { step, then, otherwise, .. } => {
+            step.args.iter().any(|a| parse_ref(a).is_some()) || uses_refs(then) || uses_refs(otherwise)
+        }
+        Node::Repeat { body, .. } => uses_refs(body),
+    })
+}
+
+/// Test name from the script file name, e.g. `checkout-flow`
+fn test_name(path: &Path) -> String {
+    path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "exported".to_string())
+}
+
+/// The complete test file for a script
+fn render(nodes: &[Node], name: &str, lang: Lang, refs: &Refs) -> (String, Vec<String>) {
+    let mut exporter = Exporter::new(lang, refs);
+    exporter.ref_page = ref_page(nodes);
+    exporter.nodes(nodes);
+    let mut out = Vec::new();
+    match lang {
+        Lang::Ts => {
+            out.push("import { test, expect } from '@playwright/test';".to_string());
+            out.push(String::new());
+            out.push(format!("test({}, async ({{ page }}) => {{", quote(name, lang)));
+            out.append(&mut exporter.lines);
+            out.push("});".to_string());
+        }
+        Lang::Py => {
+            if exporter.uses_env {
+                out.push("import os".to_string());
+            }
+            if exporter.uses_re {
+                out.push("import re".to_string());
+            }
+            if exporter.uses_env || exporter.uses_re {
+                out.push(String::new());
+            }
+            out.push("from playwright.sync_api import Page, expect".to_string());
+            out.push(String::new());
+            out.push(String::new());
+            let ident: String = name
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+                .collect();
+            out.push(format!("def test_{}(page: Page) -> None:", ident));
+            if exporter.lines.is_empty() {
+                out.push("    pass".to_string());
+            }
+            out.append(&mut exporter.lines);
+        }
+    }
+    out.push(String::new());
+    (out.join("\n"), exporter.skipped)
+}
+
+/// Ask the session for the refs of its last snapshot
+fn fetch_refs(session: &str) -> Result<Refs, String> {
+    if !is_daemon_alive(session) {
+        return Err(format!(
+            "The script uses @refs, but session '{}' is not running. Open the page and take a snapshot, then export again.",
+            session
+        ));
+    }
+    let resp = send_command(json!({ "id": gen_id(), "action": "refs" }), session).map_err(|e| e.message)?;
+    if !resp.success {
+        return Err(resp.error.unwrap_or_else(|| "Could not read refs".to_string()));
+    }
+    let refs = resp.data.and_then(|d| d.get("refs").cloned()).unwrap_or(Value::Null);
+    Ok(serde_json::from_value(refs).unwrap_or_default())
+}
+
+pub fn run_export(args: &[String], session: &str, json_mode: bool) {
+    const USAGE: &str = "Usage: agent-browser export playwright <file> [--lang ts|py] [--out <path>]";
+    let parse_error = |msg: &str| -> ! { fail(ErrorKind::ParseError, msg, Some("export"), json_mode) };
+
+    let value_of = |names: &[&str]| {
+        args.iter()
+            .position(|a| names.contains(&a.as_str()))
+            .map(|i| args.get(i + 1).cloned().unwrap_or_default())
+    };
+    let lang = match value_of(&["--lang"]).as_deref() {
+        None | Some("ts") => Lang::Ts,
+        Some("py") => Lang::Py,
+        Some(other) => parse_error(&format!("Unknown --lang '{}' (expected ts or py)", other)),
+    };
+    let out = value_of(&["--out", "-o"]);
+
+    let positional: Vec<&String> = args[1..]
+        .iter()
+        .enumerate()
+        .filter(|(i, a)| !a.starts_with('-') && !matches!(args[*i].as_str(), "--lang" | "--out" | "-o"))
+        .map(|(_, a)| a)
+        .collect();
+    let file = match positional.as_slice() {
+        [format, file] if format.as_str() == "playwright" => file.as_str(),
+        [format, ..] if format.as_str() != "playwright" => {
+            parse_error(&format!("Unknown export format '{}' (supported: playwright)\n{}", format, USAGE))
+        }
+        _ => parse_error(USAGE),
+    };
+
+    let path = Path::new(file);
+    let nodes = match load_script(path) {
+        Ok(nodes) => nodes,
+        Err(e) => fail(ErrorKind::ActionFailed, &e, Some("export"), json_mode),
+    };
+    let refs = if uses_refs(&nodes) {
+        match fetch_refs(session) {
+            Ok(refs) => refs,
+            Err(e) => fail(ErrorKind::ActionFailed, &e, Some("export"), json_mode),
+        }
+    } else {
+        Refs::new()
+    };
+
+    let (code, skipped) = render(&nodes, &test_name(path), lang, &refs);
+    if let Some(out) = &out {
+        if let Err(e) = fs::write(out, &code) {
+            let msg = format!("Could not write {}: {}", out, e);
+            fail(ErrorKind::ActionFailed, &msg, Some("export"), json_mode);
+        }
+    }
+
+    if json_mode {
+        let mut data = json!({ "lang": if lang == Lang::Ts { "ts" } else { "py" }, "skipped": skipped });
+        match &out {
+            Some(out) => data["path"] = json!(out),
+            None => data["code"] = json!(code),
+        }
+        println!("{}", json!({ "success": true, "data": data }));
+        return;
+    }
+    match &out {
+        Some(out) => println!("{} Exported {} to {}", color::success_indicator(), file, out),
+        None => print!("{}", code),
+    }
+    for s in &skipped {
+        eprintln!("{} Not exported: {}", color::warning_indicator(), s);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::parse_script;
+
+    fn export(script: &str, lang: Lang, refs: &Refs) -> (String, Vec<String>) {
+        render(&parse_script(script).unwrap(), "login", lang, refs)
+    }
+
+    fn refs() -> Refs {
+        let mut refs = Refs::new();
+        refs.insert("e1".to_string(), RefInfo { role: "textbox".to_string(), name: Some("Email".to_string()), nth: None });
+        refs.insert("e2".to_string(), RefInfo { role: "button".to_string(), name: Some("Sign in".to_string()), nth: Some(1) });
+        refs
+    }
+
+    #[test]
+    fn test_export_ts() {
+        let script = "open example.com/login\nfill @e1 \"${EMAIL}\"\nclick @e2\nwait --url \"**/dashboard\"\nassert text \"#welcome\" contains Hello\nassert count .row ge 2\n";
+        let (code, skipped) = export(script, Lang::Ts, &refs());
+        assert!(skipped.is_empty());
+        assert_eq!(
+            code,
+            "import { test, expect } from '@playwright/test';\n\n\
+             test('login', async ({ page }) => {\n  \
+             await page.goto('https://example.com/login');\n  \
+             await page.getByRole('textbox', { name: 'Email', exact: true }).fill(`${process.env.EMAIL}`);\n  \
+             await page.getByRole('button', { name: 'Sign in', exact: true }).nth(1).click();\n  \
+             await page.waitForURL('**/dashboard');\n  \
+             await expect(page.locator('#welcome')).toContainText('Hello');\n  \
+             await expect.poll(() => page.locator('.row').count()).toBeGreaterThanOrEqual(2);\n\
+             });\n"
+        );
+    }
+
+    #[test]
+    fn test_export_py_blocks() {
+        let script = "set-var n 2\nrepeat ${n}\n  press Tab\nend\nif not visible \"#done\"\n  click @e2\nelse\n  assert url **/home\nend\n";
+        let (code, skipped) = export(script, Lang::Py, &refs());
+        assert!(skipped.is_empty());
+        assert!(code.starts_with("import re\n\nfrom playwright.sync_api import Page, expect\n\n\ndef test_login(page: Page) -> None:\n"));
+        assert!(code.contains("    n = \"2\"\n    for i in range(1, int(f\"{n}\") + 1):\n        page.keyboard.press(\"Tab\")\n"));
+        assert!(code.contains("    if not (page.locator(\"#done\").is_visible()):\n        page.get_by_role(\"button\", name=\"Sign in\", exact=True).nth(1).click()\n    else:\n        expect(page).to_have_url(re.compile("));
+    }
+
+    #[test]
+    fn test_export_skips_unsupported() {
+        let (code, skipped) = export("click @e9\ncookies clear\n", Lang::Ts, &refs());
+        assert_eq!(skipped.len(), 2);
+        assert!(skipped[0].starts_with("line 1: click @e9 (ref @e9 is not in the session's last snapshot"));
+        assert!(code.contains("  // TODO: cookies clear ("));
+    }
+
+    #[test]
+    fn test_export_skips_refs_from_earlier_pages() {
+        let script = "open a.com\nsnapshot -i\nclick @e1\nopen b.com\nsnapshot -i\nfill @e1 x\nclick @e2\n";
+        let (code, skipped) = export(script, Lang::Ts, &refs());
+        let stale: Vec<&String> = skipped.iter().filter(|s| s.contains("earlier snapshot")).collect();
+        assert_eq!(stale.len(), 1);
+        assert!(stale[0].starts_with("line 3: click @e1 (ref @e1 is from an earlier snapshot or page"));
+        assert!(code.contains("  // TODO: click @e1 ("));
+        assert!(code.contains("getByRole('textbox', { name: 'Email', exact: true }).fill('x')"));
+
+        // A snapshot after the last ref use leaves no refs to resolve against
+        let (_, skipped) = export("snapshot\nclick @e2\nsnapshot\n", Lang::Ts, &refs());
+        assert!(skipped.iter().any(|s| s.starts_with("line 2: click @e2 (ref @e2 is from an earlier")));
+    }
+}
@@ -16,6 +16,16 @@ pub struct Flags {
     pub args: Option<String>,
     pub user_agent: Option<String>,
     pub provider: Option<String>,
+    pub no_auto_restart: bool,
+    pub idle_timeout: Option<String>,
+    pub relaunch: bool,
+    pub no_lock: bool,
+    pub recover: bool,
+    pub lock_timeout: Option<String>,
+    pub retry: Option<String>,
+    pub retry_delay: Option<String>,
+    pub retry_unsafe: bool,
+    pub allow_stale_refs: bool,
 }
 
 pub fn parse_flags(args: &[String]) -> Flags {
@@ -28,7 +38,7 @@ pub fn parse_flags(args: &[String]) -> Flags {
         json: false,
         full: false,
         headed: false,
-        debug: false,
+        debug: env::var("AGENT_BROWSER_DEBUG").map(|v| v == "1" || v == "true").unwrap_or(false),
         session: env::var("AGENT_BROWSER_SESSION").unwrap_or_else(|_| "default".to_string()),
         headers: None,
         executable_path: env::var("AGENT_BROWSER_EXECUTABLE_PATH").ok(),
@@ -40,6 +50,16 @@ pub fn parse_flags(args: &[String]) -> Flags {
         args: env::var("AGENT_BROWSER_ARGS").ok(),
         user_agent: env::var("AGENT_BROWSER_USER_AGENT").ok(),
         provider: env::var("AGENT_BROWSER_PROVIDER").ok(),
+        idle_timeout: env::var("AGENT_BROWSER_IDLE_TIMEOUT").ok(),
+        relaunch: false,
+        recover: env::var("AGENT_BROWSER_RECOVER").map(|v| v == "1" || v == "true").unwrap_or(false),
+        no_lock: env::var("AGENT_BROWSER_NO_LOCK").map(|v| v == "1" || v == "true").unwrap_or(false),
+        lock_timeout: env::var("AGENT_BROWSER_LOCK_TIMEOUT").ok(),
+        retry: env::var("AGENT_BROWSER_RETRY").ok(),
+        retry_delay: env::var("AGENT_BROWSER_RETRY_DELAY").ok(),
+        retry_unsafe: false,
+        no_auto_restart: env::var("AGENT_BROWSER_NO_AUTO_RESTART").map(|v| v == "1" || v == "true").unwrap_or(false),
+        allow_stale_refs: env::var("AGENT_BROWSER_ALLOW_STALE_REFS").map(|v| v == "1" || v == "true").unwrap_or(false),
     };
 
     let mut i = 0;
@@ -49,6 +69,12 @@ pub fn parse_flags(args: &[String]) -> Flags {
             "--full" | "-f" => flags.full = true,
             "--headed" => flags.headed = true,
             "--debug" => flags.debug = true,
+            "--no-auto-restart" => flags.no_auto_restart = true,
+            "--relaunch" => flags.relaunch = true,
+            "--no-lock" => flags.no_lock = true,
+            "--recover" => flags.recover = true,
+            "--retry-unsafe" => flags.retry_unsafe = true,
+            "--allow-stale-refs" => flags.allow_stale_refs = true,
             "--session" => {
                 if let Some(s) = args.get(i + 1) {
                     flags.session = s.clone();
@@ -109,6 +135,30 @@ pub fn parse_flags(args: &[String]) -> Flags {
                     i += 1;
                 }
             }
+            "--idle-timeout" => {
+                if let Some(s) = args.get(i + 1) {
+                    flags.idle_timeout = Some(s.clone());
+                    i += 1;
+                }
+            }
+            "--lock-timeout" => {
+                if let Some(s) = args.get(i + 1) {
+                    flags.lock_timeout = Some(s.clone());
+                    i += 1;
+                }
+            }
+            "--retry" => {
+                if let Some(s) = args.get(i + 1) {
+                    flags.retry = Some(s.clone());
+                    i += 1;
+                }
+            }
+            "--retry-delay" => {
+                if let Some(s) = args.get(i + 1) {
+                    flags.retry_delay = Some(s.clone());
+                    i += 1;
+                }
+            }
             "-p" | "--provider" => {
                 if let Some(p) = args.get(i + 1) {
                     flags.provider = Some(p.clone());
@@ -122,12 +172,30 @@ pub fn parse_flags(args: &[String]) -> Flags {
     flags
 }
 
+/// Parse a duration like "500ms", "90s", "30m" or "2h" into milliseconds.
+/// A bare number is taken as seconds.
+pub fn parse_duration_ms(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let (num, mult) = if let Some(n) = s.strip_suffix("ms") {
+        (n, 1)
+    } else if let Some(n) = s.strip_suffix('s') {
+        (n, 1000)
+    } else if let Some(n) = s.strip_suffix('m') {
+        (n, 60 * 1000)
+    } else if let Some(n) = s.strip_suffix('h') {
+        (n, 60 * 60 * 1000)
+    } else {
+        (s, 1000)
+    };
+    num.trim().parse::<u64>().ok().and_then(|n| n.checked_mul(mult))
+}
+
 pub fn clean_args(args: &[String]) -> Vec<String> {
     let mut result = Vec::new();
     let mut skip_next = false;
 
     // Global flags that should be stripped from command args
-    const GLOBAL_FLAGS: &[&str] = &["--json", "--full", "--headed", "--debug"];
+    const GLOBAL_FLAGS: &[&str] = &["--json", "--full", "--headed", "--debug", "--no-auto-restart", "--relaunch", "--no-lock", "--recover", "--retry-unsafe", "--allow-stale-refs"];
     // Global flags that take a value (need to skip the next arg too)
     const GLOBAL_FLAGS_WITH_VALUE: &[&str] = &[
         "--session",
@@ -142,6 +210,10 @@ pub fn clean_args(args: &[String]) -> Vec<String> {
         "--user-agent",
         "-p",
         "--provider",
+        "--idle-timeout",
+        "--lock-timeout",
+        "--retry",
+        "--retry-delay",
     ];
 
     for arg in args.iter() {
@@ -271,6 +343,51 @@ mod tests {
         assert_eq!(cleaned, vec!["open", "example.com"]);
     }
 
+    #[test]
+    fn test_no_auto_restart_flag() {
+        let input = args("--no-auto-restart open example.com");
+        assert!(parse_flags(&input).no_auto_restart);
+        assert_eq!(clean_args(&input), vec!["open", "example.com"]);
+    }
+
+    #[test]
+    fn test_idle_timeout_flag() {
+        let input = args("--idle-timeout 30m open example.com");
+        assert_eq!(parse_flags(&input).idle_timeout, Some("30m".to_string()));
+        assert_eq!(clean_args(&input), vec!["open", "example.com"]);
+    }
+
+    #[test]
+    fn test_lock_flags() {
+        let input = args("--no-lock --lock-timeout 5s click #btn");
+        let flags = parse_flags(&input);
+        assert!(flags.no_lock);
+        assert_eq!(flags.lock_timeout, Some("5s".to_string()));
+        assert_eq!(clean_args(&input), vec!["click", "#btn"]);
+    }
+
+    #[test]
+    fn test_retry_flags() {
+        let input = args("click #btn --retry 3 --retry-delay 200 --retry-unsafe");
+        let flags = parse_flags(&input);
+        assert_eq!(flags.retry, Some("3".to_string()));
+        assert_eq!(flags.retry_delay, Some("200".to_string()));
+        assert!(flags.retry_unsafe);
+        assert_eq!(clean_args(&input), vec!["click", "#btn"]);
+    }
+
+    #[test]
+    fn test_parse_duration_ms() {
+        assert_eq!(parse_duration_ms("500ms"), Some(500));
+        assert_eq!(parse_duration_ms("90s"), Some(90_000));
+        assert_eq!(parse_duration_ms("30m"), Some(1_800_000));
+        assert_eq!(parse_duration_ms("2h"), Some(7_200_000));
+        assert_eq!(parse_duration_ms("45"), Some(45_000));
+        assert_eq!(parse_duration_ms("0"), Some(0));
+        assert_eq!(parse_duration_ms("soon"), None);
+        assert_eq!(parse_duration_ms("-5m"), None);
+    }
+
     #[test]
     fn test_parse_flags_with_session_and_executable_path() {
         let flags = parse_flags(&args(
@@ -0,0 +1,406 @@
+//! Command history: every command sent for a session is appended to
+//! `<socket_dir>/<session>.history.jsonl`, so `history` can show what was done
+//! (e.g. by an autonomous agent) and `replay` can do it again.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::assert::check_assertion;
+use crate::color;
+use crate::commands::gen_id;
+use crate::connection::{get_socket_dir, send_command, Response};
+use crate::debug;
+use crate::error::{fail, CliError, ErrorKind};
+
+/// Rotate the journal once it grows past this size
+const MAX_HISTORY_SIZE: u64 = 5 * 1024 * 1024;
+
+/// Number of entries shown by `history` without `--last`
+const DEFAULT_HISTORY_ENTRIES: usize = 50;
+
+/// Text-entry actions and the field holding what was typed, masked in the
+/// journal when `AGENT_BROWSER_HISTORY_MASK_INPUT=1`
+const TYPED_FIELDS: &[(&str, &str)] = &[("fill", "value"), ("type", "text")];
+
+/// Returns true if typed text (fill/type values) should be masked in the journal
+fn mask_input() -> bool {
+    std::env::var("AGENT_BROWSER_HISTORY_MASK_INPUT").map(|v| v == "1" || v == "true").unwrap_or(false)
+}
+
+/// Mask the typed text of a fill/type command
+fn mask_typed(command: &mut Value) {
+    let action = command["action"].as_str().unwrap_or_default().to_string();
+    for (_, field) in TYPED_FIELDS.iter().filter(|(a, _)| *a == action) {
+        if command.get(*field).is_some_and(|v| !v.is_null()) {
+            command[*field] = json!("***");
+        }
+    }
+}
+
+/// Returns true if a recorded command had values masked (by `debug::redact`
+/// or `AGENT_BROWSER_HISTORY_MASK_INPUT`), so replaying it would send `***`
+fn is_masked(value: &Value) -> bool {
+    match value {
+        Value::String(s) => s == "***" || s.contains("://***@"),
+        Value::Array(items) => items.iter().any(is_masked),
+        Value::Object(map) => map.values().any(is_masked),
+        _ => false,
+    }
+}
+
+/// One journal line
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    /// When the command was sent, in milliseconds since the Unix epoch
+    pub ts: u64,
+    /// The parsed command as sent to the daemon (without its id), with
+    /// credentials masked
+    pub command: Value,
+    /// The command line it was parsed from, if it contained nothing to mask
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub args: Option<Vec<String>>,
+    pub duration_ms: u64,
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Get the journal path for a session: `<socket_dir>/<session>.history.jsonl`
+pub fn get_history_path(session: &str) -> PathBuf {
+    get_socket_dir().join(format!("{}.history.jsonl", session))
+}
+
+/// Append a command to the session's journal. Failing to write it never fails the command.
+pub fn record(session: &str, cmd: &Value, args: Option<&[String]>, started: Instant, error: Option<&str>) {
+    let mut command = cmd.clone();
+    if let Some(obj) = command.as_object_mut() {
+        obj.remove("id");
+    }
+    let mut redacted = debug::redact(&command);
+    if mask_input() {
+        mask_typed(&mut redacted);
+    }
+    // The typed command line would show what was masked, so only keep it if nothing was
+    let args = args.filter(|_| redacted == command).map(|a| a.to_vec());
+    let ts = SystemTime::now()
+        .checked_sub(started.elapsed())
+        .unwrap_or_else(SystemTime::now)
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let entry = Entry {
+        ts,
+        command: redacted,
+        args,
+        duration_ms: started.elapsed().as_millis() as u64,
+        success: error.is_none(),
+        error: error.map(String::from),
+    };
+
+    let path = get_history_path(session);
+    if fs::metadata(&path).map(|m| m.len() > MAX_HISTORY_SIZE).unwrap_or(false) {
+        let _ = fs::rename(&path, path.with_extension("jsonl.1"));
+    }
+    if let (Ok(mut file), Ok(line)) = (
+        OpenOptions::new().create(true).append(true).open(&path),
+        serde_json::to_string(&entry),
+    ) {
+        let _ = writeln!(file, "{}", line);
+    }
+}
+
+/// `send_command`, recorded in the session's journal
+pub fn send_recorded(cmd: Value, session: &str) -> Result<Response, CliError> {
+    let started = Instant::now();
+    let result = send_command(cmd.clone(), session);
+    let error = match &result {
+        Ok(resp) if resp.success => None,
+        Ok(resp) => Some(resp.error.clone().unwrap_or_else(|| "Unknown error".to_string())),
+        Err(e) => Some(e.message.clone()),
+    };
+    record(session, &cmd, None, started, error.as_deref());
+    result
+}
+
+/// Read a journal, skipping lines that don't parse (e.g. a partly written last line)
+pub fn load_journal(path: &Path) -> Result<Vec<Entry>, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    Ok(content.lines().filter_map(|l| serde_json::from_str(l).ok()).collect())
+}
+
+fn quote_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'' || c == '\\') {
+        return arg.to_string();
+    }
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The command as typed, or `action key=value ...` if it wasn't kept
+fn describe(entry: &Entry) -> String {
+    if let Some(args) = &entry.args {
+        return args.iter().map(|a| quote_arg(a)).collect::<Vec<_>>().join(" ");
+    }
+    let mut parts = vec![entry.command["action"].as_str().unwrap_or("?").to_string()];
+    if let Some(obj) = entry.command.as_object() {
+        for (key, value) in obj.iter().filter(|(k, v)| *k != "action" && !v.is_null()) {
+            let value = match value {
+                Value::String(s) => quote_arg(s),
+                other => other.to_string(),
+            };
+            parts.push(format!("{}={}", key, value));
+        }
+    }
+    parts.join(" ")
+}
+
+/// `YYYY-MM-DD HH:MM:SS` (UTC) for a Unix timestamp in milliseconds
+fn format_timestamp(ms: u64) -> String {
+    let secs = ms / 1000;
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    // Civil-from-days (Howard Hinnant), valid for any date after 1970
+    let z = days as i64 + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+fn print_entry(index: usize, entry: &Entry) {
+    let status = if entry.success { color::success_indicator() } else { color::error_indicator() };
+    println!(
+        "{:>4}  {}  {} {} {}",
+        index,
+        color::dim(&format_timestamp(entry.ts)),
+        status,
+        describe(entry),
+        color::dim(&format!("({}ms)", entry.duration_ms))
+    );
+    if let Some(error) = &entry.error {
+        println!("      {}", color::red(error.lines().next().unwrap_or_default()));
+    }
+}
+
+pub fn run_history(args: &[String], session: &str, json_mode: bool) {
+    let last = match args.iter().position(|a| a == "--last" || a == "-n") {
+        Some(idx) => match args.get(idx + 1).and_then(|s| s.parse::<usize>().ok()) {
+            Some(n) => n,
+            None => fail(
+                ErrorKind::ParseError,
+                "Invalid value for --last: expected a number",
+                Some("history"),
+                json_mode,
+            ),
+        },
+        None => DEFAULT_HISTORY_ENTRIES,
+    };
+
+    let path = get_history_path(session);
+    if !path.exists() {
+        let msg = format!("No history for session '{}' ({})", session, path.display());
+        fail(ErrorKind::ActionFailed, &msg, Some("history"), json_mode);
+    }
+    let entries = match load_journal(&path) {
+        Ok(e) => e,
+        Err(e) => fail(ErrorKind::ActionFailed, &e, Some("history"), json_mode),
+    };
+
+    // Entries are numbered from 1 over the whole journal, as `replay --from` expects
+    let start = entries.len().saturating_sub(last);
+    if json_mode {
+        let shown: Vec<Value> = entries[start..]
+            .iter()
+            .enumerate()
+            .map(|(i, e)| {
+                let mut v = json!(e);
+                v["index"] = json!(start + i + 1);
+                v
+            })
+            .collect();
+        println!(
+            "{}",
+            json!({ "success": true, "data": { "path": path.to_string_lossy(), "entries": shown } })
+        );
+        return;
+    }
+    for (i, entry) in entries[start..].iter().enumerate() {
+        print_entry(start + i + 1, entry);
+    }
+}
+
+/// Send one journal entry again, returning the error if it fails
+fn replay_entry(entry: &Entry, session: &str) -> Result<(), CliError> {
+    let mut cmd = entry.command.clone();
+    cmd["id"] = json!(gen_id());
+    if cmd["action"] == "assert" {
+        let started = Instant::now();
+        let outcome = check_assertion(&cmd, session)?;
+        let message = (!outcome.passed).then(|| outcome.failure_message(&cmd));
+        record(session, &cmd, entry.args.as_deref(), started, message.as_deref());
+        return match message {
+            Some(m) => Err(CliError::new(ErrorKind::AssertionFailed, m)),
+            None => Ok(()),
+        };
+    }
+    let started = Instant::now();
+    let result = send_command(cmd.clone(), session);
+    let error = match &result {
+        Ok(resp) if resp.success => None,
+        Ok(resp) => Some(CliError::new(
+            ErrorKind::ActionFailed,
+            resp.error.clone().unwrap_or_else(|| "Unknown error".to_string()),
+        )),
+        Err(e) => Some(CliError::new(e.kind, e.message.clone())),
+    };
+    record(session, &cmd, entry.args.as_deref(), started, error.as_ref().map(|e| e.message.as_str()));
+    error.map_or(Ok(()), Err)
+}
+
+/// Re-execute a journal (from `cmd["journal"]`, starting at entry `cmd["from"]`)
+/// against the current session, which the caller has just started fresh.
+/// Commands that failed when recorded may fail again; the first new failure stops the replay.
+/// Commands recorded with masked values are skipped, since they would send `***`.
+pub fn run_replay(cmd: &Value, session: &str, json_mode: bool) {
+    let journal = cmd["journal"].as_str().unwrap_or_default();
+    let from = cmd["from"].as_u64().unwrap_or(1).max(1) as usize;
+    let entries = match load_journal(Path::new(journal)) {
+        Ok(e) => e,
+        Err(e) => fail(ErrorKind::ActionFailed, &e, Some("replay"), json_mode),
+    };
+
+    let mut results = Vec::new();
+    let mut failure: Option<CliError> = None;
+    for (i, entry) in entries.iter().enumerate().skip(from - 1) {
+        let index = i + 1;
+        if entry.command["action"] == "replay" {
+            continue;
+        }
+        if is_masked(&entry.command) {
+            if !json_mode {
+                println!(
+                    "{:>4}  {} {} {}",
+                    index,
+                    color::warning_indicator(),
+                    describe(entry),
+                    color::dim("(skipped: recorded with masked values)")
+                );
+            }
+            results.push(json!({ "index": index, "skipped": true, "reason": "recorded with masked values" }));
+            continue;
+        }
+        let result = replay_entry(entry, session);
+        if !json_mode {
+            let status = if result.is_ok() { color::success_indicator() } else { color::error_indicator() };
+            println!("{:>4}  {} {}", index, status, describe(entry));
+        }
+        let mut r = json!({ "index": index, "success": result.is_ok() });
+        if let Err(e) = result {
+            r["error"] = json!(e.message);
+            results.push(r);
+            if entry.success {
+                failure = Some(e);
+                break;
+            }
+            if !json_mode {
+                println!("      {}", color::dim("(also failed when recorded)"));
+            }
+            continue;
+        }
+        results.push(r);
+    }
+
+    if json_mode {
+        let skipped = results.iter().filter(|r| r["skipped"] == true).count();
+        let mut out = json!({
+            "success": failure.is_none(),
+            "data": { "replayed": results.len() - skipped, "skipped": skipped, "results": results }
+        });
+        if let Some(e) = &failure {
+            out["error"] = json!(e.message);
+            out["type"] = json!(e.kind);
+            out["command"] = json!("replay");
+        }
+        println!("{}", out);
+    } else if let Some(e) = &failure {
+        eprintln!("{} {}", color::error_indicator(), e.message);
+    }
+    if let Some(e) = failure {
+        exit(e.kind.exit_code());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(command: Value, args: Option<&[&str]>) -> Entry {
+        Entry {
+            ts: 0,
+            command,
+            args: args.map(|a| a.iter().map(|s| s.to_string()).collect()),
+            duration_ms: 5,
+            success: true,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00");
+        assert_eq!(format_timestamp(1_709_210_096_000), "2024-02-29 12:34:56");
+    }
+
+    #[test]
+    fn test_describe() {
+        let typed = entry(json!({ "action": "fill" }), Some(&["fill", "#q", "hello world"]));
+        assert_eq!(describe(&typed), "fill #q \"hello world\"");
+        let masked = entry(json!({ "action": "credentials", "username": "***", "password": "***" }), None);
+        assert_eq!(describe(&masked), "credentials password=*** username=***");
+    }
+
+    #[test]
+    fn test_masked_entries_are_detected() {
+        assert!(is_masked(&json!({ "action": "credentials", "username": "***", "password": "***" })));
+        assert!(is_masked(&json!({ "action": "launch", "proxy": "http://***@proxy.com:8080" })));
+        assert!(!is_masked(&json!({ "action": "fill", "selector": "#q", "value": "a***b" })));
+        assert!(!is_masked(&json!({ "action": "open", "url": "https://example.com" })));
+    }
+
+    #[test]
+    fn test_mask_typed_values() {
+        let mut fill = json!({ "action": "fill", "selector": "#pw", "value": "hunter2" });
+        mask_typed(&mut fill);
+        assert_eq!(fill["value"], "***");
+        assert_eq!(fill["selector"], "#pw");
+        let mut click = json!({ "action": "click", "selector": "#a" });
+        mask_typed(&mut click);
+        assert_eq!(click, json!({ "action": "click", "selector": "#a" }));
+    }
+
+    #[test]
+    fn test_entry_roundtrip() {
+        let line = serde_json::to_string(&entry(json!({ "action": "click", "selector": "#a" }), None)).unwrap();
+        assert_eq!(line, r##"{"ts":0,"command":{"action":"click","selector":"#a"},"durationMs":5,"success":true}"##);
+        let parsed: Entry = serde_json::from_str(&line).unwrap();
+        assert_eq!(parsed.command["selector"], "#a");
+    }
+}
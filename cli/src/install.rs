@@ -8,98 +8,7 @@ pub fn run_install(with_deps: bool) {
         if with_deps {
             println!("{}", color::cyan("Installing system dependencies..."));
 
-            let (pkg_mgr, deps) = if which_exists("apt-get") {
-                let libasound = if package_exists_apt("libasound2t64") {
-                    "libasound2t64"
-                } else {
-                    "libasound2"
-                };
-
-                (
-                    "apt-get",
-                    vec![
-                        "libxcb-shm0",
-                        "libx11-xcb1",
-                        "libx11-6",
-                        "libxcb1",
-                        "libxext6",
-                        "libxrandr2",
-                        "libxcomposite1",
-                        "libxcursor1",
-                        "libxdamage1",
-                        "libxfixes3",
-                        "libxi6",
-                        "libgtk-3-0",
-                        "libpangocairo-1.0-0",
-                        "libpango-1.0-0",
-                        "libatk1.0-0",
-                        "libcairo-gobject2",
-                        "libcairo2",
-                        "libgdk-pixbuf-2.0-0",
-                        "libxrender1",
-                        libasound,
-                        "libfreetype6",
-                        "libfontconfig1",
-                        "libdbus-1-3",
-                        "libnss3",
-                        "libnspr4",
-                        "libatk-bridge2.0-0",
-                        "libdrm2",
-                        "libxkbcommon0",
-                        "libatspi2.0-0",
-                        "libcups2",
-                        "libxshmfence1",
-                        "libgbm1",
-                    ],
-                )
-            } else if which_exists("dnf") {
-                (
-                    "dnf",
-                    vec![
-                        "nss",
-                        "nspr",
-                        "atk",
-                        "at-spi2-atk",
-                        "cups-libs",
-                        "libdrm",
-                        "libXcomposite",
-                        "libXdamage",
-                        "libXrandr",
-                        "mesa-libgbm",
-                        "pango",
-                        "alsa-lib",
-                        "libxkbcommon",
-                        "libxcb",
-                        "libX11-xcb",
-                        "libX11",
-                        "libXext",
-                        "libXcursor",
-                        "libXfixes",
-                        "libXi",
-                        "gtk3",
-                        "cairo-gobject",
-                    ],
-                )
-            } else if which_exists("yum") {
-                (
-                    "yum",
-                    vec![
-                        "nss",
-                        "nspr",
-                        "atk",
-                        "at-spi2-atk",
-                        "cups-libs",
-                        "libdrm",
-                        "libXcomposite",
-                        "libXdamage",
-                        "libXrandr",
-                        "mesa-libgbm",
-                        "pango",
-                        "alsa-lib",
-                        "libxkbcommon",
-                    ],
-                )
-            } else {
+            let Some((pkg_mgr, deps)) = linux_dependencies() else {
                 eprintln!("{} No supported package manager found (apt-get, dnf, or yum)", color::error_indicator());
                 exit(1);
             };
@@ -175,7 +84,106 @@ pub fn run_install(with_deps: bool) {
     }
 }
 
-fn which_exists(cmd: &str) -> bool {
+/// System packages Chromium needs on Linux, with the package manager that provides them.
+/// Returns None if no supported package manager (apt-get, dnf, yum) is available.
+pub fn linux_dependencies() -> Option<(&'static str, Vec<&'static str>)> {
+    if which_exists("apt-get") {
+        let libasound = if package_exists_apt("libasound2t64") {
+            "libasound2t64"
+        } else {
+            "libasound2"
+        };
+
+        Some((
+            "apt-get",
+            vec![
+                "libxcb-shm0",
+                "libx11-xcb1",
+                "libx11-6",
+                "libxcb1",
+                "libxext6",
+                "libxrandr2",
+                "libxcomposite1",
+                "libxcursor1",
+                "libxdamage1",
+                "libxfixes3",
+                "libxi6",
+                "libgtk-3-0",
+                "libpangocairo-1.0-0",
+                "libpango-1.0-0",
+                "libatk1.0-0",
+                "libcairo-gobject2",
+                "libcairo2",
+                "libgdk-pixbuf-2.0-0",
+                "libxrender1",
+                libasound,
+                "libfreetype6",
+                "libfontconfig1",
+                "libdbus-1-3",
+                "libnss3",
+                "libnspr4",
+                "libatk-bridge2.0-0",
+                "libdrm2",
+                "libxkbcommon0",
+                "libatspi2.0-0",
+                "libcups2",
+                "libxshmfence1",
+                "libgbm1",
+            ],
+        ))
+    } else if which_exists("dnf") {
+        Some((
+            "dnf",
+            vec![
+                "nss",
+                "nspr",
+                "atk",
+                "at-spi2-atk",
+                "cups-libs",
+                "libdrm",
+                "libXcomposite",
+                "libXdamage",
+                "libXrandr",
+                "mesa-libgbm",
+                "pango",
+                "alsa-lib",
+                "libxkbcommon",
+                "libxcb",
+                "libX11-xcb",
+                "libX11",
+                "libXext",
+                "libXcursor",
+                "libXfixes",
+                "libXi",
+                "gtk3",
+                "cairo-gobject",
+            ],
+        ))
+    } else if which_exists("yum") {
+        Some((
+            "yum",
+            vec![
+                "nss",
+                "nspr",
+                "atk",
+                "at-spi2-atk",
+                "cups-libs",
+                "libdrm",
+                "libXcomposite",
+                "libXdamage",
+                "libXrandr",
+                "mesa-libgbm",
+                "pango",
+                "alsa-lib",
+                "libxkbcommon",
+            ],
+        ))
+    } else {
+        None
+    }
+}
+
+pub fn which_exists(cmd: &str) -> bool {
     #[cfg(unix)]
     {
         Command::new("which")
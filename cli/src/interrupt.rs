@@ -0,0 +1,103 @@
+//! Ctrl-C handling: forward a `cancel` for the in-flight request to the daemon
+//! over a second connection, then exit with the conventional code 130.
+//!
+//! On Unix, SIGINT is blocked in every thread and received synchronously by a
+//! dedicated thread via `sigwait`, so the cancel can be sent with ordinary code
+//! instead of inside an async signal handler.
+
+use std::process::exit;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::color;
+use crate::connection::cancel_request;
+use crate::debug;
+
+/// Exit code after Ctrl-C (128 + SIGINT)
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+/// Session and id of the request currently waiting for a response
+static IN_FLIGHT: Mutex<Option<(String, String)>> = Mutex::new(None);
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_in_flight(session: &str, id: &str) {
+    if let Ok(mut guard) = IN_FLIGHT.lock() {
+        *guard = Some((session.to_string(), id.to_string()));
+    }
+}
+
+pub fn clear_in_flight() {
+    if let Ok(mut guard) = IN_FLIGHT.lock() {
+        *guard = None;
+    }
+}
+
+/// Once Ctrl-C was pressed, block the calling thread so it cannot print the
+/// (cancelled) response or exit with another code before `on_interrupt` finishes
+pub fn park_if_interrupted() {
+    while INTERRUPTED.load(Ordering::SeqCst) {
+        thread::park();
+    }
+}
+
+fn on_interrupt() -> ! {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+    let pending = IN_FLIGHT.lock().ok().and_then(|mut g| g.take());
+    if let Some((session, id)) = pending {
+        debug::log(&format!("interrupted, cancelling request {}", id));
+        match cancel_request(&session, &id) {
+            Ok(()) => eprintln!("{} Cancelled", color::warning_indicator()),
+            Err(e) => debug::log(&format!("cancel failed: {}", e)),
+        }
+    }
+    exit(EXIT_INTERRUPTED);
+}
+
+/// Route Ctrl-C through `on_interrupt`. Must be called before any other thread is spawned.
+/// Child processes inherit the blocked SIGINT, so spawns must unblock it (see `unblock_in_child`).
+#[cfg(unix)]
+pub fn install() {
+    unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGINT);
+        if libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut()) != 0 {
+            return;
+        }
+        std::thread::spawn(move || {
+            let mut sig: libc::c_int = 0;
+            if libc::sigwait(&set, &mut sig) == 0 {
+                on_interrupt();
+            }
+        });
+    }
+}
+
+/// Restore default SIGINT delivery in a forked child (call from `pre_exec`)
+#[cfg(unix)]
+pub fn unblock_in_child() {
+    unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGINT);
+        libc::pthread_sigmask(libc::SIG_UNBLOCK, &set, std::ptr::null_mut());
+    }
+}
+
+#[cfg(windows)]
+pub fn install() {
+    use windows_sys::Win32::System::Console::{SetConsoleCtrlHandler, CTRL_C_EVENT};
+
+    // Runs on a separate thread created by the system
+    unsafe extern "system" fn handler(ctrl_type: u32) -> i32 {
+        if ctrl_type == CTRL_C_EVENT {
+            on_interrupt();
+        }
+        0
+    }
+    unsafe {
+        SetConsoleCtrlHandler(Some(handler), 1);
+    }
+}
@@ -0,0 +1,141 @@
+//! Per-session advisory lock so concurrent CLI invocations against one session
+//! run one at a time instead of interleaving their commands.
+//!
+//! The lock is an OS file lock on `<socket_dir>/<session>.lock`, so it is
+//! released automatically when the holding process exits, even on a crash.
+
+use std::fs::{self, File, OpenOptions};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::connection::get_socket_dir;
+use crate::debug;
+use crate::error::{CliError, ErrorKind};
+
+/// How long to wait for another invocation to finish when --lock-timeout is not given
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Held for as long as this process talks to the session's daemon
+pub struct SessionLock {
+    file: File,
+}
+
+impl Drop for SessionLock {
+    fn drop(&mut self) {
+        unlock(&self.file);
+    }
+}
+
+/// Take the session's lock, waiting up to `timeout` for another holder to release it
+pub fn lock_session(session: &str, timeout: Duration) -> Result<SessionLock, CliError> {
+    let dir = get_socket_dir();
+    if !dir.exists() {
+        fs::create_dir_all(&dir).map_err(|e| {
+            CliError::new(ErrorKind::ActionFailed, format!("Failed to create socket directory: {}", e))
+        })?;
+    }
+    let path = dir.join(format!("{}.lock", session));
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .map_err(|e| {
+            CliError::new(
+                ErrorKind::ActionFailed,
+                format!("Failed to open lock file {}: {}", path.display(), e),
+            )
+        })?;
+
+    let start = Instant::now();
+    let mut logged = false;
+    loop {
+        if try_lock(&file) {
+            if logged {
+                debug::log(&format!("acquired session lock after {}ms", start.elapsed().as_millis()));
+            }
+            return Ok(SessionLock { file });
+        }
+        if !logged {
+            debug::log(&format!("waiting for session lock {}", path.display()));
+            logged = true;
+        }
+        if start.elapsed() >= timeout {
+            return Err(CliError::new(
+                ErrorKind::Timeout,
+                format!(
+                    "Timed out after {}s waiting for another agent-browser command on session '{}' (use --lock-timeout or --no-lock)",
+                    timeout.as_secs(),
+                    session
+                ),
+            ));
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[cfg(unix)]
+fn try_lock(file: &File) -> bool {
+    use std::os::unix::io::AsRawFd;
+    unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) == 0 }
+}
+
+#[cfg(unix)]
+fn unlock(file: &File) {
+    use std::os::unix::io::AsRawFd;
+    unsafe {
+        libc::flock(file.as_raw_fd(), libc::LOCK_UN);
+    }
+}
+
+#[cfg(windows)]
+fn try_lock(file: &File) -> bool {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Storage::FileSystem::{
+        LockFileEx, LOCKFILE_EXCLUSIVE_LOCK, LOCKFILE_FAIL_IMMEDIATELY,
+    };
+    use windows_sys::Win32::System::IO::OVERLAPPED;
+    unsafe {
+        let mut overlapped: OVERLAPPED = std::mem::zeroed();
+        LockFileEx(
+            file.as_raw_handle() as isize,
+            LOCKFILE_EXCLUSIVE_LOCK | LOCKFILE_FAIL_IMMEDIATELY,
+            0,
+            1,
+            0,
+            &mut overlapped,
+        ) != 0
+    }
+}
+
+#[cfg(windows)]
+fn unlock(file: &File) {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Storage::FileSystem::UnlockFileEx;
+    use windows_sys::Win32::System::IO::OVERLAPPED;
+    unsafe {
+        let mut overlapped: OVERLAPPED = std::mem::zeroed();
+        UnlockFileEx(file.as_raw_handle() as isize, 0, 1, 0, &mut overlapped);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lock_file(name: &str) -> File {
+        let path = std::env::temp_dir().join(format!("agent-browser-test-{}-{}.lock", name, std::process::id()));
+        OpenOptions::new().create(true).truncate(false).write(true).open(path).unwrap()
+    }
+
+    #[test]
+    fn test_lock_is_exclusive_until_released() {
+        let a = lock_file("exclusive");
+        let b = lock_file("exclusive");
+        assert!(try_lock(&a));
+        assert!(!try_lock(&b));
+        unlock(&a);
+        assert!(try_lock(&b));
+        unlock(&b);
+    }
+}
@@ -0,0 +1,167 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::connection::get_socket_dir;
+use crate::error::{fail, ErrorKind};
+
+/// Rotate the daemon log once it grows past this size
+const MAX_LOG_SIZE: u64 = 5 * 1024 * 1024;
+
+/// Number of log lines shown by `logs` and in startup errors
+pub const DEFAULT_TAIL_LINES: usize = 50;
+
+/// Get the daemon log path for a session: `<socket_dir>/<session>.log`
+pub fn get_log_path(session: &str) -> PathBuf {
+    get_socket_dir().join(format!("{}.log", session))
+}
+
+/// Move the log to `<name>.log.1` once it has grown past `max_size`. Only one
+/// rotated file is kept. The log is copied and then truncated in place rather
+/// than renamed, because a running daemon keeps writing to the open file; it
+/// appends, so it carries on at the start of the emptied file.
+fn rotate_if_large(path: &Path, max_size: u64) -> std::io::Result<()> {
+    if fs::metadata(path).map(|m| m.len() <= max_size).unwrap_or(true) {
+        return Ok(());
+    }
+    fs::copy(path, path.with_extension("log.1"))?;
+    OpenOptions::new().write(true).open(path)?.set_len(0)
+}
+
+/// Rotate the session log if it has grown past `MAX_LOG_SIZE`. Called on every
+/// CLI invocation so a long-lived daemon's log stays bounded too.
+pub fn rotate_log(session: &str) {
+    let _ = rotate_if_large(&get_log_path(session), MAX_LOG_SIZE);
+}
+
+/// Open the session log for appending, rotating it first if it is too large
+pub fn open_log_for_daemon(session: &str) -> std::io::Result<File> {
+    let path = get_log_path(session);
+    rotate_if_large(&path, MAX_LOG_SIZE)?;
+    OpenOptions::new().create(true).append(true).open(&path)
+}
+
+/// Return the last `n` lines of a file (empty if it does not exist)
+pub fn tail_lines(path: &Path, n: usize) -> Vec<String> {
+    let Ok(content) = fs::read(path) else {
+        return Vec::new();
+    };
+    let text = String::from_utf8_lossy(&content);
+    let lines: Vec<&str> = text.lines().collect();
+    let start = lines.len().saturating_sub(n);
+    lines[start..].iter().map(|s| s.to_string()).collect()
+}
+
+pub fn run_logs(args: &[String], session: &str, json_mode: bool) {
+    let follow = args.iter().any(|a| a == "--follow" || a == "-f");
+    let lines = match args.iter().position(|a| a == "--lines" || a == "-n") {
+        Some(idx) => match args.get(idx + 1).and_then(|s| s.parse::<usize>().ok()) {
+            Some(n) => n,
+            None => fail(
+                ErrorKind::ParseError,
+                "Invalid value for --lines: expected a number",
+                Some("logs"),
+                json_mode,
+            ),
+        },
+        None => DEFAULT_TAIL_LINES,
+    };
+
+    let path = get_log_path(session);
+    if !path.exists() {
+        let msg = format!("No log file for session '{}' ({})", session, path.display());
+        fail(ErrorKind::ActionFailed, &msg, Some("logs"), json_mode);
+    }
+
+    let tail = tail_lines(&path, lines);
+    if json_mode && !follow {
+        println!(
+            "{}",
+            json!({ "success": true, "data": { "path": path.to_string_lossy(), "lines": tail } })
+        );
+        return;
+    }
+    for line in &tail {
+        println!("{}", line);
+    }
+
+    if follow {
+        follow_file(&path);
+    }
+}
+
+/// Print data appended to the log until interrupted, reopening after rotation
+fn follow_file(path: &Path) {
+    let mut offset = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    loop {
+        thread::sleep(Duration::from_millis(250));
+        let len = match fs::metadata(path) {
+            Ok(m) => m.len(),
+            Err(_) => continue,
+        };
+        if len < offset {
+            // Log was rotated or truncated; start from the beginning of the new file
+            offset = 0;
+        }
+        if len == offset {
+            continue;
+        }
+        if let Ok(mut file) = File::open(path) {
+            if file.seek(SeekFrom::Start(offset)).is_ok() {
+                let mut buf = Vec::new();
+                if file.read_to_end(&mut buf).is_ok() {
+                    print!("{}", String::from_utf8_lossy(&buf));
+                    offset += buf.len() as u64;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_tail_lines_returns_last_n() {
+        let path = env_temp_file("tail");
+        let mut f = File::create(&path).unwrap();
+        for i in 0..10 {
+            writeln!(f, "line {}", i).unwrap();
+        }
+        assert_eq!(tail_lines(&path, 3), vec!["line 7", "line 8", "line 9"]);
+        assert_eq!(tail_lines(&path, 100).len(), 10);
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_rotate_if_large_keeps_writer_appending() {
+        let path = env_temp_file("rotate");
+        let rotated = path.with_extension("log.1");
+        let mut writer = OpenOptions::new().create(true).append(true).open(&path).unwrap();
+        writeln!(writer, "old line that makes the log too large").unwrap();
+        rotate_if_large(&path, 1000).unwrap();
+        assert!(!rotated.exists());
+
+        rotate_if_large(&path, 10).unwrap();
+        assert_eq!(fs::read_to_string(&rotated).unwrap(), "old line that makes the log too large\n");
+        writeln!(writer, "new line").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new line\n");
+        fs::remove_file(&path).ok();
+        fs::remove_file(&rotated).ok();
+    }
+
+    #[test]
+    fn test_tail_lines_missing_file() {
+        assert!(tail_lines(Path::new("/nonexistent/agent-browser.log"), 5).is_empty());
+    }
+
+    fn env_temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("agent-browser-test-{}-{}.log", name, std::process::id()))
+    }
+}
@@ -1,28 +1,64 @@
+mod assert;
+mod budget;
 mod color;
 mod commands;
 mod connection;
+mod debug;
+mod doctor;
+mod error;
+mod export;
 mod flags;
+mod history;
 mod install;
+mod interrupt;
+mod lock;
+mod logs;
 mod output;
+mod recovery;
+mod refs;
+mod retry;
+mod runner;
+mod script;
+mod session;
+mod snapshot;
+mod visual;
 
 use serde_json::json;
 use std::env;
 use std::fs;
+use std::path::Path;
 use std::process::exit;
+use std::thread;
+use std::time::{Duration, Instant};
 
 #[cfg(unix)]
 use libc;
 
-#[cfg(windows)]
-use windows_sys::Win32::Foundation::CloseHandle;
-#[cfg(windows)]
-use windows_sys::Win32::System::Threading::{OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION};
-
+use assert::run_assert;
 use commands::{gen_id, parse_command, ParseError};
-use connection::{ensure_daemon, get_socket_dir, send_command};
-use flags::{clean_args, parse_flags};
+use connection::{
+    ensure_daemon, get_socket_dir, is_daemon_alive, is_process_alive, query_daemon_status,
+    query_daemon_version, send_command, stop_daemon, terminate_process,
+};
+use doctor::run_doctor;
+use error::{error_envelope, fail, ErrorKind};
+use export::run_export;
+use flags::{clean_args, parse_duration_ms, parse_flags, Flags};
+use history::{record, run_history, run_replay, send_recorded};
 use install::run_install;
-use output::{print_command_help, print_help, print_response, print_version};
+use lock::{lock_session, DEFAULT_LOCK_TIMEOUT};
+use logs::run_logs;
+use output::{format_duration, print_command_help, print_help, print_response, print_version};
+use recovery::{
+    autosave_age, autosave_if_due, autosave_state_path, capture_tabs, changes_state, clear_autosave,
+    clear_crash_marker, detect_crash, load_autosave, restore_tabs, Crash, SavedTab,
+};
+use refs::check_refs;
+use retry::{is_unsafe, send_with_retry, DEFAULT_RETRY_DELAY_MS};
+use runner::run_tests;
+use session::{remove_session_files, run_session};
+use snapshot::{finish_snapshot, run_snapshot_diff};
+use visual::{fail_compare, finish_compare};
 
 fn parse_proxy(proxy_str: &str) -> serde_json::Value {
     let Some(protocol_end) = proxy_str.find("://") else {
@@ -54,72 +90,218 @@ fn parse_proxy(proxy_str: &str) -> serde_json::Value {
     })
 }
 
-fn run_session(args: &[String], session: &str, json_mode: bool) {
-    let subcommand = args.get(1).map(|s| s.as_str());
-
-    match subcommand {
-        Some("list") => {
-            let socket_dir = get_socket_dir();
-            let mut sessions: Vec<String> = Vec::new();
-
-            if let Ok(entries) = fs::read_dir(&socket_dir) {
-                for entry in entries.flatten() {
-                    let name = entry.file_name().to_string_lossy().to_string();
-                    // Look for pid files in socket directory
-                    if name.ends_with(".pid") {
-                        let session_name = name.strip_suffix(".pid").unwrap_or("");
-                        if !session_name.is_empty() {
-                            // Check if session is actually running
-                            let pid_path = socket_dir.join(&name);
-                            if let Ok(pid_str) = fs::read_to_string(&pid_path) {
-                                if let Ok(pid) = pid_str.trim().parse::<u32>() {
-                                    #[cfg(unix)]
-                                    let running = unsafe { libc::kill(pid as i32, 0) == 0 };
-                                    #[cfg(windows)]
-                                    let running = unsafe {
-                                        let handle =
-                                            OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
-                                        if handle != 0 {
-                                            CloseHandle(handle);
-                                            true
-                                        } else {
-                                            false
-                                        }
-                                    };
-                                    if running {
-                                        sessions.push(session_name.to_string());
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
+/// Build the launch command for the browser options given on the command line
+fn build_launch_command(flags: &Flags) -> serde_json::Value {
+    let mut launch_cmd = json!({
+        "id": gen_id(),
+        "action": "launch",
+        "headless": !flags.headed
+    });
 
-            if json_mode {
-                println!(
-                    r#"{{"success":true,"data":{{"sessions":{}}}}}"#,
-                    serde_json::to_string(&sessions).unwrap_or_default()
-                );
-            } else if sessions.is_empty() {
-                println!("No active sessions");
-            } else {
-                println!("Active sessions:");
-                for s in &sessions {
-                    let marker = if s == session { color::cyan("→") } else { " ".to_string() };
-                    println!("{} {}", marker, s);
-                }
+    let cmd_obj = launch_cmd.as_object_mut()
+        .expect("json! macro guarantees object type");
+
+    // Add profile path if specified
+    if let Some(ref profile_path) = flags.profile {
+        cmd_obj.insert("profile".to_string(), json!(profile_path));
+    }
+
+    if let Some(ref path) = flags.executable_path {
+        cmd_obj.insert("executablePath".to_string(), json!(path));
+    }
+
+    if !flags.extensions.is_empty() {
+        cmd_obj.insert("extensions".to_string(), json!(flags.extensions));
+    }
+
+    if let Some(ref proxy_str) = flags.proxy {
+        let mut proxy_obj = parse_proxy(proxy_str);
+        // Add bypass if specified
+        if let Some(ref bypass) = flags.proxy_bypass {
+            if let Some(obj) = proxy_obj.as_object_mut() {
+                obj.insert("bypass".to_string(), json!(bypass));
             }
         }
-        None | Some(_) => {
-            // Just show current session
-            if json_mode {
-                println!(r#"{{"success":true,"data":{{"session":"{}"}}}}"#, session);
-            } else {
-                println!("{}", session);
+        cmd_obj.insert("proxy".to_string(), proxy_obj);
+    }
+
+    if let Some(ref ua) = flags.user_agent {
+        cmd_obj.insert("userAgent".to_string(), json!(ua));
+    }
+
+    if let Some(ref a) = flags.args {
+        // Parse args (comma or newline separated)
+        let args_vec: Vec<String> = a
+            .split(&[',', '\n'][..])
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+        cmd_obj.insert("args".to_string(), json!(args_vec));
+    }
+
+    launch_cmd
+}
+
+/// Start a daemon with the current launch options, seed the browser with a saved
+/// storage state and reopen the given tabs
+fn start_with_state(
+    flags: &Flags,
+    idle_timeout_ms: Option<u64>,
+    state_path: Option<&Path>,
+    tabs: &[SavedTab],
+) -> Result<(), String> {
+    let session = flags.session.as_str();
+    ensure_daemon(
+        session,
+        flags.headed,
+        flags.executable_path.as_deref(),
+        &flags.extensions,
+        flags.args.as_deref(),
+        flags.user_agent.as_deref(),
+        flags.proxy.as_deref(),
+        flags.proxy_bypass.as_deref(),
+        idle_timeout_ms,
+    )?;
+
+    let mut launch_cmd = build_launch_command(flags);
+    if let Some(path) = state_path {
+        launch_cmd["storageState"] = json!(path);
+    }
+    match send_command(launch_cmd, session)? {
+        resp if resp.success => {}
+        resp => return Err(resp.error.unwrap_or_else(|| "Launch failed".to_string())),
+    }
+
+    restore_tabs(session, tabs, flags.json);
+    Ok(())
+}
+
+/// Restart the session's daemon with the current launch options, carrying over
+/// cookies, storage, open tab URLs and the active tab. Returns the number of tabs restored.
+fn relaunch(flags: &Flags, idle_timeout_ms: Option<u64>) -> Result<usize, String> {
+    if flags.cdp.is_some() || flags.provider.is_some() {
+        return Err("relaunch is not supported with --cdp or -p/--provider".to_string());
+    }
+    let session = flags.session.as_str();
+    let state_path = get_socket_dir().join(format!("{}.relaunch-state.json", session));
+    let mut tabs = Vec::new();
+    let mut have_state = false;
+
+    if is_daemon_alive(session) {
+        let save = json!({ "id": gen_id(), "action": "state_save", "path": state_path });
+        have_state = matches!(send_command(save, session), Ok(ref r) if r.success);
+        tabs = capture_tabs(session);
+        stop_daemon(session)?;
+    }
+
+    let result = start_with_state(
+        flags,
+        idle_timeout_ms,
+        have_state.then_some(state_path.as_path()),
+        &tabs,
+    );
+    let _ = fs::remove_file(&state_path);
+    result.map(|_| tabs.len())
+}
+
+/// Replace a daemon from another CLI version. Returns the number of tabs carried over
+/// (none with --cdp or -p/--provider, where the browser outlives the daemon anyway).
+fn restart_for_version(flags: &Flags, idle_timeout_ms: Option<u64>) -> Result<usize, String> {
+    if flags.cdp.is_none() && flags.provider.is_none() {
+        return relaunch(flags, idle_timeout_ms);
+    }
+    stop_daemon(&flags.session)?;
+    ensure_daemon(
+        &flags.session,
+        flags.headed,
+        flags.executable_path.as_deref(),
+        &flags.extensions,
+        flags.args.as_deref(),
+        flags.user_agent.as_deref(),
+        flags.proxy.as_deref(),
+        flags.proxy_bypass.as_deref(),
+        idle_timeout_ms,
+    )?;
+    Ok(0)
+}
+
+/// Replace a crashed session's daemon and restore its last autosave.
+/// Returns the number of tabs restored and the age of the snapshot, if there was one.
+fn recover(
+    flags: &Flags,
+    idle_timeout_ms: Option<u64>,
+    crash: &Crash,
+) -> Result<Option<(usize, Duration)>, String> {
+    if flags.cdp.is_some() || flags.provider.is_some() {
+        return Err("--recover is not supported with --cdp or -p/--provider".to_string());
+    }
+    let session = flags.session.as_str();
+    if let Some(pid) = crash.hung_pid {
+        terminate_process(pid, true);
+        for _ in 0..30 {
+            if !is_process_alive(pid) {
+                break;
             }
+            thread::sleep(Duration::from_millis(100));
         }
     }
+    remove_session_files(session);
+
+    let autosave = load_autosave(session);
+    let state_path = autosave_state_path(session);
+    let have_state = autosave.is_some() && state_path.exists();
+    let tabs = autosave.as_ref().map(|a| a.tabs.clone()).unwrap_or_default();
+    start_with_state(flags, idle_timeout_ms, have_state.then_some(state_path.as_path()), &tabs)?;
+    Ok(autosave.map(|a| (a.tabs.len(), autosave_age(&a))))
+}
+
+/// Launch flags on the command line that the running daemon was not started
+/// with. `launch_options` is `launchOptions` from the daemon's `status`; if it
+/// could not be read, every launch flag given counts.
+fn unapplied_launch_flags(
+    flags: &Flags,
+    idle_timeout_ms: Option<u64>,
+    launch_options: Option<&serde_json::Value>,
+) -> Vec<&'static str> {
+    let Some(opts) = launch_options else {
+        return [
+            flags.headed.then_some("--headed"),
+            flags.executable_path.as_ref().map(|_| "--executable-path"),
+            (!flags.extensions.is_empty()).then_some("--extension"),
+            flags.profile.as_ref().map(|_| "--profile"),
+            flags.args.as_ref().map(|_| "--args"),
+            flags.user_agent.as_ref().map(|_| "--user-agent"),
+            flags.proxy.as_ref().map(|_| "--proxy"),
+            flags.proxy_bypass.as_ref().map(|_| "--proxy-bypass"),
+            idle_timeout_ms.map(|_| "--idle-timeout"),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+    };
+    let differs = |value: &Option<String>, key: &str| {
+        value.as_deref().is_some_and(|v| opts.get(key).and_then(|o| o.as_str()) != Some(v))
+    };
+    let running_extensions: Vec<&str> = opts
+        .get("extensions")
+        .and_then(|e| e.as_array())
+        .map(|e| e.iter().filter_map(|v| v.as_str()).collect())
+        .unwrap_or_default();
+    let idle_differs = |ms: u64| opts.get("idleTimeoutMs").and_then(|v| v.as_u64()).unwrap_or(0) != ms;
+    [
+        (flags.headed && opts.get("headed") != Some(&json!(true))).then_some("--headed"),
+        differs(&flags.executable_path, "executablePath").then_some("--executable-path"),
+        (!flags.extensions.is_empty() && flags.extensions != running_extensions).then_some("--extension"),
+        differs(&flags.profile, "profile").then_some("--profile"),
+        differs(&flags.args, "args").then_some("--args"),
+        differs(&flags.user_agent, "userAgent").then_some("--user-agent"),
+        differs(&flags.proxy, "proxy").then_some("--proxy"),
+        differs(&flags.proxy_bypass, "proxyBypass").then_some("--proxy-bypass"),
+        idle_timeout_ms.filter(|ms| idle_differs(*ms)).map(|_| "--idle-timeout"),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
 }
 
 fn main() {
@@ -132,6 +314,11 @@ fn main() {
     let args: Vec<String> = env::args().skip(1).collect();
     let flags = parse_flags(&args);
     let clean = clean_args(&args);
+    let command = clean.first().map(|s| s.as_str());
+
+    if flags.debug {
+        debug::enable();
+    }
 
     let has_help = args.iter().any(|a| a == "--help" || a == "-h");
     let has_version = args.iter().any(|a| a == "--version" || a == "-V");
@@ -147,7 +334,9 @@ fn main() {
     }
 
     if has_version {
-        print_version();
+        // Report the running daemon too, but never start one just to print versions
+        let daemon = query_daemon_version(&flags.session).ok().flatten();
+        print_version(&flags.session, daemon.as_ref(), flags.json);
         return;
     }
 
@@ -163,34 +352,229 @@ fn main() {
         return;
     }
 
+    // Handle doctor separately (diagnoses the installation without a daemon)
+    if clean.first().map(|s| s.as_str()) == Some("doctor") {
+        run_doctor(flags.json);
+        return;
+    }
+
+    // Handle logs separately (reads the daemon log file directly)
+    if clean.first().map(|s| s.as_str()) == Some("logs") {
+        run_logs(&args, &flags.session, flags.json);
+        return;
+    }
+
+    // Handle history separately (reads the session's journal directly)
+    if clean.first().map(|s| s.as_str()) == Some("history") {
+        run_history(&clean, &flags.session, flags.json);
+        return;
+    }
+
+    // Handle export separately (only asks a running session for its refs)
+    if clean.first().map(|s| s.as_str()) == Some("export") {
+        run_export(&clean, &flags.session, flags.json);
+        return;
+    }
+
     // Handle session separately (doesn't need daemon)
-    if clean.get(0).map(|s| s.as_str()) == Some("session") {
+    if clean.first().map(|s| s.as_str()) == Some("session") {
         run_session(&clean, &flags.session, flags.json);
         return;
     }
 
+    // Handle snapshot diff separately (compares two saved snapshots offline)
+    if clean.first().map(|s| s.as_str()) == Some("snapshot") && clean.get(1).map(|s| s.as_str()) == Some("diff") {
+        run_snapshot_diff(&clean, flags.json);
+        return;
+    }
+
+    // Handle test separately (each step runs as its own agent-browser process)
+    if clean.first().map(|s| s.as_str()) == Some("test") {
+        run_tests(&clean, &flags);
+        return;
+    }
+
+    let idle_timeout_ms = match flags.idle_timeout.as_deref().map(parse_duration_ms) {
+        None => None,
+        Some(Some(ms)) => Some(ms),
+        Some(None) => {
+            let msg = format!(
+                "Invalid idle timeout: '{}' (use e.g. 90s, 30m, 2h)",
+                flags.idle_timeout.as_deref().unwrap_or_default()
+            );
+            fail(ErrorKind::ParseError, &msg, command, flags.json);
+        }
+    };
+
+    let lock_timeout = match flags.lock_timeout.as_deref().map(parse_duration_ms) {
+        None => DEFAULT_LOCK_TIMEOUT,
+        Some(Some(ms)) => Duration::from_millis(ms),
+        Some(None) => {
+            let msg = format!(
+                "Invalid lock timeout: '{}' (use e.g. 500ms, 10s, 2m)",
+                flags.lock_timeout.as_deref().unwrap_or_default()
+            );
+            fail(ErrorKind::ParseError, &msg, command, flags.json);
+        }
+    };
+
+    let retries = match flags.retry.as_deref().map(|s| s.parse::<u32>()) {
+        None => 0,
+        Some(Ok(n)) => n,
+        Some(Err(_)) => {
+            let msg = format!(
+                "Invalid retry count: '{}' (expected a number of retries, e.g. 3)",
+                flags.retry.as_deref().unwrap_or_default()
+            );
+            fail(ErrorKind::ParseError, &msg, command, flags.json);
+        }
+    };
+
+    let retry_delay_ms = match flags.retry_delay.as_deref().map(|s| s.parse::<u64>()) {
+        None => DEFAULT_RETRY_DELAY_MS,
+        Some(Ok(ms)) => ms,
+        Some(Err(_)) => {
+            let msg = format!(
+                "Invalid retry delay: '{}' (expected milliseconds, e.g. 500)",
+                flags.retry_delay.as_deref().unwrap_or_default()
+            );
+            fail(ErrorKind::ParseError, &msg, command, flags.json);
+        }
+    };
+
+    // Serialize with other invocations on this session, including daemon startup,
+    // until this process exits
+    let _session_lock = if flags.no_lock {
+        None
+    } else {
+        match lock_session(&flags.session, lock_timeout) {
+            Ok(lock) => Some(lock),
+            Err(e) => fail(e.kind, &e.message, command, flags.json),
+        }
+    };
+
+    // From here on Ctrl-C cancels the request the daemon is working on
+    interrupt::install();
+
+    // A leftover pid file or crash marker means the previous daemon died
+    if let Some(crash) = detect_crash(&flags.session) {
+        let autosave = load_autosave(&flags.session);
+        if flags.recover {
+            match recover(&flags, idle_timeout_ms, &crash) {
+                Ok(restored) => {
+                    if !flags.json {
+                        let detail = match restored {
+                            Some((tabs, age)) => format!(
+                                "restored {} tab{} and cookies saved {} ago",
+                                tabs,
+                                if tabs == 1 { "" } else { "s" },
+                                format_duration(age)
+                            ),
+                            None => "no autosave found, started a fresh browser".to_string(),
+                        };
+                        eprintln!(
+                            "{} Recovered session {} ({}): {}",
+                            color::success_indicator(),
+                            flags.session,
+                            crash.reason,
+                            detail
+                        );
+                    }
+                }
+                Err(e) => {
+                    let msg = format!("Could not recover session '{}': {}", flags.session, e);
+                    fail(ErrorKind::SessionCrashed, &msg, command, flags.json);
+                }
+            }
+        } else if crash.hung_pid.is_some() || autosave.is_some() {
+            // Don't silently replace a session that can still be recovered
+            let restore = match autosave {
+                Some(ref a) => format!(
+                    " and restore {} tab{} and cookies saved {} ago",
+                    a.tabs.len(),
+                    if a.tabs.len() == 1 { "" } else { "s" },
+                    format_duration(autosave_age(a))
+                ),
+                None => String::new(),
+            };
+            let fresh = if crash.hung_pid.is_some() {
+                format!("agent-browser session kill {}", flags.session)
+            } else {
+                "agent-browser session prune".to_string()
+            };
+            let msg = format!(
+                "Session '{}' crashed: {}. Rerun with --recover to restart it{}, or run '{}' to start fresh",
+                flags.session, crash.reason, restore, fresh
+            );
+            fail(ErrorKind::SessionCrashed, &msg, command, flags.json);
+        } else if !flags.json {
+            eprintln!(
+                "{} Session '{}' crashed: {}. Starting a fresh browser.",
+                color::warning_indicator(),
+                flags.session,
+                crash.reason
+            );
+        }
+        clear_crash_marker(&flags.session);
+    }
+
+    // Handle relaunch separately (restarts the daemon instead of sending a command)
+    if clean.first().map(|s| s.as_str()) == Some("relaunch") {
+        match relaunch(&flags, idle_timeout_ms) {
+            Ok(tabs) => {
+                if flags.json {
+                    println!("{}", json!({ "success": true, "data": { "relaunched": true, "tabs": tabs } }));
+                } else {
+                    println!(
+                        "{} Relaunched session {} ({} tab{} restored)",
+                        color::success_indicator(),
+                        flags.session,
+                        tabs,
+                        if tabs == 1 { "" } else { "s" }
+                    );
+                }
+            }
+            Err(e) => fail(ErrorKind::ActionFailed, &e, command, flags.json),
+        }
+        return;
+    }
+
     let cmd = match parse_command(&clean, &flags) {
         Ok(c) => c,
         Err(e) => {
             if flags.json {
-                let error_type = match &e {
+                let reason = match &e {
                     ParseError::UnknownCommand { .. } => "unknown_command",
                     ParseError::UnknownSubcommand { .. } => "unknown_subcommand",
                     ParseError::MissingArguments { .. } => "missing_arguments",
                     ParseError::InvalidValue { .. } => "invalid_value",
                 };
-                println!(
-                    r#"{{"success":false,"error":"{}","type":"{}"}}"#,
-                    e.format().replace('\n', " "),
-                    error_type
-                );
-            } else {
-                eprintln!("{}", color::red(&e.format()));
+                let mut envelope = error_envelope(ErrorKind::ParseError, &e.format(), command);
+                envelope["reason"] = json!(reason);
+                println!("{}", envelope);
+                exit(ErrorKind::ParseError.exit_code());
             }
-            exit(1);
+            fail(ErrorKind::ParseError, &e.format(), command, false);
         }
     };
 
+    // Replaying into a session that already has state would not reproduce anything
+    if cmd.get("action").and_then(|v| v.as_str()) == Some("replay") && is_daemon_alive(&flags.session) {
+        let msg = format!(
+            "Session '{}' is already running; replay needs a fresh session. Use --session <new name>, or close it first.",
+            flags.session
+        );
+        fail(ErrorKind::ActionFailed, &msg, command, flags.json);
+    }
+
+    if retries > 0 && !flags.retry_unsafe && is_unsafe(&cmd) {
+        let msg = format!(
+            "'{}' is not safe to retry (repeating it after a partial failure can repeat its effect). Use --retry-unsafe to retry it anyway.",
+            command.unwrap_or_default()
+        );
+        fail(ErrorKind::ParseError, &msg, command, flags.json);
+    }
+
     let daemon_result = match ensure_daemon(
         &flags.session,
         flags.headed,
@@ -200,37 +584,63 @@ fn main() {
         flags.user_agent.as_deref(),
         flags.proxy.as_deref(),
         flags.proxy_bypass.as_deref(),
+        idle_timeout_ms,
     ) {
         Ok(result) => result,
-        Err(e) => {
-            if flags.json {
-                println!(r#"{{"success":false,"error":"{}"}}"#, e);
-            } else {
-                eprintln!("{} {}", color::error_indicator(), e);
+        Err(e) => fail(ErrorKind::DaemonStartFailed, &e, command, flags.json),
+    };
+
+    // A daemon from another CLI version is restarted the way --relaunch does it,
+    // keeping tabs and cookies, and --json output says so in `restarted`
+    let mut restarted = None;
+    match daemon_result.version_mismatch {
+        Some(ref v) if !flags.no_auto_restart => {
+            let tabs = match restart_for_version(&flags, idle_timeout_ms) {
+                Ok(tabs) => tabs,
+                Err(e) => fail(ErrorKind::DaemonStartFailed, &e, command, flags.json),
+            };
+            if !flags.json {
+                eprintln!(
+                    "{} Restarted daemon {} to match CLI version {} ({} tab(s) and cookies carried over)",
+                    color::warning_indicator(),
+                    v,
+                    env!("CARGO_PKG_VERSION"),
+                    tabs
+                );
             }
-            exit(1);
+            restarted = Some(json!({ "from": v, "to": env!("CARGO_PKG_VERSION"), "tabs": tabs }));
         }
-    };
+        Some(ref v) if !flags.json => {
+            eprintln!(
+                "{} Daemon version {} does not match CLI version {}. Run 'agent-browser close' to restart it.",
+                color::warning_indicator(),
+                v,
+                env!("CARGO_PKG_VERSION")
+            );
+        }
+        _ => {}
+    }
 
     // Warn if launch-time options were specified but daemon was already running
-    if daemon_result.already_running {
-        let has_extensions = !flags.extensions.is_empty();
-        let ignored_flags: Vec<&str> = [
-            flags.executable_path.as_ref().map(|_| "--executable-path"),
-            if has_extensions { Some("--extension") } else { None },
-            flags.profile.as_ref().map(|_| "--profile"),
-            flags.args.as_ref().map(|_| "--args"),
-            flags.user_agent.as_ref().map(|_| "--user-agent"),
-            flags.proxy.as_ref().map(|_| "--proxy"),
-            flags.proxy_bypass.as_ref().map(|_| "--proxy-bypass"),
-        ]
-        .into_iter()
-        .flatten()
-        .collect();
-
-        if !ignored_flags.is_empty() && !flags.json {
+    if daemon_result.already_running && restarted.is_none() {
+        let status = query_daemon_status(&flags.session).ok().flatten();
+        let launch_options = status.as_ref().and_then(|s| s.get("launchOptions"));
+        let ignored_flags = unapplied_launch_flags(&flags, idle_timeout_ms, launch_options);
+
+        if !ignored_flags.is_empty() && flags.relaunch {
+            if let Err(e) = relaunch(&flags, idle_timeout_ms) {
+                fail(ErrorKind::DaemonStartFailed, &e, command, flags.json);
+            }
+            if !flags.json {
+                eprintln!(
+                    "{} Relaunched to apply {}",
+                    color::success_indicator(),
+                    ignored_flags.join(", ")
+                );
+            }
+        } else if !ignored_flags.is_empty() && !flags.json {
             eprintln!(
-                "{} {} ignored: daemon already running. Use 'agent-browser close' first to restart with new options.",
+                "{} {} ignored: daemon already running. Use --relaunch to apply them and keep tabs and cookies, or 'agent-browser close' first.",
                 color::warning_indicator(),
                 ignored_flags.join(", ")
             );
@@ -240,22 +650,12 @@ fn main() {
     // Validate mutually exclusive options
     if flags.cdp.is_some() && flags.provider.is_some() {
         let msg = "Cannot use --cdp and -p/--provider together";
-        if flags.json {
-            println!(r#"{{"success":false,"error":"{}"}}"#, msg);
-        } else {
-            eprintln!("\x1b[31m✗\x1b[0m {}", msg);
-        }
-        exit(1);
+        fail(ErrorKind::ParseError, msg, command, flags.json);
     }
 
     if flags.provider.is_some() && !flags.extensions.is_empty() {
         let msg = "Cannot use --extension with -p/--provider (extensions require local browser)";
-        if flags.json {
-            println!(r#"{{"success":false,"error":"{}"}}"#, msg);
-        } else {
-            eprintln!("\x1b[31m✗\x1b[0m {}", msg);
-        }
-        exit(1);
+        fail(ErrorKind::ParseError, msg, command, flags.json);
     }
 
     // Connect via CDP if --cdp flag is set
@@ -277,24 +677,14 @@ fn main() {
             let cdp_port: u16 = match cdp_value.parse::<u32>() {
                 Ok(p) if p == 0 => {
                     let msg = "Invalid CDP port: port must be greater than 0".to_string();
-                    if flags.json {
-                        println!(r#"{{"success":false,"error":"{}"}}"#, msg);
-                    } else {
-                        eprintln!("{} {}", color::error_indicator(), msg);
-                    }
-                    exit(1);
+                    fail(ErrorKind::ParseError, &msg, command, flags.json);
                 }
                 Ok(p) if p > 65535 => {
                     let msg = format!(
                         "Invalid CDP port: {} is out of range (valid range: 1-65535)",
                         p
                     );
-                    if flags.json {
-                        println!(r#"{{"success":false,"error":"{}"}}"#, msg);
-                    } else {
-                        eprintln!("{} {}", color::error_indicator(), msg);
-                    }
-                    exit(1);
+                    fail(ErrorKind::ParseError, &msg, command, flags.json);
                 }
                 Ok(p) => p as u16,
                 Err(_) => {
@@ -302,12 +692,7 @@ fn main() {
                         "Invalid CDP value: '{}' is not a valid port number or URL",
                         cdp_value
                     );
-                    if flags.json {
-                        println!(r#"{{"success":false,"error":"{}"}}"#, msg);
-                    } else {
-                        eprintln!("{} {}", color::error_indicator(), msg);
-                    }
-                    exit(1);
+                    fail(ErrorKind::ParseError, &msg, command, flags.json);
                 }
             };
             json!({
@@ -317,22 +702,13 @@ fn main() {
             })
         };
 
-        let err = match send_command(launch_cmd, &flags.session) {
-            Ok(resp) if resp.success => None,
-            Ok(resp) => Some(
-                resp.error
-                    .unwrap_or_else(|| "CDP connection failed".to_string()),
-            ),
-            Err(e) => Some(e.to_string()),
-        };
-
-        if let Some(msg) = err {
-            if flags.json {
-                println!(r#"{{"success":false,"error":"{}"}}"#, msg);
-            } else {
-                eprintln!("{} {}", color::error_indicator(), msg);
+        match send_recorded(launch_cmd, &flags.session) {
+            Ok(resp) if resp.success => {}
+            Ok(resp) => {
+                let msg = resp.error.unwrap_or_else(|| "CDP connection failed".to_string());
+                fail(ErrorKind::ActionFailed, &msg, command, flags.json);
             }
-            exit(1);
+            Err(e) => fail(e.kind, &e.message, command, flags.json),
         }
     }
 
@@ -344,90 +720,126 @@ fn main() {
             "provider": provider
         });
 
-        let err = match send_command(launch_cmd, &flags.session) {
-            Ok(resp) if resp.success => None,
-            Ok(resp) => Some(resp.error.unwrap_or_else(|| "Provider connection failed".to_string())),
-            Err(e) => Some(e.to_string()),
-        };
-
-        if let Some(msg) = err {
-            if flags.json {
-                println!(r#"{{"success":false,"error":"{}"}}"#, msg);
-            } else {
-                eprintln!("\x1b[31m✗\x1b[0m {}", msg);
+        match send_recorded(launch_cmd, &flags.session) {
+            Ok(resp) if resp.success => {}
+            Ok(resp) => {
+                let msg = resp.error.unwrap_or_else(|| "Provider connection failed".to_string());
+                fail(ErrorKind::ActionFailed, &msg, command, flags.json);
             }
-            exit(1);
+            Err(e) => fail(e.kind, &e.message, command, flags.json),
         }
     }
 
     // Launch headed browser or configure browser options (without CDP or provider)
     if (flags.headed || flags.profile.is_some() || flags.proxy.is_some() || flags.args.is_some() || flags.user_agent.is_some()) && flags.cdp.is_none() && flags.provider.is_none() {
-        let mut launch_cmd = json!({
-            "id": gen_id(),
-            "action": "launch",
-            "headless": !flags.headed
-        });
-
-        let cmd_obj = launch_cmd.as_object_mut()
-            .expect("json! macro guarantees object type");
-
-        // Add profile path if specified
-        if let Some(ref profile_path) = flags.profile {
-            cmd_obj.insert("profile".to_string(), json!(profile_path));
-        }
-
-        if let Some(ref proxy_str) = flags.proxy {
-            let mut proxy_obj = parse_proxy(proxy_str);
-            // Add bypass if specified
-            if let Some(ref bypass) = flags.proxy_bypass {
-                if let Some(obj) = proxy_obj.as_object_mut() {
-                    obj.insert("bypass".to_string(), json!(bypass));
-                }
+        let launch_cmd = build_launch_command(&flags);
+        if let Err(e) = send_recorded(launch_cmd, &flags.session) {
+            if !flags.json {
+                eprintln!("{} Could not configure browser: {}", color::warning_indicator(), e);
             }
-            cmd_obj.insert("proxy".to_string(), proxy_obj);
         }
+    }
 
-        if let Some(ref ua) = flags.user_agent {
-            cmd_obj.insert("userAgent".to_string(), json!(ua));
+    // Refs only hold on the page their snapshot was taken on
+    if let Some(msg) = check_refs(&cmd, &flags.session) {
+        if !flags.allow_stale_refs {
+            let msg = format!("{}. Run 'snapshot' again to get fresh refs (or pass --allow-stale-refs)", msg);
+            fail(ErrorKind::StaleRef, &msg, command, flags.json);
         }
-
-        if let Some(ref a) = flags.args {
-            // Parse args (comma or newline separated)
-            let args_vec: Vec<String> = a
-                .split(&[',', '\n'][..])
-                .map(|s| s.trim().to_string())
-                .filter(|s| !s.is_empty())
-                .collect();
-            cmd_obj.insert("args".to_string(), json!(args_vec));
+        if !flags.json {
+            eprintln!("{} {}", color::warning_indicator(), msg);
         }
+    }
 
-        if let Err(e) = send_command(launch_cmd, &flags.session) {
-            if !flags.json {
-                eprintln!("{} Could not configure browser: {}", color::warning_indicator(), e);
-            }
+    // Assertions poll the daemon themselves and exit with their own code on failure
+    if cmd.get("action").and_then(|v| v.as_str()) == Some("assert") {
+        let started = Instant::now();
+        let result = run_assert(&cmd, &flags.session, flags.json);
+        record(&flags.session, &cmd, Some(&clean), started, result.as_ref().err().map(|e| e.as_str()));
+        if result.is_err() {
+            exit(ErrorKind::AssertionFailed.exit_code());
         }
+        return;
     }
 
-    match send_command(cmd.clone(), &flags.session) {
-        Ok(resp) => {
-            let success = resp.success;
+    if cmd.get("action").and_then(|v| v.as_str()) == Some("replay") {
+        run_replay(&cmd, &flags.session, flags.json);
+        return;
+    }
+
+    let started = Instant::now();
+    let (result, failed_attempts) =
+        send_with_retry(cmd.clone(), &flags.session, retries, retry_delay_ms, flags.json);
+    let error = match &result {
+        Ok(resp) if resp.success => None,
+        Ok(resp) => Some(resp.error.as_deref().unwrap_or("Unknown error")),
+        Err(e) => Some(e.message.as_str()),
+    };
+    record(&flags.session, &cmd, Some(&clean), started, error);
+    // With --retry, --json output says how many attempts it took and why earlier ones failed
+    let retry_summary = (retries > 0).then(|| {
+        json!({ "attempts": failed_attempts.len() + 1, "failures": failed_attempts })
+    });
+
+    match result {
+        Ok(mut resp) => {
+            if !resp.success {
+                let msg = resp.error.as_deref().unwrap_or("Unknown error");
+                fail_with_retries(ErrorKind::ActionFailed, msg, command, flags.json, retry_summary);
+            }
             // Extract action for context-specific output handling
             let action = cmd
                 .get("action")
                 .and_then(|v| v.as_str());
-            print_response(&resp, flags.json, action);
-            if !success {
-                exit(1);
+            if action == Some("snapshot") {
+                if let Err(e) = finish_snapshot(&cmd, &mut resp, &flags.session, flags.json) {
+                    fail(ErrorKind::ActionFailed, &e, command, flags.json);
+                }
             }
-        }
-        Err(e) => {
-            if flags.json {
-                println!(r#"{{"success":false,"error":"{}"}}"#, e);
+            if action == Some("screenshot") && cmd.get("compare").is_some() {
+                if let Err(failure) = finish_compare(&cmd, &mut resp) {
+                    fail_compare(failure, flags.json);
+                }
+            }
+            if flags.json && (retry_summary.is_some() || restarted.is_some()) {
+                let mut out = json!(resp);
+                if let Some(summary) = retry_summary {
+                    out["retry"] = summary;
+                }
+                if let Some(restarted) = restarted {
+                    out["restarted"] = restarted;
+                }
+                println!("{}", out);
             } else {
-                eprintln!("{} {}", color::error_indicator(), e);
+                print_response(&resp, flags.json, action);
             }
-            exit(1);
+            // Snapshot the session now and then so a crash can be recovered with --recover
+            if action == Some("close") {
+                clear_autosave(&flags.session);
+            } else if changes_state(action) {
+                autosave_if_due(&flags.session);
+            }
+        }
+        Err(e) => fail_with_retries(e.kind, &e.message, command, flags.json, retry_summary),
+    }
+}
+
+/// Like `fail`, but with the `--retry` summary added to the --json envelope
+fn fail_with_retries(
+    kind: ErrorKind,
+    msg: &str,
+    command: Option<&str>,
+    json_mode: bool,
+    retry_summary: Option<serde_json::Value>,
+) -> ! {
+    match retry_summary {
+        Some(summary) if json_mode => {
+            let mut envelope = error_envelope(kind, msg, command);
+            envelope["retry"] = summary;
+            println!("{}", envelope);
+            exit(kind.exit_code());
         }
+        _ => fail(kind, msg, command, json_mode),
     }
 }
 
@@ -435,6 +847,29 @@ fn main() {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_idle_timeout_matching_daemon_is_not_ignored() {
+        let flags = parse_flags(&["open".to_string(), "example.com".to_string()]);
+        let opts = json!({ "headed": false, "idleTimeoutMs": 600000 });
+        assert!(unapplied_launch_flags(&flags, Some(600000), Some(&opts)).is_empty());
+        assert_eq!(unapplied_launch_flags(&flags, Some(60000), Some(&opts)), vec!["--idle-timeout"]);
+        assert_eq!(unapplied_launch_flags(&flags, Some(60000), Some(&json!({}))), vec!["--idle-timeout"]);
+    }
+
+    #[test]
+    fn test_launch_flags_matching_daemon_are_not_ignored() {
+        let args: Vec<String> = ["open", "example.com", "--headed", "--profile", "~/p", "--proxy", "http://a:1"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let flags = parse_flags(&args);
+        let same = json!({ "headed": true, "profile": "~/p", "proxy": "http://a:1" });
+        assert!(unapplied_launch_flags(&flags, None, Some(&same)).is_empty());
+        let headless = json!({ "headed": false, "profile": "~/p", "proxy": "http://b:2" });
+        assert_eq!(unapplied_launch_flags(&flags, None, Some(&headless)), vec!["--headed", "--proxy"]);
+        assert_eq!(unapplied_launch_flags(&flags, None, None), vec!["--headed", "--profile", "--proxy"]);
+    }
+
     #[test]
     fn test_parse_proxy_simple() {
         let result = parse_proxy("http://proxy.com:8080");
@@ -1,5 +1,5 @@
 use crate::color;
-use crate::connection::Response;
+use crate::connection::{Response, VersionInfo};
 
 pub fn print_response(resp: &Response, json_mode: bool, action: Option<&str>) {
     if json_mode {
@@ -147,6 +147,15 @@ pub fn print_response(resp: &Response, json_mode: bool, action: Option<&str>) {
             );
             return;
         }
+        // Locators for an element (get locator)
+        if let (Some(css), Some(xpath)) = (data.get("css").and_then(|v| v.as_str()), data.get("xpath").and_then(|v| v.as_str())) {
+            if let Some(locator) = data.get("locator").and_then(|v| v.as_str()) {
+                println!("role:  {}", locator);
+            }
+            println!("css:   {}", css);
+            println!("xpath: {}", xpath);
+            return;
+        }
         // Element styles
         if let Some(elements) = data.get("elements").and_then(|v| v.as_array()) {
             for (i, el) in elements.iter().enumerate() {
@@ -235,15 +244,41 @@ pub fn print_response(resp: &Response, json_mode: bool, action: Option<&str>) {
                 return;
             }
         }
-        // Screenshot base64
+        // Screenshot compared with a baseline
+        if let Some(compare) = data.get("compare") {
+            if let Some(path) = data.get("path").and_then(|v| v.as_str()) {
+                println!("{} Screenshot saved to {}", color::success_indicator(), color::green(path));
+            }
+            for line in annotation_legend(data) {
+                println!("{}", line);
+            }
+            let baseline = compare["baseline"].as_str().unwrap_or("");
+            if compare["updated"] == true {
+                println!("{} Baseline updated: {}", color::success_indicator(), color::green(baseline));
+            } else {
+                let ignored = compare["antialiasedPixels"].as_u64().unwrap_or(0);
+                let note = if ignored > 0 { format!(" ({} anti-aliased pixels ignored)", ignored) } else { String::new() };
+                println!("{} Matches {}{}", color::success_indicator(), baseline, note);
+            }
+            return;
+        }
+        // Screenshot base64 (the --annotate legend goes to stderr to keep stdout decodable)
         if let Some(base64) = data.get("base64").and_then(|v| v.as_str()) {
             println!("{}", base64);
+            for line in annotation_legend(data) {
+                eprintln!("{}", line);
+            }
             return;
         }
         // Path-based operations (screenshot/pdf/trace/har/download/state/video)
         if let Some(path) = data.get("path").and_then(|v| v.as_str()) {
             match action.unwrap_or("") {
-                "screenshot" => println!("{} Screenshot saved to {}", color::success_indicator(), color::green(path)),
+                "screenshot" => {
+                    println!("{} Screenshot saved to {}", color::success_indicator(), color::green(path));
+                    for line in annotation_legend(data) {
+                        println!("{}", line);
+                    }
+                }
                 "pdf" => println!("{} PDF saved to {}", color::success_indicator(), color::green(path)),
                 "trace_stop" => println!("{} Trace saved to {}", color::success_indicator(), color::green(path)),
                 "har_stop" => println!("{} HAR saved to {}", color::success_indicator(), color::green(path)),
@@ -278,6 +313,28 @@ pub fn print_response(resp: &Response, json_mode: bool, action: Option<&str>) {
     }
 }
 
+/// One line per box drawn by `screenshot --annotate`: its label and the ref it stands for
+fn annotation_legend(data: &serde_json::Value) -> Vec<String> {
+    let Some(annotations) = data.get("annotations").and_then(|v| v.as_array()) else {
+        return Vec::new();
+    };
+    annotations
+        .iter()
+        .map(|a| {
+            let mut line = format!(
+                "[{}] @{} {}",
+                a["label"],
+                a["ref"].as_str().unwrap_or(""),
+                a["role"].as_str().unwrap_or("")
+            );
+            if let Some(name) = a["name"].as_str() {
+                line.push_str(&format!(" \"{}\"", name));
+            }
+            line
+        })
+        .collect()
+}
+
 /// Print command-specific help. Returns true if help was printed, false if command unknown.
 pub fn print_command_help(command: &str) -> bool {
     let help = match command {
@@ -733,13 +790,31 @@ Examples:
             r##"
 agent-browser screenshot - Take a screenshot
 
-Usage: agent-browser screenshot [path]
+Usage: agent-browser screenshot [selector] [path]
 
 Captures a screenshot of the current page. If no path is provided,
 outputs base64-encoded image data.
 
+With --annotate, each interactive ref from the last snapshot (an
+interactive snapshot is taken if there is none or the page has
+navigated since) gets a numbered box,
+numbered like the ref (12 for @e12), and a legend of the boxes is
+printed: on stdout after the path, on stderr with base64 output, or in
+data.annotations (label, ref, role, name, box) with --json.
+
+With --compare, the screenshot is checked against a baseline PNG pixel by
+pixel (anti-aliasing differences are ignored). If any pixels differ, a diff
+image with them in red is written and the command fails with exit code 7
+and the share of differing pixels.
+
 Options:
-  --full, -f           Capture full page (not just viewport)
+  --full, -f             Capture full page (not just viewport)
+  --annotate             Draw numbered boxes over interactive refs
+  --compare <baseline>   Compare with a baseline PNG
+  --threshold <0-1>      How different a pixel's color may be (default: 0.1)
+  --diff-out <file>      Where to write the diff image
+                         (default: <baseline>.diff.png next to the baseline)
+  --update               Write the screenshot to the baseline instead
 
 Global Options:
   --json               Output as JSON
@@ -749,6 +824,10 @@ Examples:
   agent-browser screenshot
   agent-browser screenshot ./screenshot.png
   agent-browser screenshot --full ./full-page.png
+  agent-browser screenshot --annotate ./page.png
+  agent-browser screenshot --compare baselines/home.png
+  agent-browser screenshot "#hero" --compare hero.png --threshold 0.2
+  agent-browser screenshot --compare baselines/home.png --update
 "##
         }
         "pdf" => {
@@ -780,11 +859,34 @@ Returns an accessibility tree representation of the page with element
 references (like @e1, @e2) that can be used in subsequent commands.
 Designed for AI agents to understand page structure.
 
+The last snapshot of each session is kept by the CLI, so --diff can print
+only the lines that were added (+), removed (-) or changed (~, shown as
+they are now) since the previous snapshot taken with the same options.
+Refs are renumbered on every snapshot; lines whose only change is their
+ref are not repeated, and the moved refs are listed on one line instead
+(e.g. "Renumbered: e5-e40 → e6-e41").
+
 Options:
   -i, --interactive    Only include interactive elements
   -c, --compact        Remove empty structural elements
   -d, --depth <n>      Limit tree depth
   -s, --selector <sel> Scope snapshot to CSS selector
+  --visible            Only elements that are at least partly in the viewport
+  --page <n>           Scroll the window to the nth viewport-sized page and
+                       snapshot what is visible there (implies --visible)
+  --diff               Only print what changed since the previous snapshot
+  --save <file>        Also write the full tree to a file
+  --format <fmt>       text (default), json or yaml. json and yaml parse the
+                       tree into nodes with role, name, ref, attributes
+                       (level, checked, ...), text, props (/url) and children;
+                       with --json the nodes are returned in data.tree
+  --max-chars <n>      Shorten the tree to at most n characters: long runs of
+                       similar rows are elided, then subtrees without
+                       interactive elements are collapsed. Shown refs stay valid
+  --max-tokens <n>     Same as --max-chars with n * 4 characters
+
+Subcommands:
+  diff <a> <b>         Compare two files written by --save (no browser needed)
 
 Global Options:
   --json               Output as JSON
@@ -795,6 +897,13 @@ Examples:
   agent-browser snapshot -i
   agent-browser snapshot --compact --depth 5
   agent-browser snapshot -s "#main-content"
+  agent-browser snapshot -i --diff
+  agent-browser snapshot -i --visible
+  agent-browser snapshot -i --page 2
+  agent-browser snapshot -i --format yaml
+  agent-browser snapshot --max-tokens 2000
+  agent-browser snapshot --save before.txt
+  agent-browser snapshot diff before.txt after.txt
 "##
         }
 
@@ -858,6 +967,8 @@ Subcommands:
   count <selector>           Count matching elements
   box <selector>             Get bounding box (x, y, width, height)
   styles <selector>          Get computed styles of elements
+  locator <ref|selector>     Get a role/name, CSS and XPath locator that finds
+                             the element again after its ref is gone
 
 Global Options:
   --json               Output as JSON
@@ -874,6 +985,7 @@ Examples:
   agent-browser get box "#header"
   agent-browser get styles "button"
   agent-browser get styles @e1
+  agent-browser get locator @e12
 "##
         }
 
@@ -902,6 +1014,38 @@ Examples:
 "##
         }
 
+        // === Assert ===
+        "assert" => {
+            r##"
+agent-browser assert - Check page state and fail with exit code 7 if it doesn't hold
+
+Usage: agent-browser assert <check> [args] [--timeout <ms>]
+
+Checks:
+  text <sel> <contains|equals|matches> <value>   Element text (matches: regex)
+  url [contains|equals|matches] <pattern>        Page URL (default: glob, like wait --url)
+  title [contains|equals|matches] <value>        Page title (default: equals)
+  visible|hidden|enabled|checked <sel>           Element state
+  count <sel> <op> <n>                           Number of matches (op: = != < <= > >=,
+                                                 or eq ne lt le gt ge to avoid shell quoting)
+
+Options:
+  --timeout <ms>       Keep re-checking until it passes or this much time has passed
+                       (default: check once)
+
+Global Options:
+  --json               Output as JSON (failures include expected and actual values)
+  --session <name>     Use specific session
+
+Examples:
+  agent-browser assert text "#status" contains "Order placed" --timeout 5000
+  agent-browser assert url "**/checkout/*"
+  agent-browser assert title matches "^Dashboard"
+  agent-browser assert hidden "#spinner" --timeout 10000
+  agent-browser assert count ".cart-item" ge 1
+"##
+        }
+
         // === Find ===
         "find" => {
             r##"
@@ -1354,7 +1498,10 @@ instance with separate cookies, storage, and state.
 
 Operations:
   (none)               Show current session name
-  list                 List all active sessions
+  list                 List all active sessions (with idle shutdown countdown)
+  info [name]          Show pid, uptime, socket, launch options, URL and tab count
+  kill <name|--all>    Close a session's daemon; SIGTERM then SIGKILL if it hangs
+  prune                Delete .sock/.pid/.port/.crashed files left by dead daemons
 
 Environment:
   AGENT_BROWSER_SESSION    Default session name
@@ -1366,10 +1513,229 @@ Global Options:
 Examples:
   agent-browser session
   agent-browser session list
+  agent-browser session info agent1
+  agent-browser session kill --all
+  agent-browser session prune
   agent-browser --session test open example.com
 "##
         }
 
+        // === Test ===
+        "test" => {
+            r##"
+agent-browser test - Run script files as tests
+
+Usage: agent-browser test <dir|file|glob>... [options]
+
+Each test file holds one agent-browser command per line (without the
+"agent-browser" prefix); blank lines and lines starting with # are skipped.
+A directory runs every *.ab file below it. Each file runs in a fresh
+session and stops at its first failing command; a screenshot plus the
+page's console messages and errors are saved for failed tests.
+
+Script syntax:
+  set-var <name> = <command>   Store a command's result, e.g. set-var id = get attr @e3 data-id
+  set-var <name> <value>       Store a literal value
+  ${name}                      Script variable, else environment variable ($${ for a literal ${)
+  if [not] <check> ... end     Run a block if an assert check holds (else is optional),
+                               e.g. if visible "#cookie-banner"
+  repeat <n> ... end           Run a block n times; ${i} is the iteration (1-based)
+
+Options:
+  --reporter <name>    summary (default), junit, or tap
+  --out, -o <path>     Write the junit/tap report to a file (default: stdout)
+  --workers, -w <n>    Run n files in parallel, each worker in its own session
+  --artifacts <dir>    Where failure screenshots go (default: test-results)
+
+Global Options:
+  --json               Output results as JSON
+  --session <name>     Name sessions <name>-1, <name>-2, ... (default: test-<pid>-N)
+  --headed, --profile, --cdp, --proxy, --debug, ...  Passed on to every command
+
+Exit codes:
+  0                    All tests passed
+  1                    At least one test failed
+  2                    Bad arguments or no test files found
+
+Examples:
+  agent-browser test tests/
+  agent-browser test "tests/**/*.ab" --workers 4
+  agent-browser test tests/ --reporter junit --out report.xml
+"##
+        }
+
+        // === Export ===
+        "export" => {
+            r##"
+agent-browser export - Convert a script file into a Playwright test
+
+Usage: agent-browser export playwright <file> [--lang ts|py] [--out <path>]
+
+Reads a script in the format run by `test` (one command per line, with
+set-var, if and repeat) and writes the equivalent Playwright test:
+@playwright/test for TypeScript, pytest-playwright for Python.
+
+@eN refs are resolved against the last snapshot of the current session,
+which must still be running, and written as role/name locators.
+Commands with no Playwright equivalent become TODO comments and are
+listed on stderr.
+
+Options:
+  --lang <ts|py>       Output language (default: ts)
+  --out, -o <path>     Write the test to a file (default: stdout)
+
+Global Options:
+  --json               Output as JSON
+  --session <name>     Session whose refs are used
+
+Examples:
+  agent-browser export playwright flow.ab
+  agent-browser export playwright flow.ab --out flow.spec.ts
+  agent-browser --session agent1 export playwright flow.ab --lang py -o test_flow.py
+"##
+        }
+
+        // === Relaunch ===
+        "relaunch" => {
+            r##"
+agent-browser relaunch - Restart the browser with new launch options
+
+Usage: agent-browser relaunch [launch options]
+
+Launch options (--headed, --proxy, --user-agent, --args, --executable-path,
+--extension, --profile) only take effect when the daemon starts. relaunch
+saves cookies and storage plus the URL of every open tab, restarts the
+daemon with the given options, and restores them. Form contents, scroll
+position and in-page script state are not kept.
+
+Pass --relaunch to any command to do the same implicitly when launch
+options are given and the daemon is already running.
+
+Not supported with --cdp or -p/--provider.
+
+Global Options:
+  --json               Output as JSON
+  --session <name>     Use specific session
+
+Examples:
+  agent-browser relaunch --headed
+  agent-browser relaunch --proxy http://127.0.0.1:8080
+  agent-browser open example.com --headed --relaunch
+"##
+        }
+
+        // === Logs ===
+        "logs" => {
+            r##"
+agent-browser logs - Show daemon output for a session
+
+Usage: agent-browser logs [--follow] [--lines <n>]
+
+Prints the end of the daemon log. The daemon's stdout and stderr
+(including browser launch errors) are written to <socket_dir>/<session>.log.
+The log is rotated to <session>.log.1 once it exceeds 5 MB (checked on
+each command, so a long-running daemon's log stays bounded too).
+
+Options:
+  -f, --follow         Keep printing new output as it is written
+  -n, --lines <n>      Number of lines to show (default: 50)
+
+Global Options:
+  --json               Output as JSON
+  --session <name>     Use specific session
+
+Examples:
+  agent-browser logs
+  agent-browser logs --lines 200
+  agent-browser --session agent1 logs --follow
+"##
+        }
+
+        // === History ===
+        "history" => {
+            r##"
+agent-browser history - Show the commands sent in a session
+
+Usage: agent-browser history [--last <n>]
+
+Every command sent to a session is appended to its journal at
+<socket_dir>/<session>.history.jsonl with a timestamp, duration and
+whether it succeeded. Passwords, tokens and auth headers are masked;
+the command line as typed is only kept when nothing was masked. Text
+entered with fill and type (including typed passwords) is kept in
+cleartext unless AGENT_BROWSER_HISTORY_MASK_INPUT=1 is set.
+The journal is rotated to <session>.history.jsonl.1 past 5 MB.
+
+Options:
+  -n, --last <n>       Number of entries to show (default: 50)
+
+Global Options:
+  --json               Output as JSON
+  --session <name>     Use specific session
+
+Examples:
+  agent-browser history
+  agent-browser --session agent1 history --last 10
+"##
+        }
+
+        // === Replay ===
+        "replay" => {
+            r##"
+agent-browser replay - Re-run a session's recorded commands
+
+Usage: agent-browser replay <journal|session> [--from <n>]
+
+Sends every command in a history journal again, in order, against a
+fresh session (the target session must not be running). A session name
+stands for that session's journal. Commands that also failed when they
+were recorded are reported and skipped over; the first new failure
+stops the replay and exits with its error's code. Commands recorded
+with masked values (credentials, proxy passwords, or fill/type text
+under AGENT_BROWSER_HISTORY_MASK_INPUT) are skipped and reported, so
+set those again before replaying a login.
+
+Options:
+  --from <n>           Start at entry n, as numbered by history (default: 1)
+
+Global Options:
+  --json               Output as JSON
+  --session <name>     Session to replay into
+
+Examples:
+  agent-browser --session repro replay agent1
+  agent-browser --session repro replay ./run.history.jsonl --from 12
+"##
+        }
+
+        // === Doctor ===
+        "doctor" => {
+            r##"
+agent-browser doctor - Diagnose the local installation
+
+Usage: agent-browser doctor
+
+Checks everything the daemon needs and prints pass/warn/fail with a fix
+for each problem. Exits with code 1 if any check fails.
+
+Checks:
+  node                 Node.js is on PATH and v18 or newer
+  daemon               daemon.js is found (AGENT_BROWSER_HOME, next to the binary, ./dist)
+  browser              Chromium is in the Playwright cache, or
+                       AGENT_BROWSER_EXECUTABLE_PATH exists
+  system deps          Shared libraries Chromium needs are installed (Linux)
+  socket dir           Socket directory is writable and not world-writable
+  sessions             No stale .sock/.pid files from dead daemons
+
+Global Options:
+  --json               Output as JSON (for CI)
+
+Examples:
+  agent-browser doctor
+  agent-browser doctor --json
+"##
+        }
+
         // === Install ===
         "install" => {
             r##"
@@ -1459,7 +1825,7 @@ Core Commands:
   scroll <dir> [px]          Scroll (up/down/left/right)
   scrollintoview <sel>       Scroll element into view
   wait <sel|ms>              Wait for element or time
-  screenshot [path]          Take screenshot
+  screenshot [path]          Take screenshot (--annotate: label refs, --compare <png>: visual diff)
   pdf <path>                 Save as PDF
   snapshot                   Accessibility tree with refs (for AI)
   eval <js>                  Run JavaScript
@@ -1472,11 +1838,15 @@ Navigation:
   reload                     Reload page
 
 Get Info:  agent-browser get <what> [selector]
-  text, html, value, attr <name>, title, url, count, box, styles
+  text, html, value, attr <name>, title, url, count, box, styles, locator
 
 Check State:  agent-browser is <what> <selector>
   visible, enabled, checked
 
+Assert:  agent-browser assert <check> [args] [--timeout <ms>]
+  text <sel> <contains|equals|matches> <value>, url <pattern>, title <value>,
+  visible|hidden|enabled|checked <sel>, count <sel> <op> <n>
+
 Find Elements:  agent-browser find <locator> <value> <action> [text]
   role, text, label, placeholder, alt, title, testid, first, last, nth
 
@@ -1507,20 +1877,40 @@ Debug:
   console [--clear]          View console logs
   errors [--clear]           View page errors
   highlight <sel>            Highlight element
+  logs [--follow] [-n <n>]   Show daemon log for the session
+  history [--last <n>]       Show commands sent in the session
+  replay <journal|session>   Re-run recorded commands in a fresh session [--from <n>]
 
 Sessions:
   session                    Show current session name
   session list               List active sessions
+  session info [name]        Show pid, uptime, launch options, URL and tabs
+  session kill <name|--all>  Stop a session's daemon (force if unresponsive)
+  session prune              Remove files left by dead sessions
+  relaunch [options]         Restart with new launch options, keeping tabs and cookies
+
+Testing:
+  test <dir|file|glob>       Run *.ab script files as tests (--reporter junit|tap, --workers <n>)
+  export playwright <file>   Convert a script to a Playwright test [--lang ts|py] [--out <path>]
 
 Setup:
   install                    Install browser binaries
   install --with-deps        Also install system dependencies (Linux)
+  doctor                     Diagnose installation problems
 
 Snapshot Options:
   -i, --interactive          Only interactive elements
   -c, --compact              Remove empty structural elements
   -d, --depth <n>            Limit tree depth
   -s, --selector <sel>       Scope to CSS selector
+  --visible                  Only elements in the viewport
+  --page <n>                 Scroll to viewport-sized page n, then --visible
+  --diff                     Only what changed since the last snapshot
+  --format <text|json|yaml>  Print the tree as parsed nodes (role, name, ref, ...)
+  --save <file>              Also write the tree to a file
+  --max-chars <n>            Shorten the tree to fit n characters
+  --max-tokens <n>           Shorten the tree to fit about n tokens
+  snapshot diff <a> <b>      Compare two saved snapshots
 
 Options:
   --session <name>           Isolated session (or AGENT_BROWSER_SESSION env)
@@ -1539,13 +1929,41 @@ Options:
   --full, -f                 Full page screenshot
   --headed                   Show browser window (not headless)
   --cdp <port>               Connect via CDP (Chrome DevTools Protocol)
-  --debug                    Debug output
-  --version, -V              Show version
+  --idle-timeout <duration>  Shut the daemon down after inactivity, e.g. 30m (or AGENT_BROWSER_IDLE_TIMEOUT)
+  --no-auto-restart          Keep a daemon from another version instead of restarting it (keeping tabs and cookies)
+  --relaunch                 Restart a running daemon to apply new launch options
+  --lock-timeout <duration>  Max wait for another command on the session, default 60s (or AGENT_BROWSER_LOCK_TIMEOUT)
+  --no-lock                  Don't wait for other commands on the session (or AGENT_BROWSER_NO_LOCK=1)
+  --recover                  If the session crashed, restart it and restore its last autosave
+  --retry <n>                Retry up to n times on transient failures (overlays, detached elements, daemon unreachable)
+  --retry-delay <ms>         First retry delay, doubled each attempt (default: 500)
+  --retry-unsafe             Also retry actions that may repeat their effect (type, press, evaluate, ...)
+  --allow-stale-refs         Only warn when a ref is used after the page navigated (or AGENT_BROWSER_ALLOW_STALE_REFS=1)
+  --debug                    Trace daemon startup and requests to stderr (or AGENT_BROWSER_DEBUG=1)
+  --version, -V              Show CLI, daemon and browser versions
 
 Environment:
   AGENT_BROWSER_SESSION          Session name (default: "default")
   AGENT_BROWSER_EXECUTABLE_PATH  Custom browser executable path
   AGENT_BROWSER_STREAM_PORT      Enable WebSocket streaming on port (e.g., 9223)
+  AGENT_BROWSER_IDLE_TIMEOUT     Idle shutdown for new daemons (e.g. 30m, 90s, 2h)
+  AGENT_BROWSER_DEBUG            Trace daemon startup and requests (set to 1)
+  AGENT_BROWSER_HISTORY_MASK_INPUT  Mask fill/type text in the history journal (set to 1)
+
+Errors:
+  With --json, failures print {{"success":false,"error":"...","type":"...","command":"..."}}
+
+Exit codes:
+  0    Success
+  1    action_failed         Browser action failed (e.g. element not found)
+  2    parse_error           Invalid command line
+  3    daemon_start_failed   Daemon could not be started
+  4    connection_failed     Daemon unreachable (transient, safe to retry)
+  5    timeout               No response in time (transient, but the command may have run)
+  6    session_crashed       Session died; rerun with --recover
+  7    assertion_failed      An assert command's condition was false, or screenshot --compare differed
+  8    stale_ref             A ref was used after the page navigated away from its snapshot
+  130  Interrupted with Ctrl-C
 
 Examples:
   agent-browser open example.com
@@ -1561,6 +1979,51 @@ Examples:
     );
 }
 
-pub fn print_version() {
-    println!("agent-browser {}", env!("CARGO_PKG_VERSION"));
+/// Format a duration compactly, e.g. "1h 5m", "4m 12s", "9s"
+pub fn format_duration(d: std::time::Duration) -> String {
+    let secs = d.as_secs();
+    let (h, m, s) = (secs / 3600, (secs % 3600) / 60, secs % 60);
+    if h > 0 {
+        format!("{}h {}m", h, m)
+    } else if m > 0 {
+        format!("{}m {}s", m, s)
+    } else {
+        format!("{}s", s)
+    }
+}
+
+pub fn print_version(session: &str, daemon: Option<&VersionInfo>, json_mode: bool) {
+    let cli_version = env!("CARGO_PKG_VERSION");
+    let daemon_version = daemon.map(|d| d.version.as_deref().unwrap_or("unknown"));
+    let browser_version = daemon.and_then(|d| d.browser_version.as_deref());
+
+    if json_mode {
+        println!(
+            "{}",
+            serde_json::json!({
+                "success": true,
+                "data": {
+                    "version": cli_version,
+                    "session": session,
+                    "daemonVersion": daemon_version,
+                    "browserVersion": browser_version,
+                }
+            })
+        );
+        return;
+    }
+
+    println!("agent-browser {}", cli_version);
+    if let Some(v) = daemon_version {
+        let note = if v != cli_version {
+            format!(" {}", color::yellow("(mismatch, run 'agent-browser close' to restart)"))
+        } else {
+            String::new()
+        };
+        println!("daemon {} (session {}){}", v, session, note);
+        match browser_version {
+            Some(b) => println!("browser {}", b),
+            None => println!("browser not launched"),
+        }
+    }
 }
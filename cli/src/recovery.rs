@@ -0,0 +1,244 @@
+//! Crash detection and recovery for session daemons.
+//!
+//! After successful commands the CLI periodically snapshots the session's
+//! cookies, storage and open tab URLs next to its socket. If the daemon or its
+//! browser later dies, the next call detects it and, with `--recover`, can
+//! bring the session back from the last snapshot.
+
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::color;
+use crate::commands::gen_id;
+use crate::connection::{get_socket_dir, is_daemon_alive, is_daemon_process, read_daemon_pid, send_command};
+use crate::debug;
+
+/// Minimum time between automatic snapshots of a session
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// An open tab as captured from a running session
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SavedTab {
+    pub url: String,
+    pub active: bool,
+}
+
+/// Tab list written next to the storage-state snapshot
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Autosave {
+    pub saved_at_ms: u64,
+    pub tabs: Vec<SavedTab>,
+}
+
+/// A session whose daemon is gone or no longer answering
+pub struct Crash {
+    pub reason: String,
+    /// Pid of a daemon that is still running but not accepting connections
+    pub hung_pid: Option<u32>,
+}
+
+fn autosave_path(session: &str) -> PathBuf {
+    get_socket_dir().join(format!("{}.autosave.json", session))
+}
+
+/// Storage-state snapshot (cookies, localStorage) written by the daemon's `state_save`
+pub fn autosave_state_path(session: &str) -> PathBuf {
+    get_socket_dir().join(format!("{}.autosave-state.json", session))
+}
+
+/// Marker the daemon writes (with the reason) when it exits because of a crash
+fn crash_marker_path(session: &str) -> PathBuf {
+    get_socket_dir().join(format!("{}.crashed", session))
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+/// Read the open tabs of a running session
+pub fn capture_tabs(session: &str) -> Vec<SavedTab> {
+    let Ok(resp) = send_command(json!({ "id": gen_id(), "action": "tab_list" }), session) else {
+        return Vec::new();
+    };
+    resp.data
+        .as_ref()
+        .and_then(|d| d.get("tabs"))
+        .and_then(|t| t.as_array())
+        .map(|list| {
+            list.iter()
+                .map(|tab| SavedTab {
+                    url: tab.get("url").and_then(|v| v.as_str()).unwrap_or("about:blank").to_string(),
+                    active: tab.get("active").and_then(|v| v.as_bool()).unwrap_or(false),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Reopen tabs in their original order, then reselect the active one
+pub fn restore_tabs(session: &str, tabs: &[SavedTab], json_mode: bool) {
+    for (i, tab) in tabs.iter().enumerate() {
+        let cmd = if i == 0 {
+            json!({ "id": gen_id(), "action": "navigate", "url": tab.url })
+        } else {
+            json!({ "id": gen_id(), "action": "tab_new", "url": tab.url })
+        };
+        if let Err(e) = send_command(cmd, session) {
+            if !json_mode {
+                eprintln!("{} Could not restore tab {}: {}", color::warning_indicator(), tab.url, e);
+            }
+        }
+    }
+    if let Some(active) = tabs.iter().position(|t| t.active) {
+        let _ = send_command(json!({ "id": gen_id(), "action": "tab_switch", "index": active }), session);
+    }
+}
+
+/// Actions that only read the page, so they can't change what an autosave would capture
+const READ_ONLY_ACTIONS: &[&str] = &[
+    "assert",
+    "boundingbox",
+    "console",
+    "cookies_get",
+    "count",
+    "errors",
+    "getattribute",
+    "gettext",
+    "innerhtml",
+    "inputvalue",
+    "ischecked",
+    "isenabled",
+    "isvisible",
+    "locator",
+    "requests",
+    "screenshot",
+    "snapshot",
+    "state_save",
+    "storage_get",
+    "styles",
+    "tab_list",
+    "title",
+    "url",
+];
+
+/// Whether an autosave is worth taking after this action (its 2 round trips
+/// are skipped after commands that only read the page)
+pub fn changes_state(action: Option<&str>) -> bool {
+    !action.is_some_and(|a| READ_ONLY_ACTIONS.contains(&a))
+}
+
+/// Snapshot the session if the last snapshot is older than `AUTOSAVE_INTERVAL`
+pub fn autosave_if_due(session: &str) {
+    let due = fs::metadata(autosave_path(session))
+        .and_then(|m| m.modified())
+        .map(|t| t.elapsed().unwrap_or_default() >= AUTOSAVE_INTERVAL)
+        .unwrap_or(true);
+    if !due {
+        return;
+    }
+    let state_path = autosave_state_path(session);
+    let save = json!({ "id": gen_id(), "action": "state_save", "path": state_path });
+    if !matches!(send_command(save, session), Ok(ref r) if r.success) {
+        debug::log("autosave: state_save failed, keeping previous snapshot");
+        return;
+    }
+    let autosave = Autosave { saved_at_ms: now_ms(), tabs: capture_tabs(session) };
+    if let Ok(data) = serde_json::to_string(&autosave) {
+        let _ = fs::write(autosave_path(session), data);
+        debug::log(&format!("autosave: {} tab(s) saved for session '{}'", autosave.tabs.len(), session));
+    }
+}
+
+/// Last automatic snapshot of the session, if any
+pub fn load_autosave(session: &str) -> Option<Autosave> {
+    let data = fs::read_to_string(autosave_path(session)).ok()?;
+    serde_json::from_str(&data).ok()
+}
+
+/// How long ago a snapshot was taken
+pub fn autosave_age(autosave: &Autosave) -> Duration {
+    Duration::from_millis(now_ms().saturating_sub(autosave.saved_at_ms))
+}
+
+/// Delete the session's snapshot (after an intentional close)
+pub fn clear_autosave(session: &str) {
+    let _ = fs::remove_file(autosave_path(session));
+    let _ = fs::remove_file(autosave_state_path(session));
+}
+
+/// Check whether the session's previous daemon died or stopped answering.
+/// A clean shutdown removes the pid file, so a leftover pid file or crash marker means a crash.
+pub fn detect_crash(session: &str) -> Option<Crash> {
+    let marker = fs::read_to_string(crash_marker_path(session)).ok();
+    let pid = read_daemon_pid(session);
+
+    match pid {
+        // The usual case: a daemon answering on its socket. Checking the process
+        // name (which spawns ps/tasklist) is only needed when something looks wrong.
+        Some(_) if marker.is_none() && is_daemon_alive(session) => None,
+        Some(pid) if is_daemon_process(pid) => {
+            // Give a daemon that is still starting up a moment to open its socket
+            for _ in 0..10 {
+                if is_daemon_alive(session) {
+                    return None;
+                }
+                thread::sleep(Duration::from_millis(100));
+            }
+            Some(Crash {
+                reason: format!("daemon (pid {}) is running but not accepting connections", pid),
+                hung_pid: Some(pid),
+            })
+        }
+        Some(pid) => Some(Crash {
+            reason: marker
+                .map(|m| m.trim().to_string())
+                .filter(|m| !m.is_empty())
+                .unwrap_or_else(|| format!("daemon process {} exited unexpectedly", pid)),
+            hung_pid: None,
+        }),
+        None => marker.map(|m| Crash {
+            reason: if m.trim().is_empty() { "daemon crashed".to_string() } else { m.trim().to_string() },
+            hung_pid: None,
+        }),
+    }
+}
+
+/// Forget a crash once it has been reported or recovered from
+pub fn clear_crash_marker(session: &str) {
+    let _ = fs::remove_file(crash_marker_path(session));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changes_state() {
+        assert!(changes_state(Some("click")));
+        assert!(changes_state(Some("navigate")));
+        assert!(!changes_state(Some("snapshot")));
+        assert!(!changes_state(Some("cookies_get")));
+    }
+
+    #[test]
+    fn test_autosave_roundtrip() {
+        let autosave = Autosave {
+            saved_at_ms: 1_700_000_000_000,
+            tabs: vec![
+                SavedTab { url: "https://example.com".to_string(), active: false },
+                SavedTab { url: "https://example.org".to_string(), active: true },
+            ],
+        };
+        let data = serde_json::to_string(&autosave).unwrap();
+        assert!(data.contains("\"savedAtMs\""));
+        let parsed: Autosave = serde_json::from_str(&data).unwrap();
+        assert_eq!(parsed.tabs, autosave.tabs);
+        assert_eq!(parsed.saved_at_ms, autosave.saved_at_ms);
+    }
+}
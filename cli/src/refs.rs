@@ -0,0 +1,111 @@
+//! Stale-ref detection.
+//!
+//! Refs (`@e12`) only mean something on the page the snapshot was taken on.
+//! The daemon keeps the page, time and navigation id of the snapshot its refs
+//! come from, whatever took it (`snapshot`, `replay`, `screenshot --annotate`),
+//! and before a command acts on a ref the CLI checks (in one `refs` request)
+//! that the page has not navigated since. Otherwise `@e12` could resolve to a different element
+//! on the new page that happens to share a role and name.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+use crate::commands::gen_id;
+use crate::connection::send_command;
+use crate::output::format_duration;
+
+/// Command fields that take a selector, and so can hold a ref
+const SELECTOR_FIELDS: &[&str] = &["selector", "source", "target"];
+
+/// The ref in a selector argument (`@e12`, `ref=e12` or `e12`), as the daemon reads it
+pub(crate) fn parse_ref(selector: &str) -> Option<&str> {
+    if let Some(r) = selector.strip_prefix('@').or_else(|| selector.strip_prefix("ref=")) {
+        return Some(r);
+    }
+    let digits = selector.strip_prefix('e')?;
+    (!digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())).then_some(selector)
+}
+
+/// Refs a command acts on
+fn refs_used(cmd: &Value) -> Vec<&str> {
+    SELECTOR_FIELDS
+        .iter()
+        .filter_map(|f| cmd.get(*f).and_then(|v| v.as_str()))
+        .filter_map(parse_ref)
+        .collect()
+}
+
+/// The `refs` response's snapshot origin, if the active page has navigated
+/// (or another tab was activated) since that snapshot was taken. The daemon
+/// counts every main-frame navigation, reloads and form posts included, but
+/// not a change of #fragment.
+fn stale_origin(data: &Value) -> Option<&Value> {
+    let origin = data.get("origin").filter(|o| o.is_object())?;
+    let current = data.get("navigation")?.as_u64()?;
+    (origin["navigation"].as_u64() != Some(current)).then_some(origin)
+}
+
+/// Before a command that acts on refs, check they come from the document that
+/// is open now. Returns a message saying why they are stale, if they are.
+pub fn check_refs(cmd: &Value, session: &str) -> Option<String> {
+    let used = refs_used(cmd);
+    let first = used.first()?;
+    let resp = send_command(json!({ "id": gen_id(), "action": "refs" }), session).ok()?;
+    let data = resp.data.as_ref()?;
+    let origin = stale_origin(data)?;
+    let snapshot_url = origin["url"].as_str().unwrap_or("?");
+    let current = data["url"].as_str().unwrap_or("?");
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+    let age = Duration::from_millis(now.saturating_sub(origin["timestamp"].as_u64().unwrap_or(now)));
+    let change = if snapshot_url == current { "has been reloaded or navigated since" } else { "is now" };
+    Some(format!(
+        "@{} is from a snapshot of {} taken {} ago, but the page {} {}",
+        first,
+        snapshot_url,
+        format_duration(age),
+        change,
+        current
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ref() {
+        assert_eq!(parse_ref("@e12"), Some("e12"));
+        assert_eq!(parse_ref("ref=e3"), Some("e3"));
+        assert_eq!(parse_ref("e3"), Some("e3"));
+        assert_eq!(parse_ref("#email"), None);
+        assert_eq!(parse_ref("em"), None);
+        assert_eq!(parse_ref("e"), None);
+    }
+
+    #[test]
+    fn test_refs_used() {
+        let cmd = json!({ "action": "fill", "selector": "@e3", "value": "@e4" });
+        assert_eq!(refs_used(&cmd), vec!["e3"]);
+        let cmd = json!({ "action": "drag", "source": "ref=e1", "target": "e12" });
+        assert_eq!(refs_used(&cmd), vec!["e1", "e12"]);
+        let cmd = json!({ "action": "click", "selector": "#email" });
+        assert!(refs_used(&cmd).is_empty());
+        let cmd = json!({ "action": "click", "selector": "em" });
+        assert!(refs_used(&cmd).is_empty());
+    }
+
+    #[test]
+    fn test_stale_origin() {
+        let origin = json!({ "url": "https://a.com/x", "timestamp": 0, "navigation": 3 });
+        let same = json!({ "origin": origin, "navigation": 3, "url": "https://a.com/x#top" });
+        assert!(stale_origin(&same).is_none());
+        let reloaded = json!({ "origin": origin, "navigation": 4, "url": "https://a.com/x" });
+        assert_eq!(stale_origin(&reloaded), Some(&origin));
+        let no_snapshot = json!({ "origin": null, "navigation": 4, "url": "https://a.com/x" });
+        assert!(stale_origin(&no_snapshot).is_none());
+        let not_launched = json!({ "origin": null, "navigation": null, "url": null });
+        assert!(stale_origin(&not_launched).is_none());
+    }
+}
@@ -0,0 +1,143 @@
+//! `--retry`: send a command again when it fails for a reason that tends to
+//! clear up on its own (an overlay still fading out, an element re-rendering,
+//! the daemon briefly unreachable before the request was sent).
+
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::color;
+use crate::commands::gen_id;
+use crate::connection::{send_command, Response};
+use crate::error::{CliError, ErrorKind};
+
+pub const DEFAULT_RETRY_DELAY_MS: u64 = 500;
+
+/// Upper bound for a single backoff delay
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Actions whose effect adds up if they are repeated after partly succeeding
+/// (typing the text twice, pressing Enter twice, opening two tabs)
+const UNSAFE_ACTIONS: &[&str] = &[
+    "type",
+    "press",
+    "keydown",
+    "keyup",
+    "mousedown",
+    "mouseup",
+    "wheel",
+    "scroll",
+    "evaluate",
+    "back",
+    "forward",
+    "tab_new",
+    "tab_close",
+    "window_new",
+];
+
+/// Whether `--retry` needs `--retry-unsafe` to retry this command
+pub fn is_unsafe(cmd: &Value) -> bool {
+    let action = cmd.get("action").and_then(|v| v.as_str()).unwrap_or_default();
+    let subaction = cmd.get("subaction").and_then(|v| v.as_str());
+    UNSAFE_ACTIONS.contains(&action) || subaction == Some("type")
+}
+
+/// Delay before retry number `retry` (1-based): base, 2x base, 4x base, ...
+pub fn backoff(base_ms: u64, retry: u32) -> Duration {
+    let factor = 1u64.checked_shl(retry.saturating_sub(1)).unwrap_or(u64::MAX);
+    Duration::from_millis(base_ms.saturating_mul(factor)).min(MAX_RETRY_DELAY)
+}
+
+/// Failures the daemon marks as transient, and requests that never reached it.
+/// A timeout or broken connection after sending is not retried: the daemon may
+/// still be running the command, and a second `click` would click twice.
+fn is_retryable(result: &Result<Response, CliError>) -> bool {
+    match result {
+        Ok(resp) => !resp.success && resp.retryable,
+        Err(e) => e.kind == ErrorKind::ConnectionFailed && !e.delivered,
+    }
+}
+
+fn describe_failure(result: &Result<Response, CliError>) -> String {
+    match result {
+        Ok(resp) => resp.error.clone().unwrap_or_else(|| "Unknown error".to_string()),
+        Err(e) => e.message.clone(),
+    }
+}
+
+/// Send `cmd`, retrying up to `retries` times with exponential backoff.
+/// Returns the last result and one entry per failed attempt for `--json` output.
+pub fn send_with_retry(
+    mut cmd: Value,
+    session: &str,
+    retries: u32,
+    base_delay_ms: u64,
+    json_mode: bool,
+) -> (Result<Response, CliError>, Vec<Value>) {
+    let mut failures = Vec::new();
+    let mut attempt = 1;
+    loop {
+        let result = send_command(cmd.clone(), session);
+        if attempt > retries || !is_retryable(&result) {
+            return (result, failures);
+        }
+        let error = describe_failure(&result);
+        let delay = backoff(base_delay_ms, attempt);
+        if !json_mode {
+            eprintln!(
+                "{}",
+                color::dim(&format!(
+                    "Attempt {}/{} failed: {} (retrying in {}ms)",
+                    attempt,
+                    retries + 1,
+                    error,
+                    delay.as_millis()
+                ))
+            );
+        }
+        failures.push(json!({ "attempt": attempt, "error": error, "delayMs": delay.as_millis() as u64 }));
+        thread::sleep(delay);
+        // Each attempt is a new request as far as the daemon (and Ctrl-C) is concerned
+        cmd["id"] = json!(gen_id());
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_and_caps() {
+        assert_eq!(backoff(500, 1), Duration::from_millis(500));
+        assert_eq!(backoff(500, 2), Duration::from_millis(1000));
+        assert_eq!(backoff(500, 4), Duration::from_millis(4000));
+        assert_eq!(backoff(500, 20), MAX_RETRY_DELAY);
+        assert_eq!(backoff(500, 100), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn test_unsafe_actions() {
+        assert!(is_unsafe(&json!({ "action": "type", "selector": "#q", "text": "hi" })));
+        assert!(is_unsafe(&json!({ "action": "getbyrole", "role": "textbox", "subaction": "type" })));
+        assert!(!is_unsafe(&json!({ "action": "click", "selector": "#btn" })));
+        assert!(!is_unsafe(&json!({ "action": "fill", "selector": "#q", "value": "hi" })));
+    }
+
+    #[test]
+    fn test_retryable_results() {
+        let resp = |retryable| Response {
+            success: false,
+            data: None,
+            error: Some("Element is blocked by another element".to_string()),
+            retryable,
+        };
+        assert!(is_retryable(&Ok(resp(true))));
+        assert!(!is_retryable(&Ok(resp(false))));
+        assert!(is_retryable(&Err(CliError::new(ErrorKind::ConnectionFailed, "Failed to connect"))));
+        assert!(!is_retryable(&Err(CliError::new(ErrorKind::ConnectionFailed, "Failed to read").after_delivery())));
+        assert!(!is_retryable(&Err(CliError::new(ErrorKind::Timeout, "timed out").after_delivery())));
+        assert!(!is_retryable(&Err(CliError::new(ErrorKind::DaemonStartFailed, "no"))));
+    }
+}
@@ -0,0 +1,540 @@
+//! `agent-browser test`: run script files as test cases and report the results.
+//!
+//! Each test file runs in its own fresh session (one per worker, closed after
+//! every file). On failure the runner saves a screenshot and the page's console
+//! messages and errors before closing the session.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+use serde_json::{json, Value};
+
+use crate::assert::glob_to_regex;
+use crate::color;
+use crate::error::{fail, ErrorKind};
+use crate::flags::Flags;
+use crate::script::{load_script, run_script, run_step, ScriptFailure, Vars};
+
+/// Extension of script files picked up when a directory is given
+pub const TEST_FILE_EXTENSION: &str = "ab";
+
+const USAGE: &str = "Usage: agent-browser test <dir|file|glob>... [--reporter summary|junit|tap] [--out <path>] [--workers <n>] [--artifacts <dir>]";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Reporter {
+    Summary,
+    Junit,
+    Tap,
+}
+
+struct TestOptions {
+    patterns: Vec<String>,
+    reporter: Reporter,
+    out: Option<PathBuf>,
+    workers: usize,
+    artifacts: PathBuf,
+}
+
+/// What a failed test left behind for debugging
+#[derive(Default)]
+struct Artifacts {
+    screenshot: Option<PathBuf>,
+    console: String,
+    errors: String,
+}
+
+struct TestResult {
+    name: String,
+    duration: Duration,
+    failure: Option<ScriptFailure>,
+    artifacts: Artifacts,
+}
+
+fn parse_options(args: &[String]) -> Result<TestOptions, String> {
+    let mut opts = TestOptions {
+        patterns: Vec::new(),
+        reporter: Reporter::Summary,
+        out: None,
+        workers: 1,
+        artifacts: PathBuf::from("test-results"),
+    };
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut value = |name: &str| {
+            iter.next().cloned().ok_or_else(|| format!("Missing value for {}\n{}", name, USAGE))
+        };
+        match arg.as_str() {
+            "--reporter" => {
+                opts.reporter = match value("--reporter")?.as_str() {
+                    "summary" => Reporter::Summary,
+                    "junit" => Reporter::Junit,
+                    "tap" => Reporter::Tap,
+                    other => return Err(format!("Unknown reporter: {} (expected summary, junit, or tap)", other)),
+                }
+            }
+            "--out" | "-o" => opts.out = Some(PathBuf::from(value("--out")?)),
+            "--artifacts" => opts.artifacts = PathBuf::from(value("--artifacts")?),
+            "--workers" | "-w" => {
+                let n = value("--workers")?;
+                opts.workers = match n.parse::<usize>() {
+                    Ok(n) if n > 0 => n,
+                    _ => return Err(format!("Invalid worker count: '{}' (expected a number of 1 or more)", n)),
+                }
+            }
+            _ => opts.patterns.push(arg.clone()),
+        }
+    }
+    if opts.patterns.is_empty() {
+        return Err(format!("Missing arguments for: test\n{}", USAGE));
+    }
+    Ok(opts)
+}
+
+fn collect_dir(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_dir(&path, files);
+        } else if path.extension().and_then(|e| e.to_str()) == Some(TEST_FILE_EXTENSION) {
+            files.push(path);
+        }
+    }
+}
+
+/// Expand a directory (every `.ab` file below it), a file, or a glob such as
+/// `tests/**/*.ab` into test files
+fn find_test_files(pattern: &str) -> Result<Vec<PathBuf>, String> {
+    let path = Path::new(pattern);
+    let mut files = Vec::new();
+    if path.is_dir() {
+        collect_dir(path, &mut files);
+    } else if path.is_file() {
+        files.push(path.to_path_buf());
+    } else if pattern.contains('*') {
+        // Walk from the last directory before the first wildcard
+        let base = pattern[..pattern.find('*').unwrap_or(0)]
+            .rfind('/')
+            .map(|i| &pattern[..i.max(1)])
+            .unwrap_or(".");
+        let mut candidates = Vec::new();
+        collect_all(Path::new(base), &mut candidates);
+        files.extend(glob_filter(pattern, candidates)?);
+    } else {
+        return Err(format!("No such file or directory: {}", pattern));
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Paths matching a glob, comparing both without a leading `./`
+fn glob_filter(pattern: &str, candidates: Vec<PathBuf>) -> Result<Vec<PathBuf>, String> {
+    let re = Regex::new(&glob_to_regex(pattern.trim_start_matches("./"))).map_err(|e| e.to_string())?;
+    Ok(candidates
+        .into_iter()
+        .filter(|p| re.is_match(p.to_string_lossy().trim_start_matches("./")))
+        .collect())
+}
+
+fn collect_all(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_all(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+/// Browser options given to `test` that every step should also use
+fn forwarded_flags(flags: &Flags) -> Vec<String> {
+    let mut out = Vec::new();
+    if flags.headed {
+        out.push("--headed".to_string());
+    }
+    if flags.debug {
+        out.push("--debug".to_string());
+    }
+    for (name, value) in [
+        ("--executable-path", &flags.executable_path),
+        ("--profile", &flags.profile),
+        ("--cdp", &flags.cdp),
+        ("--args", &flags.args),
+        ("--user-agent", &flags.user_agent),
+        ("--proxy", &flags.proxy),
+        ("--proxy-bypass", &flags.proxy_bypass),
+        ("--provider", &flags.provider),
+    ] {
+        if let Some(v) = value {
+            out.push(name.to_string());
+            out.push(v.clone());
+        }
+    }
+    for ext in &flags.extensions {
+        out.push("--extension".to_string());
+        out.push(ext.clone());
+    }
+    out
+}
+
+/// Console messages (`[type] text`) or page errors, one per line; empty if
+/// there are none or they could not be read
+fn capture_log(command: &str, field: &str, session: &str, extra_flags: &[String]) -> String {
+    let Ok(v) = run_step(&[command.to_string()], session, extra_flags) else {
+        return String::new();
+    };
+    let entries = v["data"][field].as_array().cloned().unwrap_or_default();
+    entries
+        .iter()
+        .map(|e| match (e["type"].as_str(), e["text"].as_str(), e["message"].as_str()) {
+            (Some(kind), Some(text), _) => format!("[{}] {}", kind, text),
+            (_, _, Some(message)) => message.to_string(),
+            _ => e.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn collect_artifacts(name: &str, session: &str, dir: &Path, extra_flags: &[String]) -> Artifacts {
+    let _ = fs::create_dir_all(dir);
+    let file_stem: String = name.chars().map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect();
+    let path = dir.join(format!("{}.png", file_stem));
+    let path = fs::canonicalize(dir).map(|d| d.join(path.file_name().unwrap_or_default())).unwrap_or(path);
+    let screenshot_args = vec!["screenshot".to_string(), path.to_string_lossy().to_string()];
+    let screenshot = run_step(&screenshot_args, session, extra_flags).ok().map(|_| path);
+    Artifacts {
+        screenshot,
+        console: capture_log("console", "messages", session, extra_flags),
+        errors: capture_log("errors", "errors", session, extra_flags),
+    }
+}
+
+fn run_test(file: &Path, session: &str, opts: &TestOptions, extra_flags: &[String]) -> TestResult {
+    let name = file.to_string_lossy().to_string();
+    let start = Instant::now();
+    let outcome = load_script(file)
+        .map_err(|message| ScriptFailure { line: 0, text: String::new(), message })
+        .and_then(|nodes| run_script(&nodes, session, extra_flags, &mut Vars::new()));
+    let duration = start.elapsed();
+    let (failure, artifacts) = match outcome {
+        Ok(()) => (None, Artifacts::default()),
+        Err(f) => (Some(f), collect_artifacts(&name, session, &opts.artifacts, extra_flags)),
+    };
+    // Every test starts from a fresh browser
+    let _ = run_step(&["close".to_string()], session, extra_flags);
+    TestResult { name, duration, failure, artifacts }
+}
+
+fn describe_failure(f: &ScriptFailure) -> String {
+    if f.line == 0 {
+        f.message.clone()
+    } else {
+        format!("line {}: {}\n{}", f.line, f.text, f.message)
+    }
+}
+
+fn print_progress(result: &TestResult) {
+    let time = color::dim(&format!("({:.1}s)", result.duration.as_secs_f64()));
+    match &result.failure {
+        None => println!("{} {} {}", color::success_indicator(), result.name, time),
+        Some(f) => {
+            println!("{} {} {}", color::error_indicator(), result.name, time);
+            for line in describe_failure(f).lines() {
+                println!("    {}", line);
+            }
+            if let Some(path) = &result.artifacts.screenshot {
+                println!("    {}", color::dim(&format!("screenshot: {}", path.display())));
+            }
+        }
+    }
+}
+
+fn xml_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            // Control characters other than tab and newlines are not allowed in XML 1.0
+            c if (c as u32) < 0x20 && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn junit_report(results: &[TestResult], total: Duration) -> String {
+    let failures = results.iter().filter(|r| r.failure.is_some()).count();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites name=\"agent-browser\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+        results.len(),
+        failures,
+        total.as_secs_f64()
+    ));
+    xml.push_str(&format!(
+        "  <testsuite name=\"agent-browser\" tests=\"{}\" failures=\"{}\" errors=\"0\" skipped=\"0\" time=\"{:.3}\">\n",
+        results.len(),
+        failures,
+        total.as_secs_f64()
+    ));
+    for r in results {
+        let classname = Path::new(&r.name)
+            .parent()
+            .map(|p| p.to_string_lossy().replace('/', "."))
+            .unwrap_or_default();
+        xml.push_str(&format!(
+            "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\"",
+            xml_escape(&r.name),
+            xml_escape(&classname),
+            r.duration.as_secs_f64()
+        ));
+        let Some(f) = &r.failure else {
+            xml.push_str("/>\n");
+            continue;
+        };
+        xml.push_str(">\n");
+        xml.push_str(&format!(
+            "      <failure message=\"{}\">{}</failure>\n",
+            xml_escape(f.message.lines().next().unwrap_or_default()),
+            xml_escape(&describe_failure(f))
+        ));
+        let mut out = String::new();
+        if let Some(path) = &r.artifacts.screenshot {
+            // Attachment syntax understood by the Jenkins and GitLab JUnit plugins
+            out.push_str(&format!("[[ATTACHMENT|{}]]\n", path.display()));
+        }
+        if !r.artifacts.console.is_empty() {
+            out.push_str(&format!("console:\n{}\n", r.artifacts.console));
+        }
+        if !out.is_empty() {
+            xml.push_str(&format!("      <system-out>{}</system-out>\n", xml_escape(&out)));
+        }
+        if !r.artifacts.errors.is_empty() {
+            xml.push_str(&format!("      <system-err>{}</system-err>\n", xml_escape(&r.artifacts.errors)));
+        }
+        xml.push_str("    </testcase>\n");
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+fn tap_report(results: &[TestResult]) -> String {
+    let mut tap = format!("TAP version 13\n1..{}\n", results.len());
+    for (i, r) in results.iter().enumerate() {
+        let Some(f) = &r.failure else {
+            tap.push_str(&format!("ok {} - {}\n", i + 1, r.name));
+            continue;
+        };
+        tap.push_str(&format!("not ok {} - {}\n  ---\n", i + 1, r.name));
+        tap.push_str(&format!("  message: {}\n", json!(f.message)));
+        if f.line > 0 {
+            tap.push_str(&format!("  line: {}\n  command: {}\n", f.line, json!(f.text)));
+        }
+        if let Some(path) = &r.artifacts.screenshot {
+            tap.push_str(&format!("  screenshot: {}\n", json!(path.display().to_string())));
+        }
+        tap.push_str(&format!("  duration_ms: {}\n  ...\n", r.duration.as_millis()));
+    }
+    tap
+}
+
+fn json_report(results: &[TestResult], total: Duration) -> Value {
+    let failed = results.iter().filter(|r| r.failure.is_some()).count();
+    let tests: Vec<Value> = results
+        .iter()
+        .map(|r| {
+            let mut t = json!({
+                "name": r.name,
+                "passed": r.failure.is_none(),
+                "durationMs": r.duration.as_millis() as u64,
+            });
+            if let Some(f) = &r.failure {
+                t["error"] = json!(f.message);
+                if f.line > 0 {
+                    t["line"] = json!(f.line);
+                    t["command"] = json!(f.text);
+                }
+                if let Some(path) = &r.artifacts.screenshot {
+                    t["screenshot"] = json!(path);
+                }
+            }
+            t
+        })
+        .collect();
+    json!({
+        "success": failed == 0,
+        "data": {
+            "passed": results.len() - failed,
+            "failed": failed,
+            "durationMs": total.as_millis() as u64,
+            "tests": tests,
+        }
+    })
+}
+
+/// Take the next test file off the shared queue (without holding the lock while it runs)
+fn next_test(queue: &Mutex<VecDeque<(usize, PathBuf)>>) -> Option<(usize, PathBuf)> {
+    queue.lock().ok()?.pop_front()
+}
+
+pub fn run_tests(args: &[String], flags: &Flags) {
+    let opts = match parse_options(args) {
+        Ok(o) => o,
+        Err(e) => fail(ErrorKind::ParseError, &e, Some("test"), flags.json),
+    };
+    let mut files = Vec::new();
+    for pattern in &opts.patterns {
+        match find_test_files(pattern) {
+            Ok(found) => files.extend(found),
+            Err(e) => fail(ErrorKind::ParseError, &e, Some("test"), flags.json),
+        }
+    }
+    if files.is_empty() {
+        let msg = format!("No test files (*.{}) found in {}", TEST_FILE_EXTENSION, opts.patterns.join(", "));
+        fail(ErrorKind::ParseError, &msg, Some("test"), flags.json);
+    }
+
+    // Sessions are named after the --session given (if any) and the worker,
+    // with the pid so two runs on one machine don't share browsers
+    let base = if flags.session == "default" {
+        format!("test-{}", std::process::id())
+    } else {
+        flags.session.clone()
+    };
+    let extra_flags = forwarded_flags(flags);
+    // Reports on stdout need it to themselves
+    let live_progress = !flags.json && (opts.reporter == Reporter::Summary || opts.out.is_some());
+
+    let start = Instant::now();
+    let queue = Arc::new(Mutex::new(files.into_iter().enumerate().collect::<VecDeque<_>>()));
+    let results = Arc::new(Mutex::new(Vec::new()));
+    let opts = Arc::new(opts);
+    let extra_flags = Arc::new(extra_flags);
+    let handles: Vec<_> = (0..opts.workers)
+        .map(|worker| {
+            let (queue, results, opts, extra_flags) =
+                (queue.clone(), results.clone(), opts.clone(), extra_flags.clone());
+            let session = format!("{}-{}", base, worker + 1);
+            thread::spawn(move || {
+                while let Some((index, file)) = next_test(&queue) {
+                    let result = run_test(&file, &session, &opts, &extra_flags);
+                    if live_progress {
+                        print_progress(&result);
+                    }
+                    if let Ok(mut r) = results.lock() {
+                        r.push((index, result));
+                    }
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        let _ = handle.join();
+    }
+    let total = start.elapsed();
+
+    let mut results = std::mem::take(&mut *results.lock().unwrap_or_else(|e| e.into_inner()));
+    results.sort_by_key(|(i, _)| *i);
+    let results: Vec<TestResult> = results.into_iter().map(|(_, r)| r).collect();
+    let failed = results.iter().filter(|r| r.failure.is_some()).count();
+
+    let report = match opts.reporter {
+        Reporter::Summary => None,
+        Reporter::Junit => Some(junit_report(&results, total)),
+        Reporter::Tap => Some(tap_report(&results)),
+    };
+    match (&report, &opts.out) {
+        (Some(report), Some(path)) => {
+            if let Err(e) = fs::write(path, report) {
+                let msg = format!("Could not write report to {}: {}", path.display(), e);
+                fail(ErrorKind::ActionFailed, &msg, Some("test"), flags.json);
+            }
+        }
+        (Some(report), None) if !flags.json => print!("{}", report),
+        _ => {}
+    }
+
+    if flags.json {
+        println!("{}", json_report(&results, total));
+    } else if live_progress {
+        let summary = format!(
+            "{} passed, {} failed ({:.1}s)",
+            results.len() - failed,
+            failed,
+            total.as_secs_f64()
+        );
+        println!();
+        println!("{}", if failed == 0 { color::green(&summary) } else { color::red(&summary) });
+        if let Some(path) = &opts.out {
+            println!("{}", color::dim(&format!("Report written to {}", path.display())));
+        }
+    }
+    if failed > 0 {
+        exit(ErrorKind::ActionFailed.exit_code());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(name: &str, failure: Option<ScriptFailure>) -> TestResult {
+        TestResult { name: name.to_string(), duration: Duration::from_millis(1500), failure, artifacts: Artifacts::default() }
+    }
+
+    fn failing() -> Option<ScriptFailure> {
+        Some(ScriptFailure {
+            line: 3,
+            text: "click \"#buy\"".to_string(),
+            message: "Element \"#buy\" not found & <gone>".to_string(),
+        })
+    }
+
+    #[test]
+    fn test_glob_filter_normalizes_dot_slash() {
+        let candidates = vec![PathBuf::from("./tests/login.ab"), PathBuf::from("./tests/auth/otp.ab"), PathBuf::from("./notes.txt")];
+        let names = |files: Vec<PathBuf>| files.iter().map(|p| p.to_string_lossy().into_owned()).collect::<Vec<_>>();
+        assert_eq!(names(glob_filter("./tests/*.ab", candidates.clone()).unwrap()), ["./tests/login.ab"]);
+        assert_eq!(names(glob_filter("tests/**/*.ab", candidates).unwrap()), ["./tests/login.ab", "./tests/auth/otp.ab"]);
+    }
+
+    #[test]
+    fn test_junit_report() {
+        let xml = junit_report(&[result("tests/login.ab", None), result("tests/cart.ab", failing())], Duration::from_secs(3));
+        assert!(xml.contains("tests=\"2\" failures=\"1\""));
+        assert!(xml.contains("<testcase name=\"tests/login.ab\" classname=\"tests\" time=\"1.500\"/>"));
+        assert!(xml.contains("<failure message=\"Element &quot;#buy&quot; not found &amp; &lt;gone&gt;\">line 3: click"));
+    }
+
+    #[test]
+    fn test_tap_report() {
+        let tap = tap_report(&[result("a.ab", None), result("b.ab", failing())]);
+        assert!(tap.starts_with("TAP version 13\n1..2\nok 1 - a.ab\nnot ok 2 - b.ab\n  ---\n"));
+        assert!(tap.contains("  line: 3\n"));
+        assert!(tap.ends_with("  duration_ms: 1500\n  ...\n"));
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let args: Vec<String> = "test tests --reporter junit --out r.xml --workers 4".split(' ').map(String::from).collect();
+        let opts = parse_options(&args).ok().unwrap();
+        assert_eq!(opts.patterns, vec!["tests"]);
+        assert!(opts.reporter == Reporter::Junit);
+        assert_eq!(opts.workers, 4);
+        assert!(parse_options(&["test".to_string()]).is_err());
+        let bad: Vec<String> = "test tests --workers 0".split(' ').map(String::from).collect();
+        assert!(parse_options(&bad).is_err());
+    }
+}
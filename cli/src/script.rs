@@ -0,0 +1,404 @@
+//! Scripts: files with one agent-browser command line per line, as run by `test`.
+//!
+//! Blank lines and lines starting with `#` are skipped. Words are split like a
+//! shell would (single and double quotes, backslash escapes), so a line reads
+//! exactly like the command typed after `agent-browser`.
+//!
+//! On top of plain commands, scripts support:
+//!
+//! - `set-var name = <command>` to capture a command's result (e.g. `get text @e3`),
+//!   or `set-var name <value>` for a literal
+//! - `${name}` interpolation of script variables, falling back to environment
+//!   variables (`$${` for a literal `${`), applied to each word before the
+//!   command is parsed
+//! - `if [not] <check> ...` / `else` / `end`, where the check is anything
+//!   `assert` accepts (`if visible #banner`, `if url **/login`)
+//! - `repeat <n>` / `end`, with the 1-based iteration in `${i}`
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use serde_json::Value;
+
+/// One command line of a script
+pub struct Step {
+    /// 1-based line number in the script file
+    pub line: usize,
+    pub text: String,
+    pub args: Vec<String>,
+}
+
+/// A statement of a parsed script
+pub enum Node {
+    Command(Step),
+    /// `set-var name <value>` (literal) or `set-var name = <command>` (captured)
+    SetVar { step: Step, name: String, value: String, capture: bool },
+    If { step: Step, negate: bool, then: Vec<Node>, otherwise: Vec<Node> },
+    Repeat { step: Step, count: String, body: Vec<Node> },
+}
+
+/// Why a script stopped: the step that failed and its error
+pub struct ScriptFailure {
+    pub line: usize,
+    pub text: String,
+    pub message: String,
+}
+
+/// Script variables, set with `set-var` and read with `${name}`
+pub type Vars = HashMap<String, String>;
+
+/// Split a command line into words, honoring quotes and backslash escapes
+pub fn split_line(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            // Inside single quotes everything is literal
+            (Some('\''), c) => word.push(c),
+            (_, '\\') => match chars.next() {
+                Some(next) => word.push(next),
+                None => return Err("line ends with a backslash".to_string()),
+            },
+            (Some(_), c) => word.push(c),
+            (None, '"') | (None, '\'') => {
+                quote = Some(c);
+                in_word = true;
+                continue;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+                continue;
+            }
+            (None, c) => word.push(c),
+        }
+        in_word = true;
+    }
+
+    if let Some(q) = quote {
+        return Err(format!("unterminated {} quote", q));
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// Replace `${name}` with a script variable or environment variable
+pub fn interpolate(word: &str, vars: &Vars) -> Result<String, String> {
+    let mut out = String::with_capacity(word.len());
+    let mut rest = word;
+    while let Some(pos) = rest.find("${") {
+        // `$${` stands for a literal `${`
+        if rest[..pos].ends_with('$') {
+            out.push_str(&rest[..pos - 1]);
+            out.push_str("${");
+            rest = &rest[pos + 2..];
+            continue;
+        }
+        out.push_str(&rest[..pos]);
+        let after = &rest[pos + 2..];
+        let close = after.find('}').ok_or_else(|| format!("Unterminated ${{ in '{}'", word))?;
+        let name = &after[..close];
+        let value = match vars.get(name) {
+            Some(v) => v.clone(),
+            None => env::var(name).map_err(|_| format!("Undefined variable: {}", name))?,
+        };
+        out.push_str(&value);
+        rest = &after[close + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// A block being parsed: its opening statement and the nodes collected so far
+struct Frame {
+    opener: Option<Node>,
+    nodes: Vec<Node>,
+    in_else: bool,
+}
+
+impl Frame {
+    fn new(opener: Option<Node>) -> Self {
+        Frame { opener, nodes: Vec::new(), in_else: false }
+    }
+}
+
+/// Read a script file into its statements
+pub fn load_script(path: &Path) -> Result<Vec<Node>, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    parse_script(&content).map_err(|(line, e)| format!("{}:{}: {}", path.display(), line, e))
+}
+
+/// Parse script text into statements, or the 1-based line and error of the first bad line
+pub fn parse_script(content: &str) -> Result<Vec<Node>, (usize, String)> {
+    let mut stack = vec![Frame::new(None)];
+
+    for (i, raw) in content.lines().enumerate() {
+        let line = i + 1;
+        let text = raw.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+        let args = split_line(text).map_err(|e| (line, e))?;
+        let step = Step { line, text: text.to_string(), args };
+
+        match step.args[0].as_str() {
+            "set-var" => {
+                let node = match step.args.get(1..).unwrap_or_default() {
+                    [name, eq, command @ ..] if eq == "=" && !command.is_empty() => {
+                        let (name, value) = (name.clone(), command.join(" "));
+                        Node::SetVar { name, value, capture: true, step }
+                    }
+                    [name, value] => {
+                        let (name, value) = (name.clone(), value.clone());
+                        Node::SetVar { name, value, capture: false, step }
+                    }
+                    _ => return Err((line, "Usage: set-var <name> = <command>, or set-var <name> <value>".to_string())),
+                };
+                if let Node::SetVar { name, .. } = &node {
+                    if !is_valid_name(name) {
+                        return Err((line, format!("Invalid variable name: {}", name)));
+                    }
+                }
+                push_node(&mut stack, node);
+            }
+            "if" => {
+                let negate = step.args.get(1).map(|s| s.as_str()) == Some("not");
+                let check_at = if negate { 2 } else { 1 };
+                if step.args.len() <= check_at {
+                    return Err((line, "Usage: if [not] <check> ... (any assert check, e.g. if visible #banner)".to_string()));
+                }
+                stack.push(Frame::new(Some(Node::If { step, negate, then: Vec::new(), otherwise: Vec::new() })));
+            }
+            "repeat" => {
+                let count = match step.args.as_slice() {
+                    [_, count] => count.clone(),
+                    _ => return Err((line, "Usage: repeat <n>".to_string())),
+                };
+                stack.push(Frame::new(Some(Node::Repeat { step, count, body: Vec::new() })));
+            }
+            "else" => {
+                let frame = stack.last_mut().filter(|f| matches!(f.opener, Some(Node::If { .. })) && !f.in_else);
+                let Some(frame) = frame else {
+                    return Err((line, "else without a matching if".to_string()));
+                };
+                if let Some(Node::If { then, .. }) = frame.opener.as_mut() {
+                    *then = std::mem::take(&mut frame.nodes);
+                }
+                frame.in_else = true;
+            }
+            "end" => {
+                let frame = match stack.pop() {
+                    Some(frame) if frame.opener.is_some() => frame,
+                    _ => return Err((line, "end without a matching if or repeat".to_string())),
+                };
+                let node = match frame.opener {
+                    Some(Node::If { step, negate, then, .. }) if frame.in_else => {
+                        Node::If { step, negate, then, otherwise: frame.nodes }
+                    }
+                    Some(Node::If { step, negate, .. }) => {
+                        Node::If { step, negate, then: frame.nodes, otherwise: Vec::new() }
+                    }
+                    Some(Node::Repeat { step, count, .. }) => Node::Repeat { step, count, body: frame.nodes },
+                    _ => unreachable!("only if and repeat open blocks"),
+                };
+                push_node(&mut stack, node);
+            }
+            _ => push_node(&mut stack, Node::Command(step)),
+        }
+    }
+
+    if let Some(Node::If { step, .. } | Node::Repeat { step, .. }) = stack.last().and_then(|f| f.opener.as_ref()) {
+        return Err((step.line, format!("'{}' is missing its end", step.args[0])));
+    }
+    Ok(stack.pop().map(|f| f.nodes).unwrap_or_default())
+}
+
+fn push_node(stack: &mut [Frame], node: Node) {
+    if let Some(frame) = stack.last_mut() {
+        frame.nodes.push(node);
+    }
+}
+
+/// Run an agent-browser command line with this same binary, returning its
+/// `--json` output, or the error message and envelope `type` it failed with
+fn invoke(args: &[String], session: &str, extra_flags: &[String]) -> Result<Value, (String, Option<String>)> {
+    let exe = env::current_exe().map_err(|e| (format!("Could not find agent-browser executable: {}", e), None))?;
+    let output = Command::new(exe)
+        .args(args)
+        .args(extra_flags)
+        .args(["--json", "--session", session])
+        .output()
+        .map_err(|e| (format!("Could not run agent-browser: {}", e), None))?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let parsed: Option<Value> = stdout.lines().rev().find_map(|l| serde_json::from_str(l).ok());
+    match parsed {
+        Some(v) if output.status.success() && v["success"] != false => Ok(v),
+        Some(v) => Err((
+            v["error"].as_str().unwrap_or("Command failed").to_string(),
+            v["type"].as_str().map(String::from),
+        )),
+        None => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            Err((stderr.trim().lines().last().unwrap_or("Command failed").to_string(), None))
+        }
+    }
+}
+
+/// Run one command line in `session`, returning its `--json` output or the
+/// error it failed with
+pub fn run_step(args: &[String], session: &str, extra_flags: &[String]) -> Result<Value, String> {
+    invoke(args, session, extra_flags).map_err(|(message, _)| message)
+}
+
+/// The value a command produced, for `set-var`: the main field of its data
+/// (`text`, `value`, `url`, ...), or the whole data as JSON
+fn captured_value(output: &Value) -> String {
+    const FIELDS: &[&str] = &["text", "value", "url", "title", "html", "result", "count", "visible", "enabled", "checked"];
+    let data = &output["data"];
+    let value = FIELDS
+        .iter()
+        .find_map(|f| data.get(*f))
+        .or_else(|| data.as_object().filter(|o| o.len() == 1).and_then(|o| o.values().next()))
+        .unwrap_or(data);
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        v => v.to_string(),
+    }
+}
+
+fn failure(step: &Step, message: String) -> ScriptFailure {
+    ScriptFailure { line: step.line, text: step.text.clone(), message }
+}
+
+fn interpolate_all(step: &Step, words: &[String], vars: &Vars) -> Result<Vec<String>, ScriptFailure> {
+    words.iter().map(|w| interpolate(w, vars).map_err(|e| failure(step, e))).collect()
+}
+
+/// Run statements in order, stopping at the first failure. Failures report the
+/// line as written (before interpolation), so secrets from the environment
+/// don't end up in test reports.
+pub fn run_script(nodes: &[Node], session: &str, extra_flags: &[String], vars: &mut Vars) -> Result<(), ScriptFailure> {
+    for node in nodes {
+        match node {
+            Node::Command(step) => {
+                let args = interpolate_all(step, &step.args, vars)?;
+                run_step(&args, session, extra_flags).map_err(|e| failure(step, e))?;
+            }
+            Node::SetVar { step, name, value, capture } => {
+                let value = if *capture {
+                    let args = interpolate_all(step, &step.args[3..], vars)?;
+                    let output = run_step(&args, session, extra_flags).map_err(|e| failure(step, e))?;
+                    captured_value(&output)
+                } else {
+                    interpolate(value, vars).map_err(|e| failure(step, e))?
+                };
+                vars.insert(name.clone(), value);
+            }
+            Node::If { step, negate, then, otherwise } => {
+                let check_at = if *negate { 2 } else { 1 };
+                let mut args = vec!["assert".to_string()];
+                args.extend(interpolate_all(step, &step.args[check_at..], vars)?);
+                let holds = match invoke(&args, session, extra_flags) {
+                    Ok(_) => true,
+                    Err((_, Some(kind))) if kind == "assertion_failed" => false,
+                    Err((message, _)) => return Err(failure(step, message)),
+                };
+                let branch = if holds != *negate { then } else { otherwise };
+                run_script(branch, session, extra_flags, vars)?;
+            }
+            Node::Repeat { step, count, body } => {
+                let count = interpolate(count, vars).map_err(|e| failure(step, e))?;
+                let n = count
+                    .parse::<u32>()
+                    .map_err(|_| failure(step, format!("Invalid repeat count: '{}'", count)))?;
+                let outer = vars.get("i").cloned();
+                for i in 1..=n {
+                    vars.insert("i".to_string(), i.to_string());
+                    run_script(body, session, extra_flags, vars)?;
+                }
+                match outer {
+                    Some(v) => vars.insert("i".to_string(), v),
+                    None => vars.remove("i"),
+                };
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_line() {
+        assert_eq!(split_line("click @e1").unwrap(), vec!["click", "@e1"]);
+        assert_eq!(
+            split_line(r##"fill "#name" "Jane Doe"  "##).unwrap(),
+            vec!["fill", "#name", "Jane Doe"]
+        );
+        assert_eq!(
+            split_line(r#"eval 'document.title = "a b"'"#).unwrap(),
+            vec!["eval", r#"document.title = "a b""#]
+        );
+        assert_eq!(split_line(r#"type #q it\'s"#).unwrap(), vec!["type", "#q", "it's"]);
+        assert_eq!(split_line(r#"fill #q """#).unwrap(), vec!["fill", "#q", ""]);
+        assert!(split_line(r#"fill #q "open"#).is_err());
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let vars: Vars = [("id".to_string(), "42".to_string())].into_iter().collect();
+        assert_eq!(interpolate("/orders/${id}/edit", &vars).unwrap(), "/orders/42/edit");
+        assert_eq!(interpolate("price: $${id}", &vars).unwrap(), "price: ${id}");
+        assert_eq!(interpolate("${PATH}", &vars).unwrap(), env::var("PATH").unwrap());
+        assert!(interpolate("${AGENT_BROWSER_UNDEFINED_TEST_VAR}", &vars).is_err());
+        assert!(interpolate("${id", &vars).is_err());
+    }
+
+    #[test]
+    fn test_parse_blocks() {
+        let script = "open example.com\nset-var id = get attr @e3 data-id\nrepeat 3\n  if not visible .done\n    click .next\n  else\n    set-var done yes\n  end\nend\n";
+        let nodes = parse_script(script).ok().unwrap();
+        assert_eq!(nodes.len(), 3);
+        assert!(matches!(&nodes[1], Node::SetVar { name, value, capture: true, .. } if name == "id" && value == "get attr @e3 data-id"));
+        let Node::Repeat { count, body, .. } = &nodes[2] else { panic!("expected repeat") };
+        assert_eq!(count, "3");
+        assert!(matches!(&body[0], Node::If { negate: true, then, otherwise, .. } if then.len() == 1 && otherwise.len() == 1));
+    }
+
+    #[test]
+    fn test_parse_block_errors() {
+        assert_eq!(parse_script("if visible #a\nclick #a\n").err().unwrap().0, 1);
+        assert_eq!(parse_script("click #a\nend\n").err().unwrap().0, 2);
+        assert_eq!(parse_script("repeat 2\nelse\nend\n").err().unwrap().0, 2);
+        assert!(parse_script("set-var 1x = get url\n").is_err());
+    }
+
+    #[test]
+    fn test_captured_value() {
+        assert_eq!(captured_value(&serde_json::json!({ "data": { "text": "Hello" } })), "Hello");
+        assert_eq!(captured_value(&serde_json::json!({ "data": { "attribute": "href", "value": "/a" } })), "/a");
+        assert_eq!(captured_value(&serde_json::json!({ "data": { "count": 3 } })), "3");
+    }
+}
@@ -0,0 +1,334 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+use std::process::exit;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::color;
+use crate::connection::{
+    daemon_endpoint, get_socket_dir, idle_remaining, is_daemon_process, is_process_alive,
+    query_daemon_status, read_daemon_pid, stop_daemon, terminate_process,
+};
+use crate::debug;
+use crate::error::{error_envelope, fail, ErrorKind};
+use crate::lock::{lock_session, DEFAULT_LOCK_TIMEOUT};
+use crate::output::format_duration;
+
+/// Per-session runtime files removed by `session prune` and `session kill`
+/// (logs and crash-recovery autosaves are kept)
+const SESSION_FILE_EXTENSIONS: &[&str] = &["sock", "pid", "port", "stream", "idle", "crashed", "snapshot.json"];
+
+/// How long `session kill` waits after SIGTERM before sending SIGKILL
+const TERM_GRACE: Duration = Duration::from_secs(3);
+
+/// Names of all sessions with a pid, socket, port or crash marker file in the socket directory
+fn known_sessions() -> BTreeSet<String> {
+    let mut sessions = BTreeSet::new();
+    if let Ok(entries) = fs::read_dir(get_socket_dir()) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let session = name
+                .strip_suffix(".pid")
+                .or_else(|| name.strip_suffix(".sock"))
+                .or_else(|| name.strip_suffix(".port"))
+                .or_else(|| name.strip_suffix(".crashed"));
+            if let Some(session) = session.filter(|s| !s.is_empty()) {
+                sessions.insert(session.to_string());
+            }
+        }
+    }
+    sessions
+}
+
+fn is_session_alive(session: &str) -> bool {
+    read_daemon_pid(session).map(is_daemon_process).unwrap_or(false)
+}
+
+/// Sessions whose pid file names a running daemon
+pub fn live_sessions() -> Vec<String> {
+    known_sessions().into_iter().filter(|s| is_session_alive(s)).collect()
+}
+
+/// Sessions with leftover files whose pid file is missing or no longer names a daemon
+pub fn stale_sessions() -> Vec<String> {
+    known_sessions().into_iter().filter(|s| !is_session_alive(s)).collect()
+}
+
+/// Delete a session's runtime files, returning the ones that existed
+pub fn remove_session_files(session: &str) -> Vec<PathBuf> {
+    let dir = get_socket_dir();
+    SESSION_FILE_EXTENSIONS
+        .iter()
+        .map(|ext| dir.join(format!("{}.{}", session, ext)))
+        .filter(|path| fs::remove_file(path).is_ok())
+        .collect()
+}
+
+/// Everything known about a running session. Fields reported by the daemon are
+/// omitted if it does not answer (busy, or too old to support `status`).
+fn session_details(session: &str) -> Value {
+    let mut info = json!({
+        "name": session,
+        "pid": read_daemon_pid(session),
+        "socketPath": daemon_endpoint(session),
+    });
+    if let Some(d) = idle_remaining(session) {
+        info["idleRemainingMs"] = json!(d.as_millis() as u64);
+    }
+    if let Ok(Some(status)) = query_daemon_status(session) {
+        for key in ["uptimeMs", "launched", "url", "tabCount"] {
+            if let Some(v) = status.get(key) {
+                info[key] = v.clone();
+            }
+        }
+        if let Some(opts) = status.get("launchOptions") {
+            info["launchOptions"] = debug::redact(opts);
+        }
+    }
+    info
+}
+
+/// One-line summary of launch options, e.g. "headed, proxy=http://***@host:8080"
+fn describe_launch_options(opts: &Value) -> String {
+    let mut parts = Vec::new();
+    if opts.get("headed").and_then(|v| v.as_bool()) == Some(true) {
+        parts.push("headed".to_string());
+    }
+    for (key, label) in [
+        ("executablePath", "executable"),
+        ("args", "args"),
+        ("userAgent", "user-agent"),
+        ("proxy", "proxy"),
+        ("proxyBypass", "proxy-bypass"),
+    ] {
+        if let Some(v) = opts.get(key).and_then(|v| v.as_str()) {
+            parts.push(format!("{}={}", label, v));
+        }
+    }
+    if let Some(exts) = opts.get("extensions").and_then(|v| v.as_array()) {
+        let exts: Vec<&str> = exts.iter().filter_map(|e| e.as_str()).collect();
+        parts.push(format!("extensions={}", exts.join(",")));
+    }
+    if let Some(ms) = opts.get("idleTimeoutMs").and_then(|v| v.as_u64()) {
+        parts.push(format!("idle-timeout={}", format_duration(Duration::from_millis(ms))));
+    }
+    if parts.is_empty() {
+        "defaults (headless)".to_string()
+    } else {
+        parts.join(", ")
+    }
+}
+
+fn wait_for_exit(pid: u32, timeout: Duration) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if !is_process_alive(pid) {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    !is_process_alive(pid)
+}
+
+/// Signal `pid` only while it is still the daemon, so a reused pid is left alone
+fn signal_daemon(pid: u32, force: bool) {
+    if is_daemon_process(pid) {
+        terminate_process(pid, force);
+    }
+}
+
+/// Stop a session's daemon: ask it to close, then SIGTERM, then SIGKILL.
+/// Holds the session lock so a command starting the daemon at the same time
+/// cannot have its new socket or pid file removed.
+fn kill_session(session: &str) -> Result<(), String> {
+    let _lock = lock_session(session, DEFAULT_LOCK_TIMEOUT).map_err(|e| e.message)?;
+    let Some(pid) = read_daemon_pid(session).filter(|p| is_daemon_process(*p)) else {
+        remove_session_files(session);
+        return Err(format!("No running daemon for session '{}'", session));
+    };
+    if stop_daemon(session).is_err() || !wait_for_exit(pid, TERM_GRACE) {
+        debug::log(&format!("session '{}' did not close, sending SIGTERM to {}", session, pid));
+        signal_daemon(pid, false);
+        if !wait_for_exit(pid, TERM_GRACE) {
+            debug::log(&format!("session '{}' still running, sending SIGKILL to {}", session, pid));
+            signal_daemon(pid, true);
+            if !wait_for_exit(pid, TERM_GRACE) {
+                return Err(format!("Could not kill daemon for session '{}' (pid {})", session, pid));
+            }
+        }
+    }
+    // A killed daemon cannot clean up after itself
+    remove_session_files(session);
+    Ok(())
+}
+
+pub fn run_session(args: &[String], session: &str, json_mode: bool) {
+    let subcommand = args.get(1).map(|s| s.as_str());
+
+    match subcommand {
+        Some("list") => {
+            let sessions = live_sessions();
+
+            if json_mode {
+                let details: Vec<Value> = sessions.iter().map(|s| session_details(s)).collect();
+                println!("{}", json!({ "success": true, "data": { "sessions": details } }));
+            } else if sessions.is_empty() {
+                println!("No active sessions");
+            } else {
+                println!("Active sessions:");
+                for s in &sessions {
+                    let marker = if s == session { color::cyan("→") } else { " ".to_string() };
+                    match idle_remaining(s) {
+                        Some(d) => println!(
+                            "{} {} {}",
+                            marker,
+                            s,
+                            color::dim(&format!("(idle shutdown in {})", format_duration(d)))
+                        ),
+                        None => println!("{} {}", marker, s),
+                    }
+                }
+            }
+        }
+        Some("info") => {
+            let name = args.get(2).map(|s| s.as_str()).unwrap_or(session);
+            if !is_session_alive(name) {
+                let msg = format!("No running daemon for session '{}'", name);
+                fail(ErrorKind::ActionFailed, &msg, Some("session"), json_mode);
+            }
+            let info = session_details(name);
+            if json_mode {
+                println!("{}", json!({ "success": true, "data": info }));
+                return;
+            }
+            println!("{}", color::bold(name));
+            if let Some(pid) = info["pid"].as_u64() {
+                println!("  pid:      {}", pid);
+            }
+            if let Some(ms) = info["uptimeMs"].as_u64() {
+                println!("  uptime:   {}", format_duration(Duration::from_millis(ms)));
+            }
+            println!("  socket:   {}", info["socketPath"].as_str().unwrap_or_default());
+            match info["launched"].as_bool() {
+                Some(true) => {
+                    println!("  url:      {}", info["url"].as_str().unwrap_or("(none)"));
+                    println!("  tabs:     {}", info["tabCount"].as_u64().unwrap_or(0));
+                }
+                Some(false) => println!("  browser:  not launched"),
+                None => println!("  browser:  {}", color::dim("(daemon did not report status)")),
+            }
+            if let Some(opts) = info.get("launchOptions") {
+                println!("  options:  {}", describe_launch_options(opts));
+            }
+            if let Some(ms) = info["idleRemainingMs"].as_u64() {
+                println!("  idle:     shutdown in {}", format_duration(Duration::from_millis(ms)));
+            }
+        }
+        Some("kill") => {
+            let targets: Vec<String> = match args.get(2).map(|s| s.as_str()) {
+                Some("--all") => live_sessions(),
+                Some(name) => vec![name.to_string()],
+                None => fail(
+                    ErrorKind::ParseError,
+                    "Usage: agent-browser session kill <name|--all>",
+                    Some("session"),
+                    json_mode,
+                ),
+            };
+            let mut killed = Vec::new();
+            let mut errors = Vec::new();
+            for target in &targets {
+                match kill_session(target) {
+                    Ok(()) => killed.push(target.clone()),
+                    Err(e) => errors.push(e),
+                }
+            }
+            if json_mode {
+                let mut out = if errors.is_empty() {
+                    json!({ "success": true })
+                } else {
+                    error_envelope(ErrorKind::ActionFailed, &errors.join("; "), Some("session"))
+                };
+                out["data"] = json!({ "killed": killed });
+                println!("{}", out);
+            } else {
+                if targets.is_empty() {
+                    println!("No active sessions");
+                }
+                for name in &killed {
+                    println!("{} Killed session {}", color::success_indicator(), name);
+                }
+                for e in &errors {
+                    eprintln!("{} {}", color::error_indicator(), e);
+                }
+            }
+            if !errors.is_empty() {
+                exit(ErrorKind::ActionFailed.exit_code());
+            }
+        }
+        Some("prune") => {
+            let mut removed: Vec<String> = Vec::new();
+            for stale in stale_sessions() {
+                // Skip sessions another command holds (it may be starting a daemon
+                // for them), and check again under the lock that they are stale
+                let Ok(_lock) = lock_session(&stale, Duration::ZERO) else {
+                    continue;
+                };
+                if is_session_alive(&stale) {
+                    continue;
+                }
+                removed.extend(
+                    remove_session_files(&stale)
+                        .into_iter()
+                        .map(|p| p.display().to_string()),
+                );
+            }
+            if json_mode {
+                println!("{}", json!({ "success": true, "data": { "removed": removed } }));
+            } else if removed.is_empty() {
+                println!("No stale session files");
+            } else {
+                for path in &removed {
+                    println!("{} Removed {}", color::success_indicator(), path);
+                }
+            }
+        }
+        None => {
+            // Just show current session
+            if json_mode {
+                println!("{}", json!({ "success": true, "data": { "session": session } }));
+            } else {
+                println!("{}", session);
+            }
+        }
+        Some(other) => fail(
+            ErrorKind::ParseError,
+            &format!("Unknown session operation: {} (expected list, info, kill, or prune)", other),
+            Some("session"),
+            json_mode,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_launch_options() {
+        assert_eq!(describe_launch_options(&json!({ "headed": false })), "defaults (headless)");
+        let opts = json!({
+            "headed": true,
+            "proxy": "http://***@proxy.com:8080",
+            "extensions": ["/a", "/b"],
+            "idleTimeoutMs": 1_800_000
+        });
+        assert_eq!(
+            describe_launch_options(&opts),
+            "headed, proxy=http://***@proxy.com:8080, extensions=/a,/b, idle-timeout=30m 0s"
+        );
+    }
+}
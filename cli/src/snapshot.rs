@@ -0,0 +1,696 @@
+//! Client-side snapshot handling: `--diff` against the session's previous
+//! snapshot, `--save <file>`, and `snapshot diff <a> <b>` for saved snapshots.
+//!
+//! The last snapshot of each session is kept in `<socket_dir>/<session>.snapshot.json`
+//! together with the options it was taken with, since a diff between e.g. `-i` and
+//! a full tree would be meaningless.
+//!
+//! `--format json|yaml` parses the daemon's text tree (one `- role "name" [attr]: text`
+//! line per node, nested by indentation) into `SnapshotNode`s.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::budget::{fit_snapshot, truncate_lines};
+use crate::color;
+use crate::connection::{get_socket_dir, Response};
+use crate::error::{fail, ErrorKind};
+
+/// Snapshot options that change what the tree contains
+const VIEW_OPTIONS: &[&str] = &["interactive", "compact", "maxDepth", "selector", "visible", "page"];
+
+/// Above this many line pairs, the changed middle of two snapshots is not
+/// aligned line by line (everything in it counts as removed and re-added)
+const MAX_DIFF_CELLS: usize = 16_000_000;
+
+/// One difference between two snapshots
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    Added(String),
+    Removed(String),
+    /// The same element (same role at the same depth, with the same name or ref)
+    /// with different text or attributes
+    Changed { old: String, new: String },
+    /// An unchanged line whose ref was renumbered, e.g. because a node was inserted above it
+    Renumbered { old: String, new: String },
+}
+
+fn get_last_snapshot_path(session: &str) -> PathBuf {
+    get_socket_dir().join(format!("{}.snapshot.json", session))
+}
+
+/// `(indent, role, name, ref)` of a snapshot line like `  - button "Save" [ref=e3]`
+pub fn parse_line(line: &str) -> (usize, &str, Option<&str>, Option<&str>) {
+    let indent = line.len() - line.trim_start().len();
+    let body = line.trim_start().trim_start_matches("- ");
+    let role_end = body.find([' ', ':']).unwrap_or(body.len());
+    let role = &body[..role_end];
+    let rest = &body[role_end..];
+    let name = rest
+        .strip_prefix(" \"")
+        .and_then(|r| r.find('"').map(|end| &r[..end]));
+    let ref_id = line
+        .find("[ref=")
+        .and_then(|start| line[start + 5..].split(']').next());
+    (indent, role, name, ref_id)
+}
+
+/// A snapshot line without its `[ref=eN]`, for matching lines across snapshots
+fn strip_ref(line: &str) -> String {
+    match line.find(" [ref=").and_then(|start| line[start..].find(']').map(|len| (start, start + len + 1))) {
+        Some((start, end)) => format!("{}{}", &line[..start], &line[end..]),
+        None => line.to_string(),
+    }
+}
+
+fn same_element(old: &str, new: &str) -> bool {
+    let (old_indent, old_role, old_name, old_ref) = parse_line(old);
+    let (new_indent, new_role, new_name, new_ref) = parse_line(new);
+    old_indent == new_indent && old_role == new_role && (old_name == new_name || (old_ref.is_some() && old_ref == new_ref))
+}
+
+/// Line pairs `(old index, new index)` of a longest common subsequence
+fn common_lines<T: PartialEq>(old: &[T], new: &[T]) -> Vec<(usize, usize)> {
+    let (n, m) = (old.len(), new.len());
+    if n == 0 || m == 0 || n.saturating_mul(m) > MAX_DIFF_CELLS {
+        return Vec::new();
+    }
+    // lengths[i][j]: LCS length of old[i..] and new[j..]
+    let mut lengths = vec![0u32; (n + 1) * (m + 1)];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[i * (m + 1) + j] = if old[i] == new[j] {
+                lengths[(i + 1) * (m + 1) + j + 1] + 1
+            } else {
+                lengths[(i + 1) * (m + 1) + j].max(lengths[i * (m + 1) + j + 1])
+            };
+        }
+    }
+    let (mut i, mut j, mut pairs) = (0, 0, Vec::new());
+    while i < n && j < m {
+        if old[i] == new[j] {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[(i + 1) * (m + 1) + j] >= lengths[i * (m + 1) + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+/// Turn one run of removed and added lines into changes, pairing lines that
+/// describe the same element
+fn push_hunk(changes: &mut Vec<Change>, removed: &[&str], added: &[&str]) {
+    let mut paired = vec![false; removed.len()];
+    let mut hunk = Vec::new();
+    for new in added {
+        let old = (0..removed.len()).find(|&k| !paired[k] && same_element(removed[k], new));
+        match old {
+            Some(k) => {
+                paired[k] = true;
+                hunk.push(Change::Changed { old: removed[k].to_string(), new: new.to_string() });
+            }
+            None => hunk.push(Change::Added(new.to_string())),
+        }
+    }
+    for (old, _) in removed.iter().zip(&paired).filter(|(_, paired)| !**paired) {
+        changes.push(Change::Removed(old.to_string()));
+    }
+    changes.append(&mut hunk);
+}
+
+/// Differences between two snapshot trees, in tree order.
+///
+/// Refs are numbered in tree order on every snapshot, so one inserted node
+/// shifts the ref of every line after it. Lines are matched with their refs
+/// stripped, and a matched line whose ref moved is reported as `Renumbered`
+/// rather than changed.
+pub fn diff_snapshots(old: &str, new: &str) -> Vec<Change> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let old_keys: Vec<String> = old.iter().map(|l| strip_ref(l)).collect();
+    let new_keys: Vec<String> = new.iter().map(|l| strip_ref(l)).collect();
+    // Only align the part between the common head and tail
+    let head = old_keys.iter().zip(&new_keys).take_while(|(a, b)| a == b).count();
+    let tail = old_keys[head..]
+        .iter()
+        .rev()
+        .zip(new_keys[head..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mid = common_lines(&old_keys[head..old.len() - tail], &new_keys[head..new.len() - tail]);
+    let pairs = (0..head)
+        .map(|k| (k, k))
+        .chain(mid.into_iter().map(|(oi, nj)| (head + oi, head + nj)))
+        .chain((0..tail).map(|k| (old.len() - tail + k, new.len() - tail + k)))
+        .chain([(old.len(), new.len())]);
+
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    for (oi, nj) in pairs {
+        push_hunk(&mut changes, &old[i..oi], &new[j..nj]);
+        if oi < old.len() {
+            if let (Some(old_ref), Some(new_ref)) = (parse_line(old[oi]).3, parse_line(new[nj]).3) {
+                if old_ref != new_ref {
+                    changes.push(Change::Renumbered { old: old_ref.to_string(), new: new_ref.to_string() });
+                }
+            }
+        }
+        i = oi + 1;
+        j = nj + 1;
+    }
+    changes
+}
+
+/// Renumbered refs as ranges that moved together: `e5-e40 → e6-e41, e44 → e43`
+fn format_renumbered(changes: &[Change]) -> Option<String> {
+    let number = |r: &str| r.strip_prefix('e').and_then(|n| n.parse::<i64>().ok());
+    let mut ranges: Vec<(&str, &str, &str, &str)> = Vec::new();
+    for change in changes {
+        let Change::Renumbered { old, new } = change else { continue };
+        if let Some(last) = ranges.last_mut() {
+            let follows = |prev: &str, next: &str| matches!((number(prev), number(next)), (Some(a), Some(b)) if b == a + 1);
+            if follows(last.1, old) && follows(last.3, new) {
+                last.1 = old;
+                last.3 = new;
+                continue;
+            }
+        }
+        ranges.push((old, old, new, new));
+    }
+    if ranges.is_empty() {
+        return None;
+    }
+    let list: Vec<String> = ranges
+        .iter()
+        .map(|&(old_first, old_last, new_first, new_last)| {
+            if old_first == old_last {
+                format!("{} → {}", old_first, new_first)
+            } else {
+                format!("{}-{} → {}-{}", old_first, old_last, new_first, new_last)
+            }
+        })
+        .collect();
+    Some(format!("Renumbered: {}", list.join(", ")))
+}
+
+/// `3 added, 1 removed, 2 changed`
+fn summary(changes: &[Change]) -> String {
+    let count = |f: fn(&Change) -> bool| changes.iter().filter(|c| f(c)).count();
+    let mut summary = format!(
+        "{} added, {} removed, {} changed",
+        count(|c| matches!(c, Change::Added(_))),
+        count(|c| matches!(c, Change::Removed(_))),
+        count(|c| matches!(c, Change::Changed { .. }))
+    );
+    let renumbered = count(|c| matches!(c, Change::Renumbered { .. }));
+    if renumbered > 0 {
+        summary.push_str(&format!(", {} renumbered", renumbered));
+    }
+    summary
+}
+
+/// Text form of a diff: `+` added, `-` removed, `~` changed (shown as it is now),
+/// with renumbered refs on one line after the summary
+pub fn format_diff(changes: &[Change]) -> String {
+    if changes.is_empty() {
+        return "No changes".to_string();
+    }
+    let mut out = vec![summary(changes)];
+    out.extend(format_renumbered(changes));
+    for change in changes {
+        match change {
+            Change::Added(line) => out.push(format!("+ {}", line)),
+            Change::Removed(line) => out.push(format!("- {}", line)),
+            Change::Changed { new, .. } => out.push(format!("~ {}", new)),
+            Change::Renumbered { .. } => {}
+        }
+    }
+    out.join("\n")
+}
+
+/// JSON form of a diff, for --json
+pub fn diff_json(changes: &[Change]) -> Value {
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    let mut changed = Vec::new();
+    let mut renumbered = serde_json::Map::new();
+    for change in changes {
+        match change {
+            Change::Added(line) => added.push(json!(line)),
+            Change::Removed(line) => removed.push(json!(line)),
+            Change::Changed { old, new } => changed.push(json!({ "old": old, "new": new })),
+            Change::Renumbered { old, new } => {
+                renumbered.insert(old.clone(), json!(new));
+            }
+        }
+    }
+    json!({ "added": added, "removed": removed, "changed": changed, "renumbered": renumbered })
+}
+
+/// One node of a parsed snapshot tree
+#[derive(Serialize, Debug, PartialEq, Default)]
+pub struct SnapshotNode {
+    pub role: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(rename = "ref", skip_serializing_if = "Option::is_none")]
+    pub ref_id: Option<String>,
+    /// `[level=1]`, `[checked]`, `[nth=1]`, ...: numbers and booleans as such,
+    /// bare attributes as `true`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, Value>,
+    /// Inline text after the colon, e.g. a paragraph's text or a textbox's value
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Metadata lines such as `- /url: https://...`, keyed without the slash
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub props: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<SnapshotNode>,
+}
+
+/// Undo YAML quoting of a scalar (`"..."` or `'...'`), as Playwright writes
+/// text that would otherwise not be valid YAML
+fn unquote(s: &str) -> String {
+    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+        if let Ok(v) = serde_json::from_str::<String>(s) {
+            return v;
+        }
+    }
+    if s.len() >= 2 && s.starts_with('\'') && s.ends_with('\'') {
+        return s[1..s.len() - 1].replace("''", "'");
+    }
+    s.to_string()
+}
+
+/// `"level"` -> 1, `"true"` -> true, anything else stays a string
+fn attribute_value(s: &str) -> Value {
+    if let Ok(n) = s.parse::<i64>() {
+        return json!(n);
+    }
+    match s {
+        "true" => json!(true),
+        "false" => json!(false),
+        _ => json!(s),
+    }
+}
+
+/// Parse `role "name" [attr]...: text` (the part of a line after `- `)
+fn parse_node(body: &str) -> SnapshotNode {
+    // A whole key Playwright had to quote, e.g. `'link "a: b"': text`
+    let (key, after) = match body.strip_prefix('\'') {
+        Some(quoted) => {
+            let mut end = 0;
+            let bytes = quoted.as_bytes();
+            while end < bytes.len() {
+                if bytes[end] == b'\'' {
+                    if bytes.get(end + 1) == Some(&b'\'') {
+                        end += 2;
+                        continue;
+                    }
+                    break;
+                }
+                end += 1;
+            }
+            (quoted[..end].replace("''", "'"), quoted.get(end + 1..).unwrap_or_default().to_string())
+        }
+        None => (body.to_string(), String::new()),
+    };
+    let line = format!("{}{}", key, after);
+
+    let mut node = SnapshotNode::default();
+    let role_end = line.find([' ', ':']).unwrap_or(line.len());
+    node.role = line[..role_end].to_string();
+    let mut rest = &line[role_end..];
+
+    if let Some(quoted) = rest.strip_prefix(" \"") {
+        let mut name = String::new();
+        let mut chars = quoted.char_indices();
+        let mut end = quoted.len();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    if let Some((_, escaped)) = chars.next() {
+                        name.push(escaped);
+                    }
+                }
+                '"' => {
+                    end = i + 1;
+                    break;
+                }
+                c => name.push(c),
+            }
+        }
+        node.name = Some(name);
+        rest = &quoted[end..];
+    }
+
+    loop {
+        rest = rest.trim_start_matches(' ');
+        let Some(attr) = rest.strip_prefix('[') else { break };
+        let Some(close) = attr.find(']') else { break };
+        let (key, value) = match attr[..close].split_once('=') {
+            Some((k, v)) => (k, Some(v)),
+            None => (&attr[..close], None),
+        };
+        match (key, value) {
+            ("ref", Some(id)) => node.ref_id = Some(id.to_string()),
+            (key, Some(v)) => {
+                node.attributes.insert(key.to_string(), attribute_value(v));
+            }
+            (key, None) => {
+                node.attributes.insert(key.to_string(), json!(true));
+            }
+        }
+        rest = &attr[close + 1..];
+    }
+
+    if let Some(text) = rest.strip_prefix(':') {
+        let text = text.trim();
+        if !text.is_empty() {
+            node.text = Some(unquote(text));
+        }
+    }
+    node
+}
+
+fn attach(stack: &mut [(usize, SnapshotNode)], roots: &mut Vec<SnapshotNode>, node: SnapshotNode) {
+    match stack.last_mut() {
+        Some((_, parent)) => parent.children.push(node),
+        None => roots.push(node),
+    }
+}
+
+/// Parse a snapshot's text tree. Lines that aren't nodes (e.g. `(empty)`) are skipped.
+pub fn parse_tree(text: &str) -> Vec<SnapshotNode> {
+    let mut roots = Vec::new();
+    // Open nodes with their indentation, innermost last
+    let mut stack: Vec<(usize, SnapshotNode)> = Vec::new();
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        let Some(body) = trimmed.strip_prefix("- ") else { continue };
+        let indent = line.len() - trimmed.len();
+        while stack.last().is_some_and(|(i, _)| *i >= indent) {
+            if let Some((_, node)) = stack.pop() {
+                attach(&mut stack, &mut roots, node);
+            }
+        }
+        if let Some(prop) = body.strip_prefix('/') {
+            let (key, value) = prop.split_once(':').unwrap_or((prop, ""));
+            if let Some((_, parent)) = stack.last_mut() {
+                parent.props.insert(key.to_string(), unquote(value.trim()));
+            }
+            continue;
+        }
+        stack.push((indent, parse_node(body)));
+    }
+    while let Some((_, node)) = stack.pop() {
+        attach(&mut stack, &mut roots, node);
+    }
+    roots
+}
+
+/// A YAML scalar: plain if that reads back as the same string, else double-quoted
+fn yaml_string(s: &str) -> String {
+    let plain = !s.is_empty()
+        && s.chars().all(|c| c.is_alphanumeric() || " _-./@()".contains(c))
+        && !s.starts_with([' ', '-', '@'])
+        && !s.ends_with(' ')
+        && s.parse::<f64>().is_err()
+        && !["true", "false", "null", "yes", "no", "on", "off", "~"].contains(&s.to_lowercase().as_str());
+    if plain {
+        s.to_string()
+    } else {
+        serde_json::to_string(s).unwrap_or_default()
+    }
+}
+
+fn write_yaml(nodes: &[SnapshotNode], indent: usize, out: &mut Vec<String>) {
+    let pad = "  ".repeat(indent);
+    for node in nodes {
+        out.push(format!("{}- role: {}", pad, yaml_string(&node.role)));
+        if let Some(name) = &node.name {
+            out.push(format!("{}  name: {}", pad, yaml_string(name)));
+        }
+        if let Some(id) = &node.ref_id {
+            out.push(format!("{}  ref: {}", pad, id));
+        }
+        if !node.attributes.is_empty() {
+            out.push(format!("{}  attributes:", pad));
+            for (key, value) in &node.attributes {
+                let value = match value {
+                    Value::String(s) => yaml_string(s),
+                    v => v.to_string(),
+                };
+                out.push(format!("{}    {}: {}", pad, key, value));
+            }
+        }
+        if let Some(text) = &node.text {
+            out.push(format!("{}  text: {}", pad, yaml_string(text)));
+        }
+        if !node.props.is_empty() {
+            out.push(format!("{}  props:", pad));
+            for (key, value) in &node.props {
+                out.push(format!("{}    {}: {}", pad, key, yaml_string(value)));
+            }
+        }
+        if !node.children.is_empty() {
+            out.push(format!("{}  children:", pad));
+            write_yaml(&node.children, indent + 2, out);
+        }
+    }
+}
+
+/// The tree as a YAML sequence of nodes
+pub fn to_yaml(nodes: &[SnapshotNode]) -> String {
+    let mut out = Vec::new();
+    write_yaml(nodes, 0, &mut out);
+    if out.is_empty() {
+        return "[]".to_string();
+    }
+    out.join("\n")
+}
+
+/// The options of a snapshot command that affect the tree
+fn view_options(cmd: &Value) -> Value {
+    let mut opts = json!({});
+    for key in VIEW_OPTIONS {
+        if let Some(v) = cmd.get(*key) {
+            opts[*key] = v.clone();
+        }
+    }
+    opts
+}
+
+/// After a successful `snapshot`: remember it for the next `--diff`, write it to
+/// `--save`, then replace the tree in the response with what changed (`--diff`)
+/// or with the `--format` asked for
+pub fn finish_snapshot(cmd: &Value, resp: &mut Response, session: &str, json_mode: bool) -> Result<(), String> {
+    let Some(data) = resp.data.as_mut() else {
+        return Ok(());
+    };
+    let tree = data["snapshot"].as_str().unwrap_or_default().to_string();
+    let options = view_options(cmd);
+
+    if let Some(page) = data.get("page").filter(|_| !json_mode) {
+        let mut note = format!("Page {} of {}", page["page"], page["pages"]);
+        if page["atEnd"] == true {
+            note.push_str(" (end of document)");
+        }
+        eprintln!("{}", color::dim(&note));
+    }
+
+    if let Some(path) = cmd["save"].as_str() {
+        fs::write(path, format!("{}\n", tree)).map_err(|e| format!("Could not write {}: {}", path, e))?;
+    }
+
+    let path = get_last_snapshot_path(session);
+    let previous: Option<Value> = fs::read_to_string(&path).ok().and_then(|s| serde_json::from_str(&s).ok());
+    let _ = fs::write(&path, json!({ "options": options, "snapshot": tree }).to_string());
+
+    if cmd["diff"] == true {
+        apply_diff(data, &tree, previous.filter(|p| p["options"] == options));
+        if let Some(max) = cmd["maxChars"].as_u64() {
+            let shown = truncate_lines(data["snapshot"].as_str().unwrap_or_default(), max as usize);
+            data["snapshot"] = json!(shown);
+        }
+        return Ok(());
+    }
+    let tree = match cmd["maxChars"].as_u64() {
+        Some(max) => {
+            let fitted = fit_snapshot(&tree, max as usize);
+            if fitted.len() != tree.len() {
+                let (full, shown) = (tree.chars().count(), fitted.chars().count());
+                if !json_mode {
+                    let note = format!("Snapshot reduced from {} to {} characters to fit the budget", full, shown);
+                    eprintln!("{}", color::dim(&note));
+                }
+                data["budget"] = json!({ "maxChars": max, "fullChars": full, "shownChars": shown });
+                data["snapshot"] = json!(fitted);
+            }
+            fitted
+        }
+        None => tree,
+    };
+    match cmd["format"].as_str() {
+        // With --json the tree goes out as data; otherwise it's printed like the text tree
+        Some("json") if json_mode => {
+            data["tree"] = json!(parse_tree(&tree));
+            if let Some(obj) = data.as_object_mut() {
+                obj.remove("snapshot");
+            }
+        }
+        Some("json") => {
+            data["snapshot"] = json!(serde_json::to_string_pretty(&parse_tree(&tree)).unwrap_or_default());
+        }
+        Some("yaml") => data["snapshot"] = json!(to_yaml(&parse_tree(&tree))),
+        _ => {}
+    }
+    Ok(())
+}
+
+fn apply_diff(data: &mut Value, tree: &str, previous: Option<Value>) {
+    let Some(previous) = previous else {
+        eprintln!(
+            "{}",
+            color::dim("No previous snapshot with the same options in this session; showing the full snapshot")
+        );
+        data["diff"] = Value::Null;
+        return;
+    };
+
+    let changes = diff_snapshots(previous["snapshot"].as_str().unwrap_or_default(), tree);
+    // Only the refs that appear in the diff are worth sending back
+    let shown: HashSet<&str> = changes
+        .iter()
+        .filter_map(|c| match c {
+            Change::Added(line) | Change::Changed { new: line, .. } => parse_line(line).3,
+            Change::Removed(_) | Change::Renumbered { .. } => None,
+        })
+        .collect();
+    if let Some(refs) = data.get_mut("refs").and_then(|r| r.as_object_mut()) {
+        refs.retain(|id, _| shown.contains(id.as_str()));
+    }
+    data["diff"] = diff_json(&changes);
+    data["snapshot"] = json!(format_diff(&changes));
+}
+
+/// `snapshot diff <a> <b>`: compare two files written by `snapshot --save`
+pub fn run_snapshot_diff(args: &[String], json_mode: bool) {
+    let files: Vec<&String> = args.iter().skip(2).filter(|a| !a.starts_with('-')).collect();
+    let [a, b] = files.as_slice() else {
+        fail(
+            ErrorKind::ParseError,
+            "Usage: agent-browser snapshot diff <old-file> <new-file>",
+            Some("snapshot"),
+            json_mode,
+        );
+    };
+    let read = |path: &str| match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) => fail(ErrorKind::ActionFailed, &format!("Could not read {}: {}", path, e), Some("snapshot"), json_mode),
+    };
+    let changes = diff_snapshots(&read(a), &read(b));
+    if json_mode {
+        println!("{}", json!({ "success": true, "data": { "diff": diff_json(&changes) } }));
+    } else {
+        println!("{}", format_diff(&changes));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BEFORE: &str = "- heading \"Cart\" [ref=e1] [level=1]\n- listitem: Apples\n- button \"Checkout\" [ref=e2]\n- textbox \"Coupon\" [ref=e3]";
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(parse_line("  - button \"Save\" [ref=e3]"), (2, "button", Some("Save"), Some("e3")));
+        assert_eq!(parse_line("- paragraph: Some text"), (0, "paragraph", None, None));
+    }
+
+    #[test]
+    fn test_diff_snapshots() {
+        let after = "- heading \"Cart\" [ref=e1] [level=1]\n- listitem: Apples\n- listitem: Pears\n- button \"Checkout\" [ref=e3] [disabled]\n- textbox \"Coupon\" [ref=e4]";
+        let changes = diff_snapshots(BEFORE, after);
+        assert_eq!(
+            changes,
+            vec![
+                Change::Added("- listitem: Pears".to_string()),
+                Change::Changed {
+                    old: "- button \"Checkout\" [ref=e2]".to_string(),
+                    new: "- button \"Checkout\" [ref=e3] [disabled]".to_string()
+                },
+                Change::Renumbered { old: "e3".to_string(), new: "e4".to_string() },
+            ]
+        );
+        assert_eq!(
+            format_diff(&changes),
+            "1 added, 0 removed, 1 changed, 1 renumbered\nRenumbered: e3 → e4\n+ - listitem: Pears\n~ - button \"Checkout\" [ref=e3] [disabled]"
+        );
+    }
+
+    #[test]
+    fn test_diff_insert_near_top_renumbers() {
+        let rows = |first: usize| -> Vec<String> {
+            (0..40).map(|k| format!("  - link \"Item {}\" [ref=e{}]", k, first + k)).collect()
+        };
+        let before = format!("- banner:\n  - link \"Home\" [ref=e1]\n{}", rows(2).join("\n"));
+        let after = format!(
+            "- banner:\n  - link \"Home\" [ref=e1]\n  - button \"Sale\" [ref=e2]\n{}",
+            rows(3).join("\n")
+        );
+        let changes = diff_snapshots(&before, &after);
+        assert_eq!(changes[0], Change::Added("  - button \"Sale\" [ref=e2]".to_string()));
+        assert_eq!(changes.iter().filter(|c| matches!(c, Change::Renumbered { .. })).count(), 40);
+        assert_eq!(
+            format_diff(&changes),
+            "1 added, 0 removed, 0 changed, 40 renumbered\nRenumbered: e2-e41 → e3-e42\n+   - button \"Sale\" [ref=e2]"
+        );
+        assert_eq!(diff_json(&changes)["renumbered"]["e41"], json!("e42"));
+    }
+
+    #[test]
+    fn test_parse_tree() {
+        let text = "- navigation:\n  - link \"Home \\\"new\\\"\" [ref=e1]:\n    - /url: /home\n- heading \"Cart\" [ref=e2] [level=1]\n- checkbox \"Gift\" [ref=e3] [checked] [nth=1]\n- paragraph: \"Total: $42\"\n- 'button \"a: b\"' [ref=e4]";
+        let tree = parse_tree(text);
+        assert_eq!(tree.len(), 5);
+        assert_eq!(tree[0].role, "navigation");
+        let link = &tree[0].children[0];
+        assert_eq!(link.name.as_deref(), Some("Home \"new\""));
+        assert_eq!(link.ref_id.as_deref(), Some("e1"));
+        assert_eq!(link.props["url"], "/home");
+        assert_eq!(tree[1].attributes["level"], json!(1));
+        assert_eq!(tree[2].attributes["checked"], json!(true));
+        assert_eq!(tree[2].attributes["nth"], json!(1));
+        assert_eq!(tree[3].text.as_deref(), Some("Total: $42"));
+        assert_eq!((tree[4].role.as_str(), tree[4].name.as_deref()), ("button", Some("a: b")));
+        assert_eq!(tree[4].ref_id.as_deref(), Some("e4"));
+        assert!(parse_tree("(empty)").is_empty());
+    }
+
+    #[test]
+    fn test_to_yaml() {
+        let tree = parse_tree("- list:\n  - listitem: \"yes\"\n  - link \"Docs\" [ref=e1]\n- heading \"Hi\" [ref=e2] [level=2]");
+        assert_eq!(
+            to_yaml(&tree),
+            "- role: list\n  children:\n    - role: listitem\n      text: \"yes\"\n    - role: link\n      name: Docs\n      ref: e1\n- role: heading\n  name: Hi\n  ref: e2\n  attributes:\n    level: 2"
+        );
+    }
+
+    #[test]
+    fn test_diff_removed_and_unchanged() {
+        let after = "- heading \"Cart\" [ref=e1] [level=1]\n- button \"Checkout\" [ref=e2]\n- textbox \"Coupon\" [ref=e3]";
+        assert_eq!(diff_snapshots(BEFORE, after), vec![Change::Removed("- listitem: Apples".to_string())]);
+        assert_eq!(format_diff(&diff_snapshots(BEFORE, BEFORE)), "No changes");
+    }
+}
@@ -0,0 +1,436 @@
+//! `screenshot --compare`: visual regression checks against a baseline PNG.
+//!
+//! The daemon takes the screenshot as usual; the CLI decodes it and the
+//! baseline, and compares them pixel by pixel the way pixelmatch does: colors
+//! are compared in YIQ space against `--threshold`, and pixels that only differ
+//! because of anti-aliasing are ignored. Differing pixels are drawn red (and
+//! anti-aliased ones yellow) over a faded copy of the screenshot in the diff image.
+
+use std::fs;
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::connection::Response;
+use crate::error::{error_envelope, print_error, ErrorKind};
+
+/// Default for `--threshold`: how different two colors may be (0 to 1) before
+/// their pixels count as mismatched
+pub const DEFAULT_THRESHOLD: f64 = 0.1;
+
+/// Largest YIQ distance between two colors (black and white)
+const MAX_YIQ_DELTA: f64 = 35215.0;
+
+/// How strongly unchanged pixels show through in the diff image
+const FADED_ALPHA: f64 = 0.1;
+
+const MISMATCH_COLOR: [u8; 3] = [255, 0, 0];
+const ANTIALIAS_COLOR: [u8; 3] = [255, 255, 0];
+
+/// An 8-bit RGBA image
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+pub struct Comparison {
+    pub mismatched: u64,
+    pub antialiased: u64,
+    pub diff: Image,
+}
+
+pub fn decode_png(bytes: &[u8]) -> Result<Image, String> {
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(|e| e.to_string())?;
+    let pixels = &buf[..info.buffer_size()];
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels.chunks(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+        png::ColorType::GrayscaleAlpha => pixels.chunks(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        png::ColorType::Indexed => return Err("unexpected indexed color after expansion".to_string()),
+    };
+    Ok(Image { width: info.width, height: info.height, rgba })
+}
+
+pub fn encode_png(image: &Image) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, image.width, image.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    writer.write_image_data(&image.rgba).map_err(|e| e.to_string())?;
+    writer.finish().map_err(|e| e.to_string())?;
+    Ok(out)
+}
+
+/// Decode standard base64, as in the daemon's screenshot response
+fn decode_base64(text: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut acc, mut bits) = (0u32, 0);
+    for c in text.bytes().filter(|c| !c.is_ascii_whitespace() && *c != b'=') {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return Err("invalid base64 in screenshot data".to_string()),
+        };
+        acc = (acc << 6) | v as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+fn blend(c: f64, a: f64) -> f64 {
+    255.0 + (c - 255.0) * a
+}
+
+fn rgb2y(r: f64, g: f64, b: f64) -> f64 {
+    r * 0.29889531 + g * 0.58662247 + b * 0.11448223
+}
+
+fn rgb2i(r: f64, g: f64, b: f64) -> f64 {
+    r * 0.59597799 - g * 0.27417610 - b * 0.32180189
+}
+
+fn rgb2q(r: f64, g: f64, b: f64) -> f64 {
+    r * 0.21147017 - g * 0.52261711 + b * 0.31114694
+}
+
+/// Pixel at byte offset `k` as RGB blended onto white
+fn rgb_at(img: &[u8], k: usize) -> (f64, f64, f64) {
+    let a = img[k + 3] as f64 / 255.0;
+    (blend(img[k] as f64, a), blend(img[k + 1] as f64, a), blend(img[k + 2] as f64, a))
+}
+
+/// Squared YIQ distance between two pixels, negative if the first is brighter.
+/// With `y_only`, just the difference in brightness.
+fn color_delta(img1: &[u8], img2: &[u8], k: usize, m: usize, y_only: bool) -> f64 {
+    if img1[k..k + 4] == img2[m..m + 4] {
+        return 0.0;
+    }
+    let (r1, g1, b1) = rgb_at(img1, k);
+    let (r2, g2, b2) = rgb_at(img2, m);
+    let (y1, y2) = (rgb2y(r1, g1, b1), rgb2y(r2, g2, b2));
+    let y = y1 - y2;
+    if y_only {
+        return y;
+    }
+    let i = rgb2i(r1, g1, b1) - rgb2i(r2, g2, b2);
+    let q = rgb2q(r1, g1, b1) - rgb2q(r2, g2, b2);
+    let delta = 0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q;
+    if y1 > y2 {
+        -delta
+    } else {
+        delta
+    }
+}
+
+/// The 3x3 neighbourhood of a pixel, clipped to the image, and whether the pixel is on an edge
+fn neighbours(x: usize, y: usize, width: usize, height: usize) -> (impl Iterator<Item = (usize, usize)>, bool) {
+    let (x0, y0) = (x.saturating_sub(1), y.saturating_sub(1));
+    let (x2, y2) = ((x + 1).min(width - 1), (y + 1).min(height - 1));
+    let on_edge = x == x0 || x == x2 || y == y0 || y == y2;
+    let around = (x0..=x2).flat_map(move |nx| (y0..=y2).map(move |ny| (nx, ny))).filter(move |&p| p != (x, y));
+    (around, on_edge)
+}
+
+/// Whether more than two neighbours of a pixel have exactly its color
+fn has_many_siblings(img: &[u8], x: usize, y: usize, width: usize, height: usize) -> bool {
+    let k = (y * width + x) * 4;
+    let (around, on_edge) = neighbours(x, y, width, height);
+    let mut zeroes = on_edge as u32;
+    for (nx, ny) in around {
+        let m = (ny * width + nx) * 4;
+        if img[k..k + 4] == img[m..m + 4] {
+            zeroes += 1;
+            if zeroes > 2 {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Whether a pixel looks like anti-aliasing: it sits between a darker and a
+/// brighter neighbour, one of which is part of a flat area in both images
+fn antialiased(img: &[u8], other: &[u8], x: usize, y: usize, width: usize, height: usize) -> bool {
+    let k = (y * width + x) * 4;
+    let (around, on_edge) = neighbours(x, y, width, height);
+    let mut zeroes = on_edge as u32;
+    let (mut min, mut max) = (0.0, 0.0);
+    let (mut min_at, mut max_at) = ((0, 0), (0, 0));
+    for (nx, ny) in around {
+        let delta = color_delta(img, img, k, (ny * width + nx) * 4, true);
+        if delta == 0.0 {
+            zeroes += 1;
+            if zeroes > 2 {
+                return false;
+            }
+        } else if delta < min {
+            min = delta;
+            min_at = (nx, ny);
+        } else if delta > max {
+            max = delta;
+            max_at = (nx, ny);
+        }
+    }
+    if min == 0.0 || max == 0.0 {
+        return false;
+    }
+    [min_at, max_at].iter().any(|&(px, py)| {
+        has_many_siblings(img, px, py, width, height) && has_many_siblings(other, px, py, width, height)
+    })
+}
+
+/// Compare two images of the same size. `threshold` (0 to 1) is how different
+/// two colors may be before their pixels count as mismatched.
+pub fn compare(actual: &Image, baseline: &Image, threshold: f64) -> Comparison {
+    let (width, height) = (actual.width as usize, actual.height as usize);
+    let (img1, img2) = (&actual.rgba, &baseline.rgba);
+    let max_delta = MAX_YIQ_DELTA * threshold * threshold;
+    let mut diff = vec![0; img1.len()];
+    let (mut mismatched, mut antialiased_count) = (0, 0);
+
+    for y in 0..height {
+        for x in 0..width {
+            let k = (y * width + x) * 4;
+            let delta = color_delta(img1, img2, k, k, false);
+            let color = if delta.abs() > max_delta {
+                if antialiased(img1, img2, x, y, width, height) || antialiased(img2, img1, x, y, width, height) {
+                    antialiased_count += 1;
+                    ANTIALIAS_COLOR
+                } else {
+                    mismatched += 1;
+                    MISMATCH_COLOR
+                }
+            } else {
+                let (r, g, b) = rgb_at(img1, k);
+                let a = img1[k + 3] as f64 / 255.0;
+                let gray = blend(rgb2y(r, g, b), FADED_ALPHA * a) as u8;
+                [gray, gray, gray]
+            };
+            diff[k..k + 3].copy_from_slice(&color);
+            diff[k + 3] = 255;
+        }
+    }
+
+    Comparison {
+        mismatched,
+        antialiased: antialiased_count,
+        diff: Image { width: actual.width, height: actual.height, rgba: diff },
+    }
+}
+
+/// Where the diff image goes without `--diff-out`: next to the baseline
+fn default_diff_path(baseline: &str) -> String {
+    let path = Path::new(baseline);
+    path.with_file_name(format!(
+        "{}.diff.png",
+        path.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default()
+    ))
+    .to_string_lossy()
+    .to_string()
+}
+
+fn write_file(path: &str, bytes: &[u8]) -> Result<(), String> {
+    if let Some(parent) = Path::new(path).parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(|e| format!("Could not create {}: {}", parent.display(), e))?;
+    }
+    fs::write(path, bytes).map_err(|e| format!("Could not write {}: {}", path, e))
+}
+
+/// A failed `screenshot --compare`, with the numbers for the --json envelope
+pub struct CompareFailure {
+    pub message: String,
+    pub details: Value,
+}
+
+impl From<String> for CompareFailure {
+    fn from(message: String) -> Self {
+        CompareFailure { message, details: Value::Null }
+    }
+}
+
+/// After a successful `screenshot --compare`: check the screenshot against the
+/// baseline (or replace the baseline with `--update`) and put the outcome in
+/// `data.compare`
+pub fn finish_compare(cmd: &Value, resp: &mut Response) -> Result<(), CompareFailure> {
+    let Some(data) = resp.data.as_mut() else {
+        return Ok(());
+    };
+    let baseline_path = cmd["compare"].as_str().unwrap_or_default();
+    // Without a path the screenshot only exists in the response; it is not printed
+    let bytes = match data["path"].as_str() {
+        Some(path) => fs::read(path).map_err(|e| format!("Could not read screenshot {}: {}", path, e))?,
+        None => {
+            let base64 = data.as_object_mut().and_then(|d| d.remove("base64"));
+            decode_base64(base64.as_ref().and_then(|b| b.as_str()).unwrap_or_default())?
+        }
+    };
+
+    if cmd["update"] == true {
+        write_file(baseline_path, &bytes)?;
+        data["compare"] = json!({ "baseline": baseline_path, "updated": true });
+        return Ok(());
+    }
+
+    let baseline_bytes = fs::read(baseline_path).map_err(|_| {
+        format!("Baseline {} does not exist. Run with --update to create it", baseline_path)
+    })?;
+    let actual = decode_png(&bytes).map_err(|e| format!("Could not decode screenshot: {}", e))?;
+    let baseline = decode_png(&baseline_bytes).map_err(|e| format!("Could not decode {}: {}", baseline_path, e))?;
+    if (actual.width, actual.height) != (baseline.width, baseline.height) {
+        return Err(CompareFailure {
+            message: format!(
+                "Screenshot is {}x{} but {} is {}x{}",
+                actual.width, actual.height, baseline_path, baseline.width, baseline.height
+            ),
+            details: json!({
+                "baseline": baseline_path,
+                "size": [actual.width, actual.height],
+                "baselineSize": [baseline.width, baseline.height],
+            }),
+        });
+    }
+
+    let threshold = cmd["threshold"].as_f64().unwrap_or(DEFAULT_THRESHOLD);
+    let result = compare(&actual, &baseline, threshold);
+    let total = actual.width as u64 * actual.height as u64;
+    let percent = result.mismatched as f64 * 100.0 / total.max(1) as f64;
+    let mut details = json!({
+        "baseline": baseline_path,
+        "threshold": threshold,
+        "mismatchedPixels": result.mismatched,
+        "antialiasedPixels": result.antialiased,
+        "totalPixels": total,
+        "mismatchPercent": (percent * 100.0).round() / 100.0,
+    });
+
+    if result.mismatched == 0 {
+        data["compare"] = details;
+        return Ok(());
+    }
+    let diff_path = cmd["diffOut"].as_str().map(String::from).unwrap_or_else(|| default_diff_path(baseline_path));
+    write_file(&diff_path, &encode_png(&result.diff)?)?;
+    details["diffPath"] = json!(diff_path);
+    Err(CompareFailure {
+        message: format!(
+            "Screenshot differs from {}: {:.2}% of pixels ({} of {}); diff written to {}",
+            baseline_path, percent, result.mismatched, total, diff_path
+        ),
+        details,
+    })
+}
+
+/// Report a failed comparison like a failed assertion and exit
+pub fn fail_compare(failure: CompareFailure, json_mode: bool) -> ! {
+    let kind = ErrorKind::AssertionFailed;
+    if json_mode {
+        let mut envelope = error_envelope(kind, &failure.message, Some("screenshot"));
+        if !failure.details.is_null() {
+            envelope["compare"] = failure.details;
+        }
+        println!("{}", envelope);
+    } else {
+        print_error(kind, &failure.message, Some("screenshot"), false);
+    }
+    std::process::exit(kind.exit_code());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, rgba: [u8; 4]) -> Image {
+        Image { width, height, rgba: rgba.repeat((width * height) as usize) }
+    }
+
+    fn set(img: &mut Image, x: u32, y: u32, rgba: [u8; 4]) {
+        let k = ((y * img.width + x) * 4) as usize;
+        img.rgba[k..k + 4].copy_from_slice(&rgba);
+    }
+
+    #[test]
+    fn test_identical_images_match() {
+        let img = solid(8, 8, [200, 100, 50, 255]);
+        let result = compare(&img, &solid(8, 8, [200, 100, 50, 255]), DEFAULT_THRESHOLD);
+        assert_eq!(result.mismatched, 0);
+        assert_eq!(result.antialiased, 0);
+    }
+
+    #[test]
+    fn test_counts_changed_block() {
+        let baseline = solid(10, 10, [255, 255, 255, 255]);
+        let mut actual = solid(10, 10, [255, 255, 255, 255]);
+        for x in 2..6 {
+            for y in 2..5 {
+                set(&mut actual, x, y, [0, 0, 0, 255]);
+            }
+        }
+        let result = compare(&actual, &baseline, DEFAULT_THRESHOLD);
+        assert_eq!(result.mismatched, 12);
+        let k = ((3 * 10 + 3) * 4) as usize;
+        assert_eq!(result.diff.rgba[k..k + 4], [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_threshold_ignores_small_color_changes() {
+        let baseline = solid(4, 4, [100, 100, 100, 255]);
+        let actual = solid(4, 4, [104, 100, 100, 255]);
+        assert_eq!(compare(&actual, &baseline, DEFAULT_THRESHOLD).mismatched, 0);
+        assert_eq!(compare(&actual, &baseline, 0.0).mismatched, 16);
+    }
+
+    #[test]
+    fn test_ignores_antialiased_edge() {
+        // A black block on white whose edge column turns gray, as when text is
+        // rendered with different anti-aliasing
+        let mut baseline = solid(10, 10, [255, 255, 255, 255]);
+        for x in 0..4 {
+            for y in 0..10 {
+                set(&mut baseline, x, y, [0, 0, 0, 255]);
+            }
+        }
+        let mut actual = solid(10, 10, [255, 255, 255, 255]);
+        actual.rgba.copy_from_slice(&baseline.rgba);
+        for y in 0..10 {
+            set(&mut actual, 4, y, [128, 128, 128, 255]);
+        }
+        let result = compare(&actual, &baseline, DEFAULT_THRESHOLD);
+        assert_eq!(result.mismatched, 0);
+        assert_eq!(result.antialiased, 10);
+    }
+
+    #[test]
+    fn test_png_round_trip() {
+        let mut img = solid(3, 2, [10, 20, 30, 255]);
+        set(&mut img, 1, 1, [1, 2, 3, 4]);
+        let decoded = decode_png(&encode_png(&img).unwrap()).unwrap();
+        assert_eq!((decoded.width, decoded.height), (3, 2));
+        assert_eq!(decoded.rgba, img.rgba);
+    }
+
+    #[test]
+    fn test_decode_base64() {
+        assert_eq!(decode_base64("aGVsbG8=").unwrap(), b"hello");
+        assert_eq!(decode_base64("aGk=\n").unwrap(), b"hi");
+        assert!(decode_base64("a$b").is_err());
+    }
+
+    #[test]
+    fn test_default_diff_path() {
+        assert_eq!(default_diff_path("baselines/home.png"), "baselines/home.diff.png");
+        assert_eq!(default_diff_path("home.png"), "home.diff.png");
+    }
+}
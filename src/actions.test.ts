@@ -1,5 +1,5 @@
 import { describe, it, expect } from 'vitest';
-import { toAIFriendlyError } from './actions.js';
+import { toAIFriendlyError, isRetryableError } from './actions.js';
 
 describe('toAIFriendlyError', () => {
   describe('element blocked by overlay', () => {
@@ -37,3 +37,17 @@ describe('toAIFriendlyError', () => {
     });
   });
 });
+
+describe('isRetryableError', () => {
+  it('should treat covered and re-rendering elements as retryable', () => {
+    expect(isRetryableError('<div class="overlay"> intercepts pointer events')).toBe(true);
+    expect(isRetryableError('Element is not attached to the DOM')).toBe(true);
+  });
+
+  it('should not retry errors that will fail the same way again', () => {
+    expect(isRetryableError('locator.click: Timeout 10000ms exceeded.')).toBe(false);
+    expect(isRetryableError('Element "#save" is not visible.')).toBe(false);
+    expect(isRetryableError('Selector "button" matched 3 elements.')).toBe(false);
+    expect(isRetryableError('Unknown action: frobnicate')).toBe(false);
+  });
+});
@@ -1,4 +1,7 @@
-import type { Page, Frame } from 'playwright-core';
+import * as fs from 'fs';
+import * as path from 'path';
+import { fileURLToPath } from 'url';
+import type { Page, Frame, Download } from 'playwright-core';
 import type { BrowserManager, ScreencastFrame } from './browser.js';
 import type {
   Command,
@@ -108,6 +111,12 @@ import type {
   ScreenshotData,
   EvaluateData,
   ContentData,
+  VersionData,
+  StatusData,
+  RefsData,
+  RefOrigin,
+  LocatorCommand,
+  LocatorData,
   TabListData,
   TabNewData,
   TabSwitchData,
@@ -119,8 +128,11 @@ import type {
   RecordingRestartData,
   InputEventData,
   StylesData,
+  SnapshotPage,
+  ScreenshotAnnotation,
 } from './types.js';
 import { successResponse, errorResponse } from './protocol.js';
+import { INTERACTIVE_ROLES, parseRef } from './snapshot.js';
 
 // Callback for screencast frames - will be set by the daemon when streaming is active
 let screencastFrameCallback: ((frame: ScreencastFrame) => void) | null = null;
@@ -139,6 +151,8 @@ export function setScreencastFrameCallback(
 interface SnapshotData {
   snapshot: string;
   refs?: Record<string, { role: string; name?: string }>;
+  origin: RefOrigin | null;
+  page?: SnapshotPage;
 }
 
 /**
@@ -192,10 +206,60 @@ export function toAIFriendlyError(error: unknown, selector: string): Error {
   return error instanceof Error ? error : new Error(message);
 }
 
+// Failures that usually clear up on their own: an element briefly covered,
+// re-rendered or still animating, or a page that is mid-navigation. Plain
+// timeouts and hidden elements are left out, as they are as often real failures.
+const RETRYABLE_ERROR_PATTERNS = [
+  'intercepts pointer events',
+  'is blocked by another element',
+  'not stable',
+  'not attached to the DOM',
+  'Element is detached',
+  'Execution context was destroyed',
+];
+
+/**
+ * Whether a failed command may succeed if sent again (used by the CLI's --retry)
+ */
+export function isRetryableError(message: string): boolean {
+  return RETRYABLE_ERROR_PATTERNS.some((pattern) => message.includes(pattern));
+}
+
+/**
+ * Wait for `work`, ending it early if the command is cancelled. Playwright waits
+ * take no abort signal, so `stop` has to make `work` settle on its own; without
+ * it the wait runs on until its timeout. Either way this only settles once
+ * `work` has, so the caller knows when the operation is really over.
+ */
+async function abortable<T>(
+  work: Promise<T>,
+  signal: AbortSignal | undefined,
+  stop?: () => void
+): Promise<T> {
+  if (!signal) return work;
+  const onAbort = () => stop?.();
+  signal.addEventListener('abort', onAbort, { once: true });
+  try {
+    const result = await work;
+    if (signal.aborted) throw new Error('Cancelled by client');
+    return result;
+  } catch (error) {
+    if (signal.aborted) throw new Error('Cancelled by client');
+    throw error;
+  } finally {
+    signal.removeEventListener('abort', onAbort);
+  }
+}
+
 /**
- * Execute a command and return a response
+ * Execute a command and return a response.
+ * `signal` aborts the wait commands when the client cancels them.
  */
-export async function executeCommand(command: Command, browser: BrowserManager): Promise<Response> {
+export async function executeCommand(
+  command: Command,
+  browser: BrowserManager,
+  signal?: AbortSignal
+): Promise<Response> {
   try {
     switch (command.action) {
       case 'launch':
@@ -241,7 +305,7 @@ export async function executeCommand(command: Command, browser: BrowserManager):
       case 'evaluate':
         return await handleEvaluate(command, browser);
       case 'wait':
-        return await handleWait(command, browser);
+        return await handleWait(command, browser, signal);
       case 'scroll':
         return await handleScroll(command, browser);
       case 'select':
@@ -252,6 +316,14 @@ export async function executeCommand(command: Command, browser: BrowserManager):
         return await handleContent(command, browser);
       case 'close':
         return await handleClose(command, browser);
+      case 'version':
+        return handleVersion(command, browser);
+      case 'status':
+        return handleStatus(command, browser);
+      case 'refs':
+        return handleRefs(command, browser);
+      case 'locator':
+        return await handleLocator(command, browser);
       case 'tab_new':
         return await handleTabNew(command, browser);
       case 'tab_list':
@@ -391,9 +463,9 @@ export async function executeCommand(command: Command, browser: BrowserManager):
       case 'nth':
         return await handleNth(command, browser);
       case 'waitforurl':
-        return await handleWaitForUrl(command, browser);
+        return await handleWaitForUrl(command, browser, signal);
       case 'waitforloadstate':
-        return await handleWaitForLoadState(command, browser);
+        return await handleWaitForLoadState(command, browser, signal);
       case 'setcontent':
         return await handleSetContent(command, browser);
       case 'timezone':
@@ -411,7 +483,7 @@ export async function executeCommand(command: Command, browser: BrowserManager):
       case 'bringtofront':
         return await handleBringToFront(command, browser);
       case 'waitforfunction':
-        return await handleWaitForFunction(command, browser);
+        return await handleWaitForFunction(command, browser, signal);
       case 'scrollintoview':
         return await handleScrollIntoView(command, browser);
       case 'addinitscript':
@@ -425,7 +497,7 @@ export async function executeCommand(command: Command, browser: BrowserManager):
       case 'multiselect':
         return await handleMultiSelect(command, browser);
       case 'waitfordownload':
-        return await handleWaitForDownload(command, browser);
+        return await handleWaitForDownload(command, browser, signal);
       case 'responsebody':
         return await handleResponseBody(command, browser);
       case 'screencast_start':
@@ -452,7 +524,7 @@ export async function executeCommand(command: Command, browser: BrowserManager):
     }
   } catch (error) {
     const message = error instanceof Error ? error.message : String(error);
-    return errorResponse(command.id, message);
+    return errorResponse(command.id, message, isRetryableError(message));
   }
 }
 
@@ -552,22 +624,99 @@ async function handleScreenshot(
     target = browser.getLocator(command.selector);
   }
 
+  const annotations = command.annotate ? await drawAnnotations(browser) : undefined;
   try {
     if (command.path) {
       await target.screenshot({ ...options, path: command.path });
-      return successResponse(command.id, { path: command.path });
+      return successResponse(command.id, { path: command.path, annotations });
     } else {
       const buffer = await target.screenshot(options);
-      return successResponse(command.id, { base64: buffer.toString('base64') });
+      return successResponse(command.id, { base64: buffer.toString('base64'), annotations });
     }
   } catch (error) {
     if (command.selector) {
       throw toAIFriendlyError(error, command.selector);
     }
     throw error;
+  } finally {
+    if (annotations) {
+      await page.evaluate((attr) => document.querySelector(`[${attr}]`)?.remove(), ANNOTATION_ATTR);
+    }
   }
 }
 
+/** Attribute on the overlay drawn by `screenshot --annotate` */
+const ANNOTATION_ATTR = 'data-agent-browser-annotations';
+
+/**
+ * Overlay a numbered box on each interactive ref from the last snapshot (taking
+ * an interactive snapshot first if there is none, or if the page has navigated
+ * since). Refs that are hidden or no longer on the page are left out.
+ */
+async function drawAnnotations(browser: BrowserManager): Promise<ScreenshotAnnotation[]> {
+  const origin = browser.getRefOrigin();
+  if (
+    Object.keys(browser.getRefMap()).length === 0 ||
+    origin?.navigation !== browser.getNavigation()
+  ) {
+    await browser.getSnapshot({ interactive: true });
+  }
+
+  const candidates = Object.entries(browser.getRefMap()).filter(([, data]) =>
+    INTERACTIVE_ROLES.has(data.role)
+  );
+  const boxes = await Promise.all(
+    candidates.map(([ref]) =>
+      browser
+        .getLocatorFromRef(ref)
+        ?.boundingBox({ timeout: 500 })
+        .catch(() => null)
+    )
+  );
+  const annotations: ScreenshotAnnotation[] = [];
+  candidates.forEach(([ref, data], i) => {
+    const box = boxes[i];
+    if (!box || box.width === 0 || box.height === 0) return;
+    annotations.push({
+      label: Number(ref.slice(1)),
+      ref,
+      role: data.role,
+      name: data.name,
+      box: {
+        x: Math.round(box.x),
+        y: Math.round(box.y),
+        width: Math.round(box.width),
+        height: Math.round(box.height),
+      },
+    });
+  });
+
+  await browser.getPage().evaluate(
+    ({ attr, annotations }) => {
+      const layer = document.createElement('div');
+      layer.setAttribute(attr, '');
+      layer.style.cssText =
+        'position:absolute;left:0;top:0;width:0;height:0;z-index:2147483647;pointer-events:none;';
+      for (const { label, box } of annotations) {
+        const frame = document.createElement('div');
+        frame.style.cssText =
+          `position:absolute;left:${box.x + window.scrollX}px;top:${box.y + window.scrollY}px;` +
+          `width:${box.width}px;height:${box.height}px;border:2px solid #e6007a;box-sizing:border-box;`;
+        const tag = document.createElement('span');
+        tag.textContent = String(label);
+        tag.style.cssText =
+          'position:absolute;left:0;top:0;padding:0 3px;background:#e6007a;color:#fff;' +
+          'font:bold 11px/14px monospace;';
+        frame.appendChild(tag);
+        layer.appendChild(frame);
+      }
+      document.documentElement.appendChild(layer);
+    },
+    { attr: ANNOTATION_ATTR, annotations }
+  );
+  return annotations;
+}
+
 async function handleSnapshot(
   command: Command & {
     action: 'snapshot';
@@ -575,15 +724,20 @@ async function handleSnapshot(
     maxDepth?: number;
     compact?: boolean;
     selector?: string;
+    visible?: boolean;
+    page?: number;
   },
   browser: BrowserManager
 ): Promise<Response<SnapshotData>> {
+  const page = command.page !== undefined ? await scrollToPage(browser, command.page) : undefined;
+
   // Use enhanced snapshot with refs and optional filtering
   const { tree, refs } = await browser.getSnapshot({
     interactive: command.interactive,
     maxDepth: command.maxDepth,
     compact: command.compact,
     selector: command.selector,
+    visible: command.visible || page !== undefined,
   });
 
   // Simplify refs for output (just role and name)
@@ -595,9 +749,36 @@ async function handleSnapshot(
   return successResponse(command.id, {
     snapshot: tree || 'Empty page',
     refs: Object.keys(simpleRefs).length > 0 ? simpleRefs : undefined,
+    origin: browser.getRefOrigin(),
+    page,
   });
 }
 
+/**
+ * Scroll the window to viewport-sized page `n` (1-based) for `snapshot --page`
+ */
+async function scrollToPage(browser: BrowserManager, n: number): Promise<SnapshotPage> {
+  const page = browser.getPage();
+  const pages = await page.evaluate(() =>
+    Math.max(1, Math.ceil(document.documentElement.scrollHeight / window.innerHeight))
+  );
+  if (n > pages) {
+    throw new Error(`Page ${n} is past the end of the document (${pages} pages)`);
+  }
+  return page.evaluate(async (n) => {
+    window.scrollTo(0, (n - 1) * window.innerHeight);
+    // Let scroll handlers (lazy loading, infinite feeds) run before the snapshot
+    await new Promise((resolve) => requestAnimationFrame(() => requestAnimationFrame(resolve)));
+    const height = document.documentElement.scrollHeight;
+    return {
+      page: n,
+      pages: Math.max(1, Math.ceil(height / window.innerHeight)),
+      scrollY: Math.round(window.scrollY),
+      atEnd: window.scrollY + window.innerHeight >= height - 1,
+    };
+  }, n);
+}
+
 async function handleEvaluate(
   command: EvaluateCommand,
   browser: BrowserManager
@@ -610,19 +791,35 @@ async function handleEvaluate(
   return successResponse(command.id, { result });
 }
 
-async function handleWait(command: WaitCommand, browser: BrowserManager): Promise<Response> {
+async function handleWait(
+  command: WaitCommand,
+  browser: BrowserManager,
+  signal?: AbortSignal
+): Promise<Response> {
   const page = browser.getPage();
 
   if (command.selector) {
-    await page.waitForSelector(command.selector, {
-      state: command.state ?? 'visible',
-      timeout: command.timeout,
-    });
+    await abortable(
+      page.waitForSelector(command.selector, {
+        state: command.state ?? 'visible',
+        timeout: command.timeout,
+      }),
+      signal
+    );
   } else if (command.timeout) {
-    await page.waitForTimeout(command.timeout);
+    let timer: ReturnType<typeof setTimeout> | undefined;
+    let wake = () => {};
+    const sleep = new Promise<void>((resolve) => {
+      timer = setTimeout(resolve, command.timeout);
+      wake = resolve;
+    });
+    await abortable(sleep, signal, () => {
+      clearTimeout(timer);
+      wake();
+    });
   } else {
     // Default: wait for load state
-    await page.waitForLoadState('load');
+    await abortable(page.waitForLoadState('load'), signal);
   }
 
   return successResponse(command.id, { waited: true });
@@ -720,6 +917,155 @@ async function handleClose(
   return successResponse(command.id, { closed: true });
 }
 
+/**
+ * Read the daemon's version from the package.json next to dist/
+ */
+function getDaemonVersion(): string {
+  try {
+    const here = path.dirname(fileURLToPath(import.meta.url));
+    const pkg = JSON.parse(fs.readFileSync(path.join(here, '..', 'package.json'), 'utf8'));
+    return typeof pkg.version === 'string' ? pkg.version : 'unknown';
+  } catch {
+    return 'unknown';
+  }
+}
+
+function handleVersion(
+  command: Command & { action: 'version' },
+  browser: BrowserManager
+): Response<VersionData> {
+  const browserVersion = browser.getBrowser()?.version();
+  return successResponse(command.id, {
+    version: getDaemonVersion(),
+    ...(browserVersion && { browserVersion }),
+  });
+}
+
+/**
+ * Report daemon and browser state. The launch options are the ones the
+ * daemon was started with (passed by the CLI through the environment).
+ */
+function handleStatus(
+  command: Command & { action: 'status' },
+  browser: BrowserManager
+): Response<StatusData> {
+  const env = process.env;
+  const launched = browser.isLaunched();
+  const pages = launched ? browser.getPages() : [];
+  const idleTimeoutMs = parseInt(env.AGENT_BROWSER_IDLE_TIMEOUT ?? '', 10);
+  return successResponse(command.id, {
+    pid: process.pid,
+    uptimeMs: Math.round(process.uptime() * 1000),
+    launched,
+    ...(pages.length > 0 && { url: browser.getPage().url() }),
+    tabCount: pages.length,
+    launchOptions: {
+      headed: env.AGENT_BROWSER_HEADED === '1',
+      ...(env.AGENT_BROWSER_EXECUTABLE_PATH && {
+        executablePath: env.AGENT_BROWSER_EXECUTABLE_PATH,
+      }),
+      ...(env.AGENT_BROWSER_EXTENSIONS && {
+        extensions: env.AGENT_BROWSER_EXTENSIONS.split(',').filter(Boolean),
+      }),
+      ...(browser.getProfile() && { profile: browser.getProfile()! }),
+      ...(env.AGENT_BROWSER_ARGS && { args: env.AGENT_BROWSER_ARGS }),
+      ...(env.AGENT_BROWSER_USER_AGENT && { userAgent: env.AGENT_BROWSER_USER_AGENT }),
+      ...(env.AGENT_BROWSER_PROXY && { proxy: env.AGENT_BROWSER_PROXY }),
+      ...(env.AGENT_BROWSER_PROXY_BYPASS && { proxyBypass: env.AGENT_BROWSER_PROXY_BYPASS }),
+      ...(idleTimeoutMs > 0 && { idleTimeoutMs }),
+    },
+  });
+}
+
+/**
+ * Refs from the last snapshot with the role/name/nth they resolve to, and the
+ * page they were taken on. Unlike `snapshot`, this does not renumber them.
+ */
+function handleRefs(
+  command: Command & { action: 'refs' },
+  browser: BrowserManager
+): Response<RefsData> {
+  const launched = browser.getPages().length > 0;
+  return successResponse(command.id, {
+    refs: browser.getRefMap(),
+    origin: browser.getRefOrigin(),
+    navigation: launched ? browser.getNavigation() : null,
+    url: launched ? browser.getPage().url() : null,
+  });
+}
+
+/**
+ * Ways to find an element again later: the role/name locator its ref resolves
+ * with, plus a CSS and an XPath path built from the DOM (anchored at the
+ * nearest unique id or data-testid)
+ */
+async function handleLocator(
+  command: LocatorCommand,
+  browser: BrowserManager
+): Promise<Response<LocatorData>> {
+  const ref = parseRef(command.selector);
+  const refData = ref ? browser.getRefMap()[ref] : undefined;
+  if (ref && !refData) {
+    throw new Error(`Unknown ref @${ref}. Run 'snapshot' to see current page elements.`);
+  }
+
+  const { css, xpath } = await browser.getLocator(command.selector).evaluate((el) => {
+    const isUnique = (selector: string) => {
+      try {
+        return document.querySelectorAll(selector).length === 1;
+      } catch {
+        return false;
+      }
+    };
+    const sameTagSiblings = (e: Element) =>
+      e.parentElement
+        ? Array.from(e.parentElement.children).filter((c) => c.localName === e.localName)
+        : [e];
+
+    const cssSteps: string[] = [];
+    for (let e: Element | null = el; e; e = e.parentElement) {
+      if (e.id && isUnique(`#${CSS.escape(e.id)}`)) {
+        cssSteps.unshift(`#${CSS.escape(e.id)}`);
+        break;
+      }
+      const testId = e.getAttribute('data-testid');
+      if (testId && isUnique(`[data-testid="${CSS.escape(testId)}"]`)) {
+        cssSteps.unshift(`[data-testid="${CSS.escape(testId)}"]`);
+        break;
+      }
+      const siblings = sameTagSiblings(e);
+      cssSteps.unshift(
+        siblings.length > 1
+          ? `${e.localName}:nth-of-type(${siblings.indexOf(e) + 1})`
+          : e.localName
+      );
+    }
+
+    let xpath = '';
+    for (let e: Element | null = el; e; e = e.parentElement) {
+      if (e.id && !e.id.includes('"') && isUnique(`#${CSS.escape(e.id)}`)) {
+        xpath = `//*[@id="${e.id}"]${xpath}`;
+        break;
+      }
+      const siblings = sameTagSiblings(e);
+      const name =
+        e.namespaceURI === 'http://www.w3.org/1999/xhtml'
+          ? e.localName
+          : `*[local-name()="${e.localName}"]`;
+      const index = siblings.length > 1 ? `[${siblings.indexOf(e) + 1}]` : '';
+      xpath = `/${name}${index}${xpath}`;
+    }
+
+    return { css: cssSteps.join(' > '), xpath };
+  });
+
+  let locator: string | undefined;
+  if (refData) {
+    locator = refData.nth !== undefined ? `${refData.selector}.nth(${refData.nth})` : refData.selector;
+  }
+  return successResponse(command.id, { ref: ref ?? undefined, locator, css, xpath });
+}
+
 async function handleTabNew(
   command: TabNewCommand,
   browser: BrowserManager
@@ -1700,19 +2046,21 @@ async function handleNth(command: NthCommand, browser: BrowserManager): Promise<
 
 async function handleWaitForUrl(
   command: WaitForUrlCommand,
-  browser: BrowserManager
+  browser: BrowserManager,
+  signal?: AbortSignal
 ): Promise<Response> {
   const page = browser.getPage();
-  await page.waitForURL(command.url, { timeout: command.timeout });
+  await abortable(page.waitForURL(command.url, { timeout: command.timeout }), signal);
   return successResponse(command.id, { url: page.url() });
 }
 
 async function handleWaitForLoadState(
   command: WaitForLoadStateCommand,
-  browser: BrowserManager
+  browser: BrowserManager,
+  signal?: AbortSignal
 ): Promise<Response> {
   const page = browser.getPage();
-  await page.waitForLoadState(command.state, { timeout: command.timeout });
+  await abortable(page.waitForLoadState(command.state, { timeout: command.timeout }), signal);
   return successResponse(command.id, { state: command.state });
 }
 
@@ -1794,10 +2142,18 @@ async function handleBringToFront(
 
 async function handleWaitForFunction(
   command: WaitForFunctionCommand,
-  browser: BrowserManager
+  browser: BrowserManager,
+  signal?: AbortSignal
 ): Promise<Response> {
   const page = browser.getPage();
-  await page.waitForFunction(command.expression, { timeout: command.timeout });
+  // The polling runs in the page, so a cancel sets a flag that ends it there
+  const flag = JSON.stringify(`__agentBrowserCancel_${command.id}`);
+  const expression =
+    `(() => { if (window[${flag}]) return true; const v = (${command.expression}); ` +
+    `return typeof v === 'function' ? v() : v; })()`;
+  await abortable(page.waitForFunction(expression, { timeout: command.timeout }), signal, () => {
+    page.evaluate(`window[${flag}] = true`).catch(() => {});
+  });
   return successResponse(command.id, { waited: true });
 }
 
@@ -1851,10 +2207,40 @@ async function handleMultiSelect(
 
 async function handleWaitForDownload(
   command: WaitForDownloadCommand,
-  browser: BrowserManager
-): Promise<Response> {
-  const page = browser.getPage();
-  const download = await page.waitForEvent('download', { timeout: command.timeout });
+  browser: BrowserManager,
+  signal?: AbortSignal
+): Promise<Response> {
+  const page = browser.getPage();
+  // Listen directly rather than with waitForEvent, so a cancelled wait stops
+  // listening at once instead of taking the next download
+  let stop = () => {};
+  const next = new Promise<Download>((resolve, reject) => {
+    const timeout = command.timeout ?? 60000;
+    const done = () => {
+      clearTimeout(timer);
+      page.off('download', onDownload);
+      page.off('close', onClose);
+    };
+    const onDownload = (download: Download) => {
+      done();
+      resolve(download);
+    };
+    const onClose = () => {
+      done();
+      reject(new Error('Page closed while waiting for download'));
+    };
+    const timer = setTimeout(() => {
+      done();
+      reject(new Error(`Timeout ${timeout}ms exceeded while waiting for download`));
+    }, timeout);
+    stop = () => {
+      done();
+      reject(new Error('Cancelled by client'));
+    };
+    page.on('download', onDownload);
+    page.on('close', onClose);
+  });
+  const download = await abortable(next, signal, () => stop());
 
   let filePath: string;
   if (command.path) {
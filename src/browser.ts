@@ -16,8 +16,8 @@ import {
 } from 'playwright-core';
 import path from 'node:path';
 import os from 'node:os';
-import { existsSync, mkdirSync, rmSync } from 'node:fs';
-import type { LaunchCommand } from './types.js';
+import { existsSync, mkdirSync, readFileSync, rmSync } from 'node:fs';
+import type { LaunchCommand, RefOrigin } from './types.js';
 import { type RefMap, type EnhancedSnapshot, getEnhancedSnapshot, parseRef } from './snapshot.js';
 
 // Screencast frame data from CDP
@@ -85,6 +85,12 @@ export class BrowserManager {
   private pageErrors: PageError[] = [];
   private isRecordingHar: boolean = false;
   private refMap: RefMap = {};
+  private refOrigin: RefOrigin | null = null;
+  // Id of the document each page shows, bumped on every main-frame navigation
+  // (reloads and form posts included, #fragment changes not)
+  private navigations: WeakMap<Page, { id: number; url: string }> = new WeakMap();
+  private navigationSeq: number = 0;
+  private profile: string | null = null;
   private lastSnapshot: string = '';
   private scopedHeaderRoutes: Map<string, (route: Route) => Promise<void>> = new Map();
 
@@ -100,6 +106,8 @@ export class BrowserManager {
   private recordingPage: Page | null = null;
   private recordingOutputPath: string = '';
   private recordingTempDir: string = '';
+  private closing: boolean = false;
+  private unexpectedCloseHandler: ((reason: string) => void) | null = null;
 
   /**
    * Check if browser is launched
@@ -116,10 +124,12 @@ export class BrowserManager {
     maxDepth?: number;
     compact?: boolean;
     selector?: string;
+    visible?: boolean;
   }): Promise<EnhancedSnapshot> {
     const page = this.getPage();
     const snapshot = await getEnhancedSnapshot(page, options);
     this.refMap = snapshot.refs;
+    this.refOrigin = { url: page.url(), timestamp: Date.now(), navigation: this.getNavigation() };
     this.lastSnapshot = snapshot.tree;
     return snapshot;
   }
@@ -131,6 +141,34 @@ export class BrowserManager {
     return this.refMap;
   }
 
+  /**
+   * The --profile directory the browser was launched with, as given
+   */
+  getProfile(): string | null {
+    return this.profile;
+  }
+
+  /**
+   * Id of the document shown in the active page; changes whenever it navigates
+   * or another tab becomes active
+   */
+  getNavigation(): number {
+    const page = this.getPage();
+    let entry = this.navigations.get(page);
+    if (!entry) {
+      entry = { id: ++this.navigationSeq, url: page.url() };
+      this.navigations.set(page, entry);
+    }
+    return entry.id;
+  }
+
+  /**
+   * Page and time of the snapshot the cached ref map comes from
+   */
+  getRefOrigin(): RefOrigin | null {
+    return this.refOrigin;
+  }
+
   /**
    * Get a locator from a ref (e.g., "e1", "@e1", "ref=e1")
    * Returns null if ref doesn't exist or is invalid
@@ -600,6 +638,32 @@ export class BrowserManager {
     }
   }
 
+  /**
+   * Load a saved storage state into an existing context. Persistent contexts
+   * cannot take a storageState at launch, so cookies are added directly and
+   * localStorage is restored by an init script on each matching origin.
+   */
+  private async restoreStorageState(context: BrowserContext, statePath: string): Promise<void> {
+    const state = JSON.parse(readFileSync(statePath, 'utf-8')) as {
+      cookies?: Parameters<BrowserContext['addCookies']>[0];
+      origins?: { origin: string; localStorage: { name: string; value: string }[] }[];
+    };
+    if (state.cookies?.length) {
+      await context.addCookies(state.cookies);
+    }
+    if (state.origins?.length) {
+      await context.addInitScript((origins) => {
+        const entry = origins.find((o) => o.origin === window.location.origin);
+        if (!entry) return;
+        for (const { name, value } of entry.localStorage) {
+          if (window.localStorage.getItem(name) === null) {
+            window.localStorage.setItem(name, value);
+          }
+        }
+      }, state.origins);
+    }
+  }
+
   /**
    * Get all pages
    */
@@ -810,6 +874,19 @@ export class BrowserManager {
     }
   }
 
+  /**
+   * Register a callback for when a locally launched browser exits without close() being called
+   */
+  onUnexpectedClose(handler: (reason: string) => void): void {
+    this.unexpectedCloseHandler = handler;
+  }
+
+  private notifyUnexpectedClose(reason: string): void {
+    if (!this.closing) {
+      this.unexpectedCloseHandler?.(reason);
+    }
+  }
+
   /**
    * Launch the browser with the specified options
    * If already launched, this is a no-op (browser stays open)
@@ -887,10 +964,14 @@ export class BrowserManager {
         }
       );
       this.isPersistentContext = true;
+      if (options.storageState) {
+        await this.restoreStorageState(context, options.storageState);
+      }
     } else if (hasProfile) {
       // Profile uses persistent context for durable cookies/storage
       // Expand ~ to home directory since it won't be shell-expanded
       const profilePath = options.profile!.replace(/^~\//, os.homedir() + '/');
+      this.profile = options.profile!;
       context = await launcher.launchPersistentContext(profilePath, {
         headless: options.headless ?? true,
         executablePath: options.executablePath,
@@ -898,6 +979,9 @@ export class BrowserManager {
         extraHTTPHeaders: options.headers,
       });
       this.isPersistentContext = true;
+      if (options.storageState) {
+        await this.restoreStorageState(context, options.storageState);
+      }
     } else {
       // Regular ephemeral browser
       this.browser = await launcher.launch({
@@ -906,16 +990,21 @@ export class BrowserManager {
         args: options.args,
       });
       this.cdpEndpoint = null;
+      this.browser.on('disconnected', () => this.notifyUnexpectedClose('browser process exited'));
       context = await this.browser.newContext({
         viewport,
         extraHTTPHeaders: options.headers,
         userAgent: options.userAgent,
         ...(options.proxy && { proxy: options.proxy }),
+        ...(options.storageState && { storageState: options.storageState }),
       });
     }
 
     context.setDefaultTimeout(60000);
     this.contexts.push(context);
+    if (this.isPersistentContext) {
+      context.on('close', () => this.notifyUnexpectedClose('browser process exited'));
+    }
 
     const page = context.pages()[0] ?? (await context.newPage());
     this.pages.push(page);
@@ -1001,6 +1090,19 @@ export class BrowserManager {
    * Set up console, error, and close tracking for a page
    */
   private setupPageTracking(page: Page): void {
+    this.navigations.set(page, { id: ++this.navigationSeq, url: page.url() });
+    page.on('framenavigated', (frame) => {
+      if (frame !== page.mainFrame()) return;
+      const previous = this.navigations.get(page);
+      const url = frame.url();
+      const withoutHash = (u: string) => u.split('#')[0];
+      if (previous && previous.url !== url && withoutHash(previous.url) === withoutHash(url)) {
+        previous.url = url;
+        return;
+      }
+      this.navigations.set(page, { id: ++this.navigationSeq, url });
+    });
+
     page.on('console', (msg) => {
       this.consoleMessages.push({
         type: msg.type(),
@@ -1573,6 +1675,8 @@ export class BrowserManager {
    * Close the browser and clean up
    */
   async close(): Promise<void> {
+    this.closing = true;
+
     // Stop recording if active (saves video)
     if (this.recordingContext) {
       await this.stopRecording();
@@ -1633,7 +1737,10 @@ export class BrowserManager {
     this.isPersistentContext = false;
     this.activePageIndex = 0;
     this.refMap = {};
+    this.refOrigin = null;
+    this.profile = null;
     this.lastSnapshot = '';
     this.frameCallback = null;
+    this.closing = false;
   }
 }
@@ -3,9 +3,10 @@ import * as fs from 'fs';
 import * as path from 'path';
 import * as os from 'os';
 import { BrowserManager } from './browser.js';
-import { parseCommand, serializeResponse, errorResponse } from './protocol.js';
+import { parseCommand, serializeResponse, errorResponse, successResponse } from './protocol.js';
 import { executeCommand } from './actions.js';
 import { StreamServer } from './stream-server.js';
+import type { Response } from './types.js';
 
 // Platform detection
 const isWindows = process.platform === 'win32';
@@ -19,6 +20,10 @@ let streamServer: StreamServer | null = null;
 // Default stream port (can be overridden with AGENT_BROWSER_STREAM_PORT)
 const DEFAULT_STREAM_PORT = 9223;
 
+// Actions that only read the daemon's own state (session list/info, the version
+// handshake, ref lookups, Ctrl-C). They neither reset nor hold off the idle timeout.
+const IDLE_NEUTRAL_ACTIONS = new Set(['status', 'version', 'refs', 'cancel']);
+
 /**
  * Set the current session
  */
@@ -138,9 +143,11 @@ export function getConnectionInfo(
 export function cleanupSocket(session?: string): void {
   const pidFile = getPidFile(session);
   const streamPortFile = getStreamPortFile(session);
+  const idleFile = getIdleFile(session);
   try {
     if (fs.existsSync(pidFile)) fs.unlinkSync(pidFile);
     if (fs.existsSync(streamPortFile)) fs.unlinkSync(streamPortFile);
+    if (fs.existsSync(idleFile)) fs.unlinkSync(idleFile);
     if (isWindows) {
       const portFile = getPortFile(session);
       if (fs.existsSync(portFile)) fs.unlinkSync(portFile);
@@ -161,6 +168,40 @@ export function getStreamPortFile(session?: string): string {
   return path.join(getSocketDir(), `${sess}.stream`);
 }
 
+/**
+ * Get the idle file path. It holds the idle timeout in ms and its mtime is the
+ * time of the last completed command, so clients can compute the time left.
+ */
+export function getIdleFile(session?: string): string {
+  const sess = session ?? currentSession;
+  return path.join(getSocketDir(), `${sess}.idle`);
+}
+
+/**
+ * Get the crash marker path. The daemon writes the reason here before exiting
+ * on a crash; cleanupSocket leaves it so the next CLI call can report it.
+ */
+export function getCrashFile(session?: string): string {
+  const sess = session ?? currentSession;
+  return path.join(getSocketDir(), `${sess}.crashed`);
+}
+
+function markCrashed(reason: string): void {
+  try {
+    fs.writeFileSync(getCrashFile(), reason);
+  } catch {
+    // Best effort; the stale pid file still reveals the crash
+  }
+}
+
+/**
+ * Parse AGENT_BROWSER_IDLE_TIMEOUT (milliseconds, set by the CLI). 0 disables the timeout.
+ */
+export function getIdleTimeoutMs(): number {
+  const value = parseInt(process.env.AGENT_BROWSER_IDLE_TIMEOUT ?? '', 10);
+  return Number.isFinite(value) && value > 0 ? value : 0;
+}
+
 /**
  * Start the daemon server
  * @param options.streamPort Port for WebSocket stream server (0 to disable)
@@ -178,6 +219,45 @@ export async function startDaemon(options?: { streamPort?: number }): Promise<vo
   const browser = new BrowserManager();
   let shuttingDown = false;
 
+  // The browser going away on its own is a crash: exit so the CLI can detect and recover it
+  browser.onUnexpectedClose((reason) => {
+    if (shuttingDown) return;
+    console.error('Browser closed unexpectedly:', reason);
+    markCrashed(reason);
+    cleanupSocket();
+    process.exit(1);
+  });
+
+  // Idle timeout: shut down after this long without commands (0 = never)
+  const idleTimeoutMs = getIdleTimeoutMs();
+  let idleTimer: ReturnType<typeof setTimeout> | null = null;
+  let inFlight = 0;
+
+  // Cancel callbacks for running commands, keyed by request id
+  const pendingCommands = new Map<string, () => void>();
+
+  const stopIdleTimer = () => {
+    if (idleTimer) {
+      clearTimeout(idleTimer);
+      idleTimer = null;
+    }
+  };
+
+  const startIdleTimer = () => {
+    if (idleTimeoutMs <= 0 || shuttingDown) return;
+    stopIdleTimer();
+    try {
+      fs.writeFileSync(getIdleFile(), idleTimeoutMs.toString());
+    } catch {
+      // Idle file is informational only
+    }
+    idleTimer = setTimeout(() => {
+      if (inFlight === 0) {
+        void shutdown();
+      }
+    }, idleTimeoutMs);
+  };
+
   // Start stream server if port is specified (or use default if env var is set)
   const streamPort =
     options?.streamPort ??
@@ -208,20 +288,43 @@ export async function startDaemon(options?: { streamPort?: number }): Promise<vo
 
         if (!line.trim()) continue;
 
-        try {
-          const parseResult = parseCommand(line);
+        const parseResult = parseCommand(line);
+
+        // Commands in progress keep the daemon alive. Queries about the daemon
+        // itself do not count as activity, or a tool polling `session list`
+        // would keep every session alive.
+        const isActivity =
+          parseResult.success && !IDLE_NEUTRAL_ACTIONS.has(parseResult.command.action);
+        if (isActivity) {
+          inFlight++;
+          stopIdleTimer();
+        }
 
+        try {
           if (!parseResult.success) {
             const resp = errorResponse(parseResult.id ?? 'unknown', parseResult.error);
             socket.write(serializeResponse(resp) + '\n');
             continue;
           }
 
+          // Cancel a command running on another connection (Ctrl-C in the CLI)
+          if (parseResult.command.action === 'cancel') {
+            const cancel = pendingCommands.get(parseResult.command.targetId);
+            cancel?.();
+            const resp = successResponse(parseResult.command.id, { cancelled: !!cancel });
+            socket.write(serializeResponse(resp) + '\n');
+            continue;
+          }
+
           // Auto-launch browser if not already launched and this isn't a launch command
+          // (the version handshake, status and refs queries must not start a browser either)
           if (
             !browser.isLaunched() &&
             parseResult.command.action !== 'launch' &&
-            parseResult.command.action !== 'close'
+            parseResult.command.action !== 'close' &&
+            parseResult.command.action !== 'version' &&
+            parseResult.command.action !== 'status' &&
+            parseResult.command.action !== 'refs'
           ) {
             const extensions = process.env.AGENT_BROWSER_EXTENSIONS
               ? process.env.AGENT_BROWSER_EXTENSIONS.split(',')
@@ -276,11 +379,38 @@ export async function startDaemon(options?: { streamPort?: number }): Promise<vo
             return;
           }
 
-          const response = await executeCommand(parseResult.command, browser);
+          // Answer a cancelled command right away and abort its wait. Waits that
+          // cannot be stopped run on until their own timeout, so the command
+          // counts as in flight until it has really ended.
+          const id = parseResult.command.id;
+          const controller = new AbortController();
+          const cancelled = new Promise<Response>((resolve) => {
+            pendingCommands.set(id, () => {
+              controller.abort();
+              resolve(errorResponse(id, 'Cancelled by client'));
+            });
+          });
+          const work = executeCommand(parseResult.command, browser, controller.signal);
+          inFlight++;
+          void work.finally(() => {
+            inFlight--;
+            if (inFlight === 0) startIdleTimer();
+          });
+          let response: Response;
+          try {
+            response = await Promise.race([work, cancelled]);
+          } finally {
+            pendingCommands.delete(id);
+          }
           socket.write(serializeResponse(response) + '\n');
         } catch (err) {
           const message = err instanceof Error ? err.message : String(err);
           socket.write(serializeResponse(errorResponse('error', message)) + '\n');
+        } finally {
+          if (isActivity) {
+            inFlight--;
+            if (inFlight === 0) startIdleTimer();
+          }
         }
       }
     });
@@ -311,16 +441,21 @@ export async function startDaemon(options?: { streamPort?: number }): Promise<vo
     });
   }
 
+  // Count idle time from startup so an unused daemon also exits
+  startIdleTimer();
+
   server.on('error', (err) => {
     console.error('Server error:', err);
+    markCrashed(`daemon server error: ${err.message}`);
     cleanupSocket();
     process.exit(1);
   });
 
   // Handle shutdown signals
-  const shutdown = async () => {
+  async function shutdown(): Promise<void> {
     if (shuttingDown) return;
     shuttingDown = true;
+    stopIdleTimer();
 
     // Stop stream server if running
     if (streamServer) {
@@ -339,7 +474,7 @@ export async function startDaemon(options?: { streamPort?: number }): Promise<vo
     server.close();
     cleanupSocket();
     process.exit(0);
-  };
+  }
 
   process.on('SIGINT', shutdown);
   process.on('SIGTERM', shutdown);
@@ -348,12 +483,14 @@ export async function startDaemon(options?: { streamPort?: number }): Promise<vo
   // Handle unexpected errors - always cleanup
   process.on('uncaughtException', (err) => {
     console.error('Uncaught exception:', err);
+    markCrashed(`daemon crashed: ${err.message}`);
     cleanupSocket();
     process.exit(1);
   });
 
   process.on('unhandledRejection', (reason) => {
     console.error('Unhandled rejection:', reason);
+    markCrashed(`daemon crashed: ${reason instanceof Error ? reason.message : String(reason)}`);
     cleanupSocket();
     process.exit(1);
   });
@@ -122,6 +122,11 @@ describe('parseCommand', () => {
       const result = parseCommand(cmd({ id: '1', action: 'screenshot', fullPage: true }));
       expect(result.success).toBe(true);
     });
+
+    it('should parse screenshot with annotate', () => {
+      const result = parseCommand(cmd({ id: '1', action: 'screenshot', annotate: true }));
+      expect(result.success).toBe(true);
+    });
   });
 
   describe('cookies', () => {
@@ -470,6 +475,32 @@ describe('parseCommand', () => {
         expect(result.command.selector).toBe('.content');
       }
     });
+
+    it('should parse snapshot with visible and page', () => {
+      const result = parseCommand(cmd({ id: '1', action: 'snapshot', visible: true, page: 2 }));
+      expect(result.success).toBe(true);
+      if (result.success) {
+        expect(result.command.visible).toBe(true);
+        expect(result.command.page).toBe(2);
+      }
+    });
+
+    it('should reject snapshot page below 1', () => {
+      const result = parseCommand(cmd({ id: '1', action: 'snapshot', page: 0 }));
+      expect(result.success).toBe(false);
+    });
+  });
+
+  describe('locator', () => {
+    it('should parse locator with a ref', () => {
+      const result = parseCommand(cmd({ id: '1', action: 'locator', selector: '@e12' }));
+      expect(result.success).toBe(true);
+    });
+
+    it('should reject locator without selector', () => {
+      const result = parseCommand(cmd({ id: '1', action: 'locator' }));
+      expect(result.success).toBe(false);
+    });
   });
 
   describe('launch', () => {
@@ -486,6 +517,17 @@ describe('parseCommand', () => {
       }
     });
 
+    it('should parse launch with storageState and profile', () => {
+      const result = parseCommand(
+        cmd({ id: '1', action: 'launch', storageState: '/tmp/state.json', profile: '~/.p' })
+      );
+      expect(result.success).toBe(true);
+      if (result.success && result.command.action === 'launch') {
+        expect(result.command.storageState).toBe('/tmp/state.json');
+        expect(result.command.profile).toBe('~/.p');
+      }
+    });
+
     it('should parse launch with cdpPort', () => {
       const result = parseCommand(cmd({ id: '1', action: 'launch', cdpPort: 9222 }));
       expect(result.success).toBe(true);
@@ -1029,6 +1071,41 @@ describe('parseCommand', () => {
     });
   });
 
+  describe('version', () => {
+    it('should parse version command', () => {
+      const result = parseCommand(cmd({ id: '1', action: 'version' }));
+      expect(result.success).toBe(true);
+      if (result.success) {
+        expect(result.command.action).toBe('version');
+      }
+    });
+  });
+
+  describe('status', () => {
+    it('should parse status command', () => {
+      const result = parseCommand(cmd({ id: '1', action: 'status' }));
+      expect(result.success).toBe(true);
+      if (result.success) {
+        expect(result.command.action).toBe('status');
+      }
+    });
+  });
+
+  describe('cancel', () => {
+    it('should parse cancel command', () => {
+      const result = parseCommand(cmd({ id: '2', action: 'cancel', targetId: '1' }));
+      expect(result.success).toBe(true);
+      if (result.success && result.command.action === 'cancel') {
+        expect(result.command.targetId).toBe('1');
+      }
+    });
+
+    it('should reject cancel without targetId', () => {
+      const result = parseCommand(cmd({ id: '2', action: 'cancel' }));
+      expect(result.success).toBe(false);
+    });
+  });
+
   describe('invalid commands', () => {
     it('should reject unknown action', () => {
       const result = parseCommand(cmd({ id: '1', action: 'unknown' }));
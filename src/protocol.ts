@@ -33,6 +33,8 @@ const launchSchema = baseCommandSchema.extend({
     .optional(),
   executablePath: z.string().optional(),
   extensions: z.array(z.string()).optional(),
+  profile: z.string().optional(),
+  storageState: z.string().optional(),
   headers: z.record(z.string()).optional(),
   proxy: z
     .object({
@@ -696,6 +698,7 @@ const screenshotSchema = baseCommandSchema.extend({
   selector: z.string().min(1).optional(),
   format: z.enum(['png', 'jpeg']).optional(),
   quality: z.number().min(0).max(100).optional(),
+  annotate: z.boolean().optional(),
 });
 
 const snapshotSchema = baseCommandSchema.extend({
@@ -704,6 +707,8 @@ const snapshotSchema = baseCommandSchema.extend({
   maxDepth: z.number().nonnegative().optional(),
   compact: z.boolean().optional(),
   selector: z.string().optional(),
+  visible: z.boolean().optional(),
+  page: z.number().int().positive().optional(),
 });
 
 const evaluateSchema = baseCommandSchema.extend({
@@ -748,6 +753,28 @@ const closeSchema = baseCommandSchema.extend({
   action: z.literal('close'),
 });
 
+const versionSchema = baseCommandSchema.extend({
+  action: z.literal('version'),
+});
+
+const statusSchema = baseCommandSchema.extend({
+  action: z.literal('status'),
+});
+
+const refsSchema = baseCommandSchema.extend({
+  action: z.literal('refs'),
+});
+
+const locatorSchema = baseCommandSchema.extend({
+  action: z.literal('locator'),
+  selector: z.string().min(1),
+});
+
+const cancelSchema = baseCommandSchema.extend({
+  action: z.literal('cancel'),
+  targetId: z.string().min(1),
+});
+
 // Tab/Window schemas
 const tabNewSchema = baseCommandSchema.extend({
   action: z.literal('tab_new'),
@@ -807,6 +834,11 @@ const commandSchema = z.discriminatedUnion('action', [
   hoverSchema,
   contentSchema,
   closeSchema,
+  versionSchema,
+  statusSchema,
+  refsSchema,
+  locatorSchema,
+  cancelSchema,
   tabNewSchema,
   tabListSchema,
   tabSwitchSchema,
@@ -949,8 +981,8 @@ export function successResponse<T>(id: string, data: T): Response<T> {
 /**
  * Create an error response
  */
-export function errorResponse(id: string, error: string): Response {
-  return { id, success: false, error };
+export function errorResponse(id: string, error: string, retryable = false): Response {
+  return { id, success: false, error, ...(retryable && { retryable: true }) };
 }
 
 /**
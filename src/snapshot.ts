@@ -14,6 +14,7 @@
  *   agent-browser snapshot              # Full snapshot
  *   agent-browser snapshot -i           # Interactive elements only
  *   agent-browser snapshot --depth 3    # Limit depth
+ *   agent-browser snapshot --visible    # Only what is in the viewport
  *   agent-browser click @e2             # Click element by ref
  */
 
@@ -43,6 +44,8 @@ export interface SnapshotOptions {
   compact?: boolean;
   /** CSS selector to scope the snapshot */
   selector?: string;
+  /** Only include elements that are (at least partly) in the viewport */
+  visible?: boolean;
 }
 
 // Counter for generating refs
@@ -65,7 +68,7 @@ function nextRef(): string {
 /**
  * Roles that are interactive and should get refs
  */
-const INTERACTIVE_ROLES = new Set([
+export const INTERACTIVE_ROLES = new Set([
   'button',
   'link',
   'textbox',
@@ -148,19 +151,163 @@ export async function getEnhancedSnapshot(
 
   // Get ARIA snapshot from Playwright
   const locator = options.selector ? page.locator(options.selector) : page.locator(':root');
-  const ariaTree = await locator.ariaSnapshot();
+  if (!options.visible) {
+    const ariaTree = await locator.ariaSnapshot();
+    if (!ariaTree) {
+      return {
+        tree: '(empty)',
+        refs: {},
+      };
+    }
+
+    // Parse and enhance the ARIA tree
+    const enhancedTree = processAriaTree(ariaTree, refs, options);
+
+    return { tree: enhancedTree, refs };
+  }
+
+  // Off-screen elements are hidden from the accessibility tree while the
+  // snapshot is taken, so the nth of each ref only counts visible elements
+  // until it is renumbered against the whole page
+  const inViewport = await hideOffscreen(page);
+  let enhancedTree: string;
+  try {
+    const ariaTree = inViewport ? await locator.ariaSnapshot() : '';
+    if (!ariaTree) {
+      return {
+        tree: '(nothing in the viewport)',
+        refs: {},
+      };
+    }
+    enhancedTree = processAriaTree(ariaTree, refs, options);
+    await markRefs(page, refs);
+  } finally {
+    await restoreOffscreen(page);
+  }
+  return { tree: await renumberRefs(page, enhancedTree, refs), refs };
+}
 
-  if (!ariaTree) {
-    return {
-      tree: '(empty)',
-      refs: {},
+/** Attribute on elements hidden by hideOffscreen */
+const OFFSCREEN_ATTR = 'data-agent-browser-offscreen';
+/** Attribute naming the ref an element got in a viewport snapshot */
+const REF_ATTR = 'data-agent-browser-ref';
+
+/**
+ * Set aria-hidden on the largest subtrees that have nothing in the viewport.
+ * Returns false if nothing on the page is in the viewport.
+ */
+async function hideOffscreen(page: Page): Promise<boolean> {
+  return page.evaluate((attr) => {
+    const inViewport = (el: Element): boolean => {
+      const rect = el.getBoundingClientRect();
+      return (
+        rect.width > 0 &&
+        rect.height > 0 &&
+        rect.bottom > 0 &&
+        rect.right > 0 &&
+        rect.top < window.innerHeight &&
+        rect.left < window.innerWidth
+      );
     };
+    // Returns whether the element or anything below it is in the viewport
+    const visit = (el: Element): boolean => {
+      const offscreen: Element[] = [];
+      let any = false;
+      for (const child of Array.from(el.children)) {
+        if (visit(child)) {
+          any = true;
+        } else {
+          offscreen.push(child);
+        }
+      }
+      if (!any && !inViewport(el)) {
+        return false;
+      }
+      for (const child of offscreen) {
+        if (!child.hasAttribute('aria-hidden')) {
+          child.setAttribute('aria-hidden', 'true');
+          child.setAttribute(attr, '');
+        }
+      }
+      return true;
+    };
+    return visit(document.documentElement);
+  }, OFFSCREEN_ATTR);
+}
+
+async function restoreOffscreen(page: Page): Promise<void> {
+  await page.evaluate((attr) => {
+    for (const el of Array.from(document.querySelectorAll(`[${attr}]`))) {
+      el.removeAttribute('aria-hidden');
+      el.removeAttribute(attr);
+    }
+  }, OFFSCREEN_ATTR);
+}
+
+/**
+ * Group refs by the role+name locator they resolve with, in nth order
+ */
+function refsByLocator(refs: RefMap): Map<string, string[]> {
+  const groups = new Map<string, string[]>();
+  for (const [ref, data] of Object.entries(refs)) {
+    const key = JSON.stringify([data.role, data.name]);
+    const group = groups.get(key) ?? [];
+    group[data.nth ?? 0] = ref;
+    groups.set(key, group);
   }
+  return groups;
+}
+
+function roleLocator(page: Page, key: string): Locator {
+  const [role, name] = JSON.parse(key) as [string, string | undefined];
+  return name !== undefined
+    ? page.getByRole(role as any, { name, exact: true })
+    : page.getByRole(role as any);
+}
 
-  // Parse and enhance the ARIA tree
-  const enhancedTree = processAriaTree(ariaTree, refs, options);
+/**
+ * While off-screen elements are hidden, tag each element with the ref it got
+ */
+async function markRefs(page: Page, refs: RefMap): Promise<void> {
+  for (const [key, group] of refsByLocator(refs)) {
+    await roleLocator(page, key).evaluateAll(
+      (els, { attr, group }) => {
+        els.forEach((el, i) => {
+          if (group[i]) el.setAttribute(attr, group[i]);
+        });
+      },
+      { attr: REF_ATTR, group: Array.from(group, (ref) => ref ?? '') }
+    );
+  }
+}
 
-  return { tree: enhancedTree, refs };
+/**
+ * Once the whole page is back, set the nth of each ref to its position among
+ * all matching elements, so the ref resolves to the same element later
+ */
+async function renumberRefs(page: Page, tree: string, refs: RefMap): Promise<string> {
+  for (const [key, group] of refsByLocator(refs)) {
+    const tagged = await roleLocator(page, key).evaluateAll((els, attr) => {
+      const found = els.map((el) => el.getAttribute(attr));
+      for (const el of els) el.removeAttribute(attr);
+      return found;
+    }, REF_ATTR);
+    for (const ref of group) {
+      if (!ref) continue;
+      const nth = tagged.indexOf(ref);
+      if (nth < 0) continue;
+      if (tagged.length > 1) {
+        refs[ref].nth = nth;
+      } else {
+        delete refs[ref].nth;
+      }
+      tree = tree.replace(
+        new RegExp(`\\[ref=${ref}\\]( \\[nth=\\d+\\])?`),
+        nth > 0 ? `[ref=${ref}] [nth=${nth}]` : `[ref=${ref}]`
+      );
+    }
+  }
+  return tree;
 }
 
 /**
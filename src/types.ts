@@ -18,6 +18,7 @@ export interface LaunchCommand extends BaseCommand {
   cdpUrl?: string;
   extensions?: string[];
   profile?: string; // Path to persistent browser profile directory
+  storageState?: string; // Path to a storage state file to restore
   proxy?: {
     server: string;
     bypass?: string;
@@ -732,6 +733,8 @@ export interface ScreenshotCommand extends BaseCommand {
   selector?: string;
   format?: 'png' | 'jpeg';
   quality?: number;
+  /** Draw a numbered box over each interactive ref before taking the screenshot */
+  annotate?: boolean;
 }
 
 export interface SnapshotCommand extends BaseCommand {
@@ -780,6 +783,33 @@ export interface CloseCommand extends BaseCommand {
   action: 'close';
 }
 
+// Version handshake (does not launch the browser)
+export interface VersionCommand extends BaseCommand {
+  action: 'version';
+}
+
+// Session status for `session info`/`session list` (does not launch the browser)
+export interface StatusCommand extends BaseCommand {
+  action: 'status';
+}
+
+// Refs from the last snapshot, for `export playwright` (does not take a new snapshot)
+export interface RefsCommand extends BaseCommand {
+  action: 'refs';
+}
+
+// Durable role/name, CSS and XPath locators for an element (`get locator @e12`)
+export interface LocatorCommand extends BaseCommand {
+  action: 'locator';
+  selector: string;
+}
+
+// Abandon a running command, sent by the CLI on Ctrl-C
+export interface CancelCommand extends BaseCommand {
+  action: 'cancel';
+  targetId: string;
+}
+
 // Tab/Window commands
 export interface TabNewCommand extends BaseCommand {
   action: 'tab_new';
@@ -834,6 +864,11 @@ export type Command =
   | HoverCommand
   | ContentCommand
   | CloseCommand
+  | VersionCommand
+  | StatusCommand
+  | RefsCommand
+  | LocatorCommand
+  | CancelCommand
   | TabNewCommand
   | TabListCommand
   | TabSwitchCommand
@@ -942,6 +977,8 @@ export interface ErrorResponse {
   id: string;
   success: false;
   error: string;
+  // Set when sending the same command again may succeed
+  retryable?: boolean;
 }
 
 export type Response<T = unknown> = SuccessResponse<T> | ErrorResponse;
@@ -952,13 +989,87 @@ export interface NavigateData {
   title: string;
 }
 
+export interface VersionData {
+  version: string;
+  browserVersion?: string;
+}
+
+export interface StatusData {
+  pid: number;
+  uptimeMs: number;
+  launched: boolean;
+  url?: string;
+  tabCount: number;
+  launchOptions: {
+    headed: boolean;
+    executablePath?: string;
+    extensions?: string[];
+    profile?: string;
+    args?: string;
+    userAgent?: string;
+    proxy?: string;
+    proxyBypass?: string;
+    idleTimeoutMs?: number;
+  };
+}
+
+/** Page URL, time (ms since epoch) and navigation id of the snapshot refs come from */
+export interface RefOrigin {
+  url: string;
+  timestamp: number;
+  navigation: number;
+}
+
+export interface RefsData {
+  refs: Record<string, { role: string; name?: string; nth?: number }>;
+  origin: RefOrigin | null;
+  /** Navigation id and URL of the active page now (null before launch) */
+  navigation: number | null;
+  url: string | null;
+}
+
+export interface LocatorData {
+  /** The ref, when the element was given as one */
+  ref?: string;
+  /** Playwright role/name locator the ref resolves with */
+  locator?: string;
+  css: string;
+  xpath: string;
+}
+
 export interface ScreenshotData {
   path?: string;
   base64?: string;
+  /** Set for `screenshot --annotate` */
+  annotations?: ScreenshotAnnotation[];
+}
+
+export interface ScreenshotAnnotation {
+  /** Number drawn on the box; the same as in the ref (12 for @e12) */
+  label: number;
+  ref: string;
+  role: string;
+  name?: string;
+  /** In viewport coordinates, like `boundingbox` */
+  box: { x: number; y: number; width: number; height: number };
 }
 
 export interface SnapshotData {
   snapshot: string;
+  /** Page and time the snapshot (and so its refs) was taken on */
+  origin?: { url: string; timestamp: number };
+  /** Set for `snapshot --page <n>` */
+  page?: SnapshotPage;
+}
+
+export interface SnapshotPage {
+  /** 1-based, in viewport heights from the top of the document */
+  page: number;
+  /** Pages at the current document height (grows on infinite feeds) */
+  pages: number;
+  scrollY: number;
+  /** Whether the bottom of the document is in view */
+  atEnd: boolean;
 }
 
 export interface EvaluateData {